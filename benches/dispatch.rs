@@ -0,0 +1,131 @@
+//! Looking up objects and dispatching requests to them.
+//!
+//! Run once as is and once with `--features dyn-dispatch` to compare the generated enum against trait objects.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use myway::{
+	client::{testing::Loopback, SendHalf},
+	object_impls::Registry,
+	object_map::Objects,
+	protocol::{AnyObject, EncodeArg, Id},
+};
+
+const DISPLAY: u32 = 1;
+const REGISTRY: u32 = 2;
+const COMPOSITOR: u32 = 3;
+const SURFACE: u32 = 4;
+
+/// Number of clients events are broadcast to.
+const CLIENTS: usize = 1000;
+
+/// Write a request with integer arguments to `object`.
+fn submit(send: &mut SendHalf<'_>, object: u32, opcode: u16, args: &[u32]) {
+	let len = args.iter().map(|arg| usize::from(arg.encoded_len())).sum();
+	let mut message = send.submit(Id::new(object).unwrap(), opcode, len, 0).unwrap();
+	for arg in args {
+		arg.encode(&mut message);
+	}
+	message.finish();
+}
+
+/// A client that has bound `wl_compositor` and created a surface.
+struct Client {
+	objects: Objects,
+	/// Requests from the client, encoded and read back
+	requests: Loopback,
+	/// Events to the client, which are never read
+	events: Loopback,
+}
+
+impl Client {
+	fn new() -> Self {
+		let mut client = Self { objects: Objects::new(), requests: Loopback::new(), events: Loopback::new() };
+		client.objects.insert(Id::new(DISPLAY).unwrap(), myway::object_impls::Display).unwrap();
+		client.dispatch(DISPLAY, 1, |send| submit(send, DISPLAY, 1, &[REGISTRY]));
+		client.dispatch(REGISTRY, 0, |send| {
+			// wl_registry.bind takes an untyped new_id, which is sent with its interface and version
+			let args: (u32, &str, u32, u32) = (1, "wl_compositor", 4, COMPOSITOR);
+			let len = args.0.encoded_len() + args.1.encoded_len() + args.2.encoded_len() + args.3.encoded_len();
+			let mut message = send.submit(Id::new(REGISTRY).unwrap(), 0, len.into(), 0).unwrap();
+			args.0.encode(&mut message);
+			args.1.encode(&mut message);
+			args.2.encode(&mut message);
+			args.3.encode(&mut message);
+			message.finish();
+		});
+		client.dispatch(COMPOSITOR, 0, |send| submit(send, COMPOSITOR, 0, &[SURFACE]));
+		client
+	}
+
+	/// Encode a request with `encode`, then read it back and dispatch it.
+	fn dispatch(&mut self, object: u32, opcode: u16, encode: impl FnOnce(&mut SendHalf<'_>)) {
+		encode(&mut self.requests.send_half());
+		let message = self.requests.recv();
+		assert_eq!((message.object_id(), message.opcode()), (Id::new(object).unwrap(), opcode));
+		self.objects.dispatch_request(&mut self.events.send_half(), message).unwrap();
+	}
+}
+
+fn dispatch(c: &mut Criterion) {
+	let mut group = c.benchmark_group("dispatch");
+	let mut client = Client::new();
+	group.bench_function("wl_surface.damage", |b| {
+		b.iter(|| client.dispatch(SURFACE, 2, |send| submit(send, SURFACE, 2, &[0, 0, 64, 64])))
+	});
+	group.bench_function("wl_surface.set_buffer_scale", |b| {
+		b.iter(|| client.dispatch(SURFACE, 8, |send| submit(send, SURFACE, 8, &[black_box(2)])))
+	});
+	group.finish();
+}
+
+fn get_many_mut(c: &mut Criterion) {
+	let mut group = c.benchmark_group("get_many_mut");
+	let mut client = Client::new();
+	let id = |id| Some(Id::<AnyObject>::new(id).unwrap());
+	group.bench_function("1", |b| {
+		b.iter(|| {
+			black_box(&client.objects.get_many_mut([id(SURFACE)]).unwrap());
+		})
+	});
+	group.bench_function("2", |b| {
+		b.iter(|| {
+			black_box(&client.objects.get_many_mut([id(SURFACE), id(COMPOSITOR)]).unwrap());
+		})
+	});
+	group.bench_function("3", |b| {
+		b.iter(|| {
+			black_box(&client.objects.get_many_mut([id(SURFACE), id(COMPOSITOR), id(REGISTRY)]).unwrap());
+		})
+	});
+	group.bench_function("new id", |b| {
+		// a fresh map each time, so the new slots have to be allocated
+		b.iter_batched_ref(
+			Objects::new,
+			|objects| black_box(objects.get_many_mut([id(DISPLAY), id(REGISTRY)]).unwrap().len()),
+			BatchSize::SmallInput,
+		)
+	});
+	group.finish();
+}
+
+/// A new global announced to every client, as when an output is plugged in.
+fn broadcast(c: &mut Criterion) {
+	let mut group = c.benchmark_group("broadcast");
+	let mut clients: Vec<Loopback> = (0..CLIENTS).map(|_| Loopback::new()).collect();
+	let registry = Id::new(REGISTRY).unwrap();
+	group.throughput(Throughput::Elements(CLIENTS as u64));
+	group.bench_function("wl_registry.global", |b| {
+		b.iter(|| {
+			for client in &mut clients {
+				Registry.send_global(registry, &mut client.send_half(), 7, "wl_output", 4).unwrap();
+			}
+			for client in &mut clients {
+				black_box(client.recv());
+			}
+		})
+	});
+	group.finish();
+}
+
+criterion_group!(benches, dispatch, get_many_mut, broadcast);
+criterion_main!(benches);
@@ -0,0 +1,79 @@
+//! Encoding and decoding of protocol messages, without a socket in between.
+//!
+//! Messages are written and read back through [`Loopback`], so every benchmark also pays for parsing the header of the
+//! message it wrote.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use myway::{
+	client::{testing::Loopback, SendHalf},
+	object_impls::{Callback, Registry},
+	protocol::{AnyObject, DecodeArg, EncodeArg, Id, Word},
+};
+
+/// Write a message with a single argument to `object`.
+fn submit(send: &mut SendHalf<'_>, object: Id<AnyObject>, arg: impl EncodeArg) {
+	let mut message = send.submit(object, 0, arg.encoded_len().into(), 0).unwrap();
+	arg.encode(&mut message);
+	message.finish();
+}
+
+fn recv(c: &mut Criterion) {
+	let mut group = c.benchmark_group("recv");
+	let object = Id::new(3).unwrap();
+	let mut loopback = Loopback::new();
+
+	group.bench_function("header", |b| {
+		b.iter(|| {
+			submit(&mut loopback.send_half(), object, 0u32);
+			let message = loopback.recv();
+			black_box((message.object_id(), message.opcode()));
+		})
+	});
+
+	let string = "xdg_wm_base_and_then_some";
+	group.throughput(Throughput::Bytes(string.len() as u64));
+	group.bench_function("string", |b| {
+		b.iter(|| {
+			submit(&mut loopback.send_half(), object, string);
+			let mut message = loopback.recv();
+			black_box(<&str>::decode_arg(&mut message).unwrap());
+		})
+	});
+
+	// about the size of a keymap-less keyboard enter with a few keys held
+	let array: Vec<Word> = (0..64).collect();
+	group.throughput(Throughput::Bytes(std::mem::size_of_val(&array[..]) as u64));
+	group.bench_function("array", |b| {
+		b.iter(|| {
+			submit(&mut loopback.send_half(), object, &array[..]);
+			let mut message = loopback.recv();
+			black_box(<&[Word]>::decode_arg(&mut message).unwrap());
+		})
+	});
+	group.finish();
+}
+
+fn send(c: &mut Criterion) {
+	let mut group = c.benchmark_group("send");
+	let mut loopback = Loopback::new();
+
+	let callback = Id::new(5).unwrap();
+	group.bench_function("wl_callback.done", |b| {
+		b.iter(|| {
+			Callback.send_done(callback, &mut loopback.send_half(), black_box(1234)).unwrap();
+			black_box(loopback.recv());
+		})
+	});
+
+	let registry = Id::new(2).unwrap();
+	group.bench_function("wl_registry.global", |b| {
+		b.iter(|| {
+			Registry.send_global(registry, &mut loopback.send_half(), 7, black_box("wl_output"), 4).unwrap();
+			black_box(loopback.recv());
+		})
+	});
+	group.finish();
+}
+
+criterion_group!(benches, recv, send);
+criterion_main!(benches);
@@ -1,7 +1,27 @@
-use std::{env, io::Result, path::PathBuf};
+use std::{env, path::PathBuf, process};
 
-fn main() -> Result<()> {
+fn main() {
 	let mut path = PathBuf::from(env::var_os("OUT_DIR").unwrap());
 	path.push("wayland_protocol.rs");
-	myway_protogen::generate(&["protocols/wayland.xml", "protocols/xdg-shell.xml"], path)
+	let result = myway_protogen::Generator::new()
+		.protocol("protocols/wayland.xml")
+		.protocol("protocols/xdg-shell.xml")
+		.protocol("protocols/content-type-v1.xml")
+		.protocol("protocols/tearing-control-v1.xml")
+		.protocol("protocols/xdg-foreign-unstable-v2.xml")
+		.protocol("protocols/idle-inhibit-unstable-v1.xml")
+		.protocol("protocols/alpha-modifier-v1.xml")
+		.protocol("protocols/fullscreen-shell-unstable-v1.xml")
+		.protocol("protocols/xdg-activation-v1.xml")
+		.protocol("protocols/myway-debug-v1.xml")
+		.round_trip_tests(true)
+		.dyn_dispatch(env::var_os("CARGO_FEATURE_DYN_DISPATCH").is_some())
+		.arg_tracing(env::var_os("CARGO_FEATURE_ARG_TRACING").is_some())
+		.file_per_protocol(true)
+		.generate(path);
+	// print diagnostics one per line rather than as a debug-formatted error
+	if let Err(err) = result {
+		eprintln!("{err}");
+		process::exit(1);
+	}
 }
@@ -0,0 +1,25 @@
+//! Arbitrary bytes sent by a client, with some file descriptors attached. The client may be disconnected for them, but
+//! the compositor must never panic.
+//!
+//! Run with `cargo +nightly fuzz run requests`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use myway::client::testing::Harness;
+use std::{fs::File, os::unix::io::AsRawFd};
+
+fuzz_target!(|data: &[u8]| {
+	// the first byte is how many file descriptors to attach, so requests taking one can get it
+	let (fds, bytes) = match data {
+		[n, rest @ ..] => (usize::from(n % 4), rest),
+		[] => return,
+	};
+	let path = std::env::temp_dir().join(format!("myway-fuzz-{}", std::process::id()));
+	let file = File::options().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+	std::fs::remove_file(path).unwrap();
+	file.set_len(4096).unwrap();
+
+	let mut harness = Harness::new();
+	let _ = harness.feed(bytes, &vec![file.as_raw_fd(); fds]);
+});
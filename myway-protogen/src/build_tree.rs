@@ -32,6 +32,7 @@ macro_rules! attributes {
 	(@stringify_inner r#type) => { "type" };
 	(@stringify_inner r#enum) => { "enum" };
 	(@stringify_inner allow_null) => { "allow-null" };
+	(@stringify_inner deprecated_since) => { "deprecated-since" };
 	(@stringify_inner $attr:tt) => { stringify!($attr) };
 	(@parse $elem:ident, $attr:ident, Option<$gty:tt>) => {
 		attributes!(@parse $elem, $attr, $gty)
@@ -97,11 +98,17 @@ fn build_interface<'doc>(node: Node<'doc, '_>) -> Result<Interface<'doc>> {
 		}
 	}
 
-	Ok(Interface { name, version, desc, requests, events, enums })
+	Ok(Interface { offset: node.range().start, name, version, desc, requests, events, enums })
 }
 
 fn build_message<'doc>(node: Node<'doc, '_>) -> Result<Message<'doc>> {
-	attributes![node; name: str, r#type: Option<str>, since: Option<NonZeroU32>];
+	attributes![
+		node;
+		name: str,
+		r#type: Option<str>,
+		since: Option<NonZeroU32>,
+		deprecated_since: Option<NonZeroU32>,
+	];
 	let mut children = Children::of(node);
 	let desc = children.description()?;
 	let mut args = Vec::new();
@@ -111,19 +118,21 @@ fn build_message<'doc>(node: Node<'doc, '_>) -> Result<Message<'doc>> {
 		// wayland-client.h
 		if matches!(arg.ty, ArgType::NewId { interface: None }) {
 			args.push(Arg {
+				offset: elem.range().start,
 				name: "interface",
 				ty: ArgType::String { nullable: false },
 				summary: Some("requested interface to bind the object as (e.g. `\"wl_seat\\0\"`)"),
 			});
 			args.push(Arg {
+				offset: elem.range().start,
 				name: "version",
 				ty: ArgType::Uint,
 				summary: Some("version of the requested interface to bind as"),
 			});
 		}
-		args.push(build_arg(elem)?);
+		args.push(arg);
 	}
-	Ok(Message { name, kind: r#type, since, desc, args })
+	Ok(Message { offset: node.range().start, name, kind: r#type, since, deprecated_since, desc, args })
 }
 
 fn build_arg<'doc>(node: Node<'doc, '_>) -> Result<Arg<'doc>> {
@@ -147,7 +156,7 @@ fn build_arg<'doc>(node: Node<'doc, '_>) -> Result<Arg<'doc>> {
 			node.range()
 		),
 	};
-	Ok(Arg { name, ty, summary })
+	Ok(Arg { offset: node.range().start, name, ty, summary })
 }
 
 fn build_enum<'doc>(node: Node<'doc, '_>) -> Result<Enum<'doc>> {
@@ -158,7 +167,7 @@ fn build_enum<'doc>(node: Node<'doc, '_>) -> Result<Enum<'doc>> {
 	while let Some(elem) = children.next_assert("entry")? {
 		entries.push(build_entry(elem)?);
 	}
-	Ok(Enum { name, since, bitfield: bitfield.unwrap_or_default(), desc, entries })
+	Ok(Enum { offset: node.range().start, name, since, bitfield: bitfield.unwrap_or_default(), desc, entries })
 }
 
 fn build_entry<'doc>(node: Node<'doc, '_>) -> Result<Entry<'doc>> {
@@ -171,7 +180,7 @@ fn build_entry<'doc>(node: Node<'doc, '_>) -> Result<Entry<'doc>> {
 		Ok(n) => n,
 		Err(err) => bail!("attribute \"value\" value {value:?} can't be parsed as a u32: {err:?}"),
 	};
-	Ok(Entry { name, value, value_is_hex, summary, since })
+	Ok(Entry { offset: node.range().start, name, value, value_is_hex, summary, since })
 }
 
 #[derive(Debug)]
@@ -1,4 +1,7 @@
-use crate::types::{Arg, ArgType, Enum, Interface, Message, Protocol};
+use crate::{
+	types::{Arg, ArgType, Enum, Interface, Message, Protocol},
+	Options,
+};
 use std::{
 	fmt::{self, Display, Formatter, Write as _},
 	io::{Result, Write},
@@ -14,43 +17,77 @@ static IMPL_TYPES: &[(&str, &str)] = &[
 	("wl_buffer", "crate::object_impls::shm::ShmBuffer"),
 	("wl_compositor", "crate::object_impls::window::Compositor"),
 	("wl_surface", "crate::object_impls::window::Surface"),
-	("wl_region", "crate::object_impls::window::Region"),
+	("wl_region", "crate::object_impls::window::RegionObject"),
+	("wl_shell", "crate::object_impls::shell::Shell"),
+	("wl_shell_surface", "crate::object_impls::shell::ShellSurface"),
 	("xdg_wm_base", "crate::object_impls::window::WindowManager"),
 	("xdg_positioner", "crate::object_impls::window::Positioner"),
 	("xdg_surface", "crate::object_impls::window::XdgSurfaceImpl"),
 	("xdg_popup", "crate::object_impls::window::PopupObject"),
 	("xdg_toplevel", "crate::object_impls::window::ToplevelObject"),
+	("wp_content_type_manager_v1", "crate::object_impls::content_type::ContentTypeManager"),
+	("wp_content_type_v1", "crate::object_impls::content_type::ContentTypeObject"),
+	("wp_tearing_control_manager_v1", "crate::object_impls::tearing_control::TearingControlManager"),
+	("wp_tearing_control_v1", "crate::object_impls::tearing_control::TearingControlObject"),
+	("zxdg_exporter_v2", "crate::object_impls::foreign::Exporter"),
+	("zxdg_importer_v2", "crate::object_impls::foreign::Importer"),
+	("zxdg_exported_v2", "crate::object_impls::foreign::Exported"),
+	("zxdg_imported_v2", "crate::object_impls::foreign::Imported"),
+	("zwp_idle_inhibit_manager_v1", "crate::object_impls::idle_inhibit::IdleInhibitManager"),
+	("zwp_idle_inhibitor_v1", "crate::object_impls::idle_inhibit::IdleInhibitorObject"),
+	("wp_alpha_modifier_v1", "crate::object_impls::alpha_modifier::AlphaModifierManager"),
+	("wp_alpha_modifier_surface_v1", "crate::object_impls::alpha_modifier::AlphaModifierObject"),
+	("zwp_fullscreen_shell_v1", "crate::object_impls::fullscreen_shell::FullscreenShell"),
+	("zwp_fullscreen_shell_mode_feedback_v1", "crate::object_impls::fullscreen_shell::ModeFeedback"),
+	("xdg_activation_v1", "crate::object_impls::activation::ActivationGlobal"),
+	("xdg_activation_token_v1", "crate::object_impls::activation::TokenObject"),
+	("myway_debug_v1", "crate::object_impls::debug::DebugGlobal"),
+	("myway_debug_object_list_v1", "crate::object_impls::debug::ObjectList"),
+	("myway_debug_surface_stats_v1", "crate::object_impls::debug::SurfaceStats"),
 ];
 
 /// Find the Rust implementation type for a given protocol interface.
-fn impl_of<'a, 'b>(iface: &'b str) -> Option<&'a str> {
+fn impl_of<'a>(iface: &str) -> Option<&'a str> {
 	IMPL_TYPES.iter().find(|&&(ifa, _)| ifa == iface).map(|&(_, ty)| ty)
 }
 
-pub(crate) fn emit_anyobject(dest: &mut impl Write) -> Result<()> {
+/// Emit `AnyObject` and the `Object` impls for the implementation types of the generated `interfaces`.
+pub(crate) fn emit_anyobject(dest: &mut impl Write, interfaces: &[&str], options: Options) -> Result<()> {
+	let impl_types: Vec<_> = IMPL_TYPES.iter().filter(|(iface, _)| interfaces.contains(iface)).collect();
+	if options.dyn_dispatch {
+		return emit_anyobject_dyn(dest, &impl_types);
+	}
 	writeln!(dest, "#[derive(Debug)]")?;
 	writeln!(dest, "pub enum AnyObject {{")?;
-	for &(_, ty) in IMPL_TYPES {
+	for &&(_, ty) in &impl_types {
 		let bare_ty = ty.rsplit_once(':').map_or(ty, |(_, name)| name);
 		writeln!(dest, "\t{bare_ty}({ty}),")?;
 	}
+	writeln!(dest, "\t/// An object implemented outside the generated bindings.")?;
+	writeln!(dest, "\tGeneric(Box<dyn crate::object_map::ObjectDyn>),")?;
 	writeln!(dest, "}}")?;
 	writeln!(dest, "impl AnyObject {{")?;
-	writeln!(
-		dest,
-		"\tpub fn request_handler(&self) -> fn(&mut Objects, &mut SendHalf<'_>, RecvMessage<'_>) -> \
-		 std::io::Result<()> {{"
-	)?;
+	writeln!(dest, "\tpub fn request_handler(&self) -> crate::object_map::RequestHandler {{")?;
 	writeln!(dest, "\t\tmatch self {{")?;
-	for &(_, ty) in IMPL_TYPES {
+	for &&(_, ty) in &impl_types {
 		let variant = ty.rsplit_once(':').map_or(ty, |(_, name)| name);
 		writeln!(dest, "\t\t\tSelf::{variant}(_) => {ty}::handle_request,")?;
 	}
+	writeln!(dest, "\t\t\tSelf::Generic(_) => crate::object_map::dispatch_dyn,")?;
+	writeln!(dest, "\t\t}}")?;
+	writeln!(dest, "\t}}")?;
+	writeln!(dest, "\tpub fn interface(&self) -> &'static str {{")?;
+	writeln!(dest, "\t\tmatch self {{")?;
+	for &&(_, ty) in &impl_types {
+		let variant = ty.rsplit_once(':').map_or(ty, |(_, name)| name);
+		writeln!(dest, "\t\t\tSelf::{variant}(_) => {ty}::INTERFACE,")?;
+	}
+	writeln!(dest, "\t\t\tSelf::Generic(obj) => obj.interface(),")?;
 	writeln!(dest, "\t\t}}")?;
 	writeln!(dest, "\t}}")?;
 	writeln!(dest, "}}")?;
 
-	for &(_, ty) in IMPL_TYPES {
+	for &&(_, ty) in &impl_types {
 		let bare_ty = ty.rsplit_once(':').map_or(ty, |(_, name)| name);
 		writeln!(dest, "impl Object for {ty} {{")?;
 		writeln!(dest, "\tfn upcast(self) -> AnyObject {{")?;
@@ -69,37 +106,93 @@ pub(crate) fn emit_anyobject(dest: &mut impl Write) -> Result<()> {
 	Ok(())
 }
 
-pub(crate) fn emit_protocol(protocol: &Protocol<'_>, dest: &mut impl Write) -> Result<()> {
+/// Emit `AnyObject` as a trait object, with `ErasedObject` impls for the implementation types.
+fn emit_anyobject_dyn(dest: &mut impl Write, impl_types: &[&(&str, &str)]) -> Result<()> {
+	writeln!(dest, "#[derive(Debug)]")?;
+	writeln!(dest, "pub struct AnyObject(pub Box<dyn crate::object_map::ErasedObject>);")?;
+	writeln!(dest, "impl AnyObject {{")?;
+	writeln!(dest, "\tpub fn request_handler(&self) -> crate::object_map::RequestHandler {{")?;
+	writeln!(dest, "\t\tself.0.request_handler()")?;
+	writeln!(dest, "\t}}")?;
+	writeln!(dest, "\tpub fn interface(&self) -> &'static str {{")?;
+	writeln!(dest, "\t\tself.0.interface()")?;
+	writeln!(dest, "\t}}")?;
+	writeln!(dest, "}}")?;
+
+	for &&(_, ty) in impl_types {
+		writeln!(dest, "impl crate::object_map::ErasedObject for {ty} {{")?;
+		writeln!(dest, "\tfn request_handler(&self) -> crate::object_map::RequestHandler {{")?;
+		writeln!(dest, "\t\tSelf::handle_request")?;
+		writeln!(dest, "\t}}")?;
+		writeln!(dest, "\tfn interface(&self) -> &'static str {{")?;
+		writeln!(dest, "\t\tSelf::INTERFACE")?;
+		writeln!(dest, "\t}}")?;
+		writeln!(dest, "\tfn as_any(&self) -> &dyn std::any::Any {{")?;
+		writeln!(dest, "\t\tself")?;
+		writeln!(dest, "\t}}")?;
+		writeln!(dest, "\tfn as_any_mut(&mut self) -> &mut dyn std::any::Any {{")?;
+		writeln!(dest, "\t\tself")?;
+		writeln!(dest, "\t}}")?;
+		writeln!(dest, "\tfn into_any(self: Box<Self>) -> Box<dyn std::any::Any> {{")?;
+		writeln!(dest, "\t\tself")?;
+		writeln!(dest, "\t}}")?;
+		writeln!(dest, "}}")?;
+	}
+	Ok(())
+}
+
+pub(crate) fn emit_protocol(protocol: &Protocol<'_>, dest: &mut impl Write, options: Options) -> Result<()> {
 	if let Some(c) = protocol.copyright {
 		writeln!(dest, "// Copyright of the protocol specification:")?;
 		write_multiline(dest, "// > ", [c])?;
 	}
+	// the generated file holds several protocols and is included into a module, so inner doc comments can't be used
 	if let Some(desc) = protocol.desc {
-		write_multiline(dest, "//! ", [desc.summary, desc.description])?;
+		writeln!(dest, "// Protocol {}:", protocol.name)?;
+		write_multiline(dest, "// > ", [desc.summary, desc.description])?;
 	}
+	// format only the code, since the pretty-printer drops plain comments
+	let mut code = Vec::new();
 	for iface in &protocol.interfaces {
-		emit_interface(dest, iface, impl_of(iface.name))?;
+		emit_interface(&mut code, protocol, iface, impl_of(iface.name), options)?;
 	}
-	Ok(())
+	dest.write_all(&crate::pretty_print(code))
 }
 
-fn emit_interface(dest: &mut impl Write, iface: &Interface, impl_type: Option<&str>) -> Result<()> {
+fn emit_interface(
+	dest: &mut impl Write,
+	protocol: &Protocol<'_>,
+	iface: &Interface,
+	impl_type: Option<&str>,
+	options: Options,
+) -> Result<()> {
 	if let Some(desc) = iface.desc {
 		write_multiline(dest, "/// ", [desc.summary, desc.description])?;
 	}
 
-	// requests, as a trait of handlers
+	// requests, as a trait of handlers, kept object-safe for `Box<dyn Trait>` implementations: methods that need the
+	// implementation's type (destructors taking it by value, and validation without a receiver) require `Self: Sized`
 	let trait_name = RustName(iface.name);
 	writeln!(dest, "pub mod {} {{", iface.name)?;
 	writeln!(dest, "\tuse crate::client::{{RecvMessage, SendMessage, SendHalf}};")?;
 	writeln!(dest, "\tuse crate::object_map::{{Objects, OccupiedEntry, VacantEntry}};")?;
 	writeln!(dest, "\tuse crate::protocol::{{Word, Fd, Fixed, DecodeArg, Id, EncodeArg}};")?;
 	writeln!(dest, "\tuse super::AnyObject;")?;
-	writeln!(dest, "\tuse log::trace;")?;
-	writeln!(dest, "\tuse std::{{io::{{self, ErrorKind, Result}}, os::unix::io::AsRawFd}};")?;
+	if options.arg_tracing {
+		writeln!(dest, "\tuse log::trace;")?;
+	}
+	writeln!(dest, "\tuse crate::error::{{InvalidRequest, ProtocolError, Result}};")?;
+	writeln!(dest, "\tuse std::os::unix::io::AsRawFd;")?;
 	writeln!(dest, "\t#[allow(clippy::too_many_arguments)]")?;
 
-	writeln!(dest, "\tpub trait {trait_name}: Sized {{")?;
+	writeln!(dest, "\tpub trait {trait_name} {{")?;
+	if iface.requests.iter().any(|req| req.deprecated_since.is_some()) {
+		writeln!(
+			dest,
+			"\t\t/// Version the object was bound at, to tell whether its deprecated requests are deprecated for it."
+		)?;
+		writeln!(dest, "\t\tfn version(&self) -> u32;")?;
+	}
 	for req in &iface.requests {
 		if let Some(desc) = req.desc {
 			write_multiline(dest, "\t\t/// ", [desc.summary, desc.description])?;
@@ -110,6 +203,7 @@ fn emit_interface(dest: &mut impl Write, iface: &Interface, impl_type: Option<&s
 		for arg in &req.args {
 			writeln!(dest, "\t\t/// - `{}`: {}", arg.name, arg.summary.unwrap_or("(no summary available)"))?;
 		}
+		emit_deprecated(dest, iface, req)?;
 		write!(dest, "\t\tfn handle_{}(", req.name)?;
 		if req.kind == Some("destructor") {
 			write!(dest, "self, ")?;
@@ -118,9 +212,32 @@ fn emit_interface(dest: &mut impl Write, iface: &Interface, impl_type: Option<&s
 		}
 		write!(dest, "client: &mut SendHalf<'_>, ")?;
 		for arg in &req.args {
-			write!(dest, "{}: {}, ", arg.name, RustArgType(arg.ty, TypePosition::Handler))?;
+			write!(dest, "{}: {}, ", RustIdent(arg.name), RustArgType(arg.ty, TypePosition::Handler))?;
+		}
+		if req.kind == Some("destructor") {
+			writeln!(dest, ") -> Result<()> where Self: Sized;")?;
+		} else {
+			writeln!(dest, ") -> Result<()>;")?;
+		}
+
+		if req.args.iter().any(|arg| is_validated(arg.ty)) {
+			writeln!(
+				dest,
+				"\t\t/// Check the arguments of a `{}` request before it is dispatched, returning an error to reject \
+				 it.",
+				req.name
+			)?;
+			writeln!(dest, "\t\t///")?;
+			writeln!(dest, "\t\t/// The default implementation accepts any arguments.")?;
+			writeln!(dest, "\t\t#[allow(unused_variables)]")?;
+			write!(dest, "\t\tfn validate_{}(", req.name)?;
+			for arg in req.args.iter().filter(|arg| is_validated(arg.ty)) {
+				write!(dest, "{}: {}, ", RustIdent(arg.name), RustArgType(arg.ty, TypePosition::Handler))?;
+			}
+			writeln!(dest, ") -> Result<()> where Self: Sized {{")?;
+			writeln!(dest, "\t\t\tOk(())")?;
+			writeln!(dest, "\t\t}}")?;
 		}
-		writeln!(dest, ") -> Result<()>;")?;
 	}
 	writeln!(dest, "\t}}")?;
 
@@ -128,9 +245,10 @@ fn emit_interface(dest: &mut impl Write, iface: &Interface, impl_type: Option<&s
 		writeln!(dest, "\timpl {impl_type} where Self: {trait_name} {{")?;
 		writeln!(dest, "\t\tpub const INTERFACE: &str = {:?};", iface.name)?;
 		writeln!(dest, "\t\tpub const VERSION: u32 = {};", iface.version)?;
-		emit_request_handler(dest, iface)?;
+		emit_request_handler(dest, iface, options)?;
 		for (opcode, ev) in iface.events.iter().enumerate() {
 			writeln!(dest, "\t\t#[allow(unused_mut)]")?;
+			emit_deprecated(dest, iface, ev)?;
 			write!(dest, "\t\tpub fn send_{}(", ev.name)?;
 			if ev.kind == Some("destructor") {
 				write!(dest, "self")?;
@@ -139,26 +257,34 @@ fn emit_interface(dest: &mut impl Write, iface: &Interface, impl_type: Option<&s
 			}
 			write!(dest, ", self_id: Id<Self>, client: &mut SendHalf<'_>")?;
 			for arg in &ev.args {
-				write!(dest, ", {}: {}", arg.name, RustArgType(arg.ty, TypePosition::Event))?;
+				write!(dest, ", {}: {}", RustIdent(arg.name), RustArgType(arg.ty, TypePosition::Event))?;
 			}
 			writeln!(dest, ") -> Result<()> {{")?;
+			writeln!(dest, "\t\t\tlet _span = tracing::trace_span!(\"encode\", event = {:?}).entered();", ev.name)?;
 			emit_log(dest, "\t\t\t", "event", ev)?;
 			writeln!(dest, "\t\t\tlet (mut len, mut fds) = (0, 0);")?;
 			for arg in &ev.args {
-				writeln!(dest, "\t\t\tlen += {}.encoded_len();", arg.name)?;
-				writeln!(dest, "\t\t\tfds += {}.is_fd() as usize;", arg.name)?;
+				writeln!(dest, "\t\t\tlen += {}.encoded_len();", RustIdent(arg.name))?;
+				writeln!(dest, "\t\t\tfds += {}.is_fd() as usize;", RustIdent(arg.name))?;
 			}
 			writeln!(dest, "\t\t\tlet mut event = client.submit(self_id.cast(), {opcode}, len as usize, fds)?;")?;
 			for arg in &ev.args {
-				writeln!(
-					dest,
-					"\t\t\ttrace!(\"encoding argument {0}={{{0}:?}} (type: {1}) for event\");",
-					arg.name,
-					RustArgType(arg.ty, TypePosition::Event)
-				)?;
-				writeln!(dest, "\t\t\t{}.encode(&mut event);", arg.name)?;
+				if options.arg_tracing {
+					writeln!(
+						dest,
+						"\t\t\ttrace!(\"encoding argument {0}={{:?}} (type: {1}) for event\", {2});",
+						arg.name,
+						RustArgType(arg.ty, TypePosition::Event),
+						RustIdent(arg.name),
+					)?;
+				}
+				writeln!(dest, "\t\t\t{}.encode(&mut event);", RustIdent(arg.name))?;
 			}
 			writeln!(dest, "\t\t\tevent.finish();")?;
+			writeln!(
+				dest,
+				"\t\t\tclient.record(crate::state_dump::Direction::Event, Self::INTERFACE, self_id.cast(), {opcode});"
+			)?;
 			writeln!(dest, "\t\t\tOk(())")?;
 			writeln!(dest, "\t\t}}")?;
 		}
@@ -169,13 +295,177 @@ fn emit_interface(dest: &mut impl Write, iface: &Interface, impl_type: Option<&s
 		emit_enum(dest, en)?;
 	}
 
+	emit_descriptor(dest, iface)?;
+	if options.round_trip_tests {
+		emit_round_trip_tests(dest, protocol, iface)?;
+	}
+
 	writeln!(dest, "}}")?;
 	Ok(())
 }
 
+/// Emit a test module encoding a sample of every request and event with `EncodeArg` and checking that `DecodeArg`
+/// reproduces it.
+///
+/// Messages carrying file descriptors are skipped, since the transport's fd bookkeeping can't round-trip them yet.
+fn emit_round_trip_tests(dest: &mut impl Write, protocol: &Protocol<'_>, iface: &Interface<'_>) -> Result<()> {
+	writeln!(dest, "\t#[cfg(test)]")?;
+	writeln!(dest, "\tmod round_trip_tests {{")?;
+	writeln!(dest, "\t\tuse crate::client::testing::Loopback;")?;
+	writeln!(dest, "\t\tuse crate::protocol::{{DecodeArg, EncodeArg, Fixed, Id, Word}};")?;
+	writeln!(dest, "\t\tuse super::AnyObject;")?;
+	for (kind, messages) in [("request", &iface.requests), ("event", &iface.events)] {
+		for (opcode, msg) in messages.iter().enumerate() {
+			let samples: Option<Vec<_>> =
+				msg.args.iter().enumerate().map(|(i, arg)| sample_value(protocol, iface, arg.ty, i)).collect();
+			let samples = match samples {
+				Some(samples) => samples,
+				None => continue,
+			};
+			writeln!(dest, "\t\t#[test]")?;
+			writeln!(dest, "\t\t#[allow(unused_mut)]")?;
+			writeln!(dest, "\t\tfn {kind}_{}() {{", msg.name)?;
+			writeln!(dest, "\t\t\tlet mut transport = Loopback::new();")?;
+			writeln!(dest, "\t\t\tlet object = Id::<AnyObject>::new(3).unwrap();")?;
+			for (arg, sample) in msg.args.iter().zip(&samples) {
+				writeln!(dest, "\t\t\tlet arg_{}: {} = {sample};", arg.name, TestArgType(arg.ty))?;
+			}
+			writeln!(dest, "\t\t\tlet mut len = 0;")?;
+			for arg in &msg.args {
+				writeln!(dest, "\t\t\tlen += arg_{}.encoded_len();", arg.name)?;
+			}
+			writeln!(dest, "\t\t\tlet mut send = transport.send_half();")?;
+			writeln!(dest, "\t\t\tlet mut message = send.submit(object, {opcode}, len as usize, 0).unwrap();")?;
+			for arg in &msg.args {
+				writeln!(dest, "\t\t\targ_{}.encode(&mut message);", arg.name)?;
+			}
+			writeln!(dest, "\t\t\tmessage.finish();")?;
+			writeln!(dest, "\t\t\tlet mut message = transport.recv();")?;
+			writeln!(dest, "\t\t\tassert_eq!(message.object_id(), object);")?;
+			writeln!(dest, "\t\t\tassert_eq!(message.opcode(), {opcode});")?;
+			for arg in &msg.args {
+				writeln!(
+					dest,
+					"\t\t\tassert_eq!(<{ty}>::decode_arg(&mut message).unwrap(), arg_{name}, \"argument {name}\");",
+					ty = TestArgType(arg.ty),
+					name = arg.name,
+				)?;
+			}
+			writeln!(dest, "\t\t\tmessage.finish().unwrap();")?;
+			writeln!(dest, "\t\t}}")?;
+		}
+	}
+	writeln!(dest, "\t}}")?;
+	Ok(())
+}
+
+/// Rust expression for a sample value of an argument, or `None` if the argument can't be round-tripped in tests.
+///
+/// `index` is the position of the argument in its message, used to vary samples between arguments of the same type.
+fn sample_value(protocol: &Protocol<'_>, iface: &Interface<'_>, ty: ArgType<'_>, index: usize) -> Option<String> {
+	// string lengths chosen to cover every amount of padding, including a terminator that needs a word of its own
+	const STRINGS: [&str; 4] = ["wl", "abc", "abcd", "round trip"];
+	let id = 7 + index;
+	Some(match ty {
+		ArgType::Int => format!("{}", -0x1234_5678 - index as i32),
+		ArgType::Uint => format!("{:#x}", 0xdead_beef_u32.wrapping_add(index as u32)),
+		ArgType::Enum(name) => {
+			let (iface_name, enum_name) = name.split_once('.').unwrap_or((iface.name, name));
+			let en = protocol
+				.interfaces
+				.iter()
+				.find(|iface| iface.name == iface_name)?
+				.enums
+				.iter()
+				.find(|en| en.name == enum_name)?;
+			format!("super::{}::{}", RustName(name), RustName(en.entries.last()?.name))
+		},
+		ArgType::Fixed => format!("Fixed::from({:?})", -12.5 - index as f64),
+		ArgType::String { nullable: false } => format!("{:?}", STRINGS[index % STRINGS.len()]),
+		ArgType::String { nullable: true } => format!("Some({:?})", STRINGS[index % STRINGS.len()]),
+		ArgType::Object { nullable: false, .. } | ArgType::NewId { .. } => format!("Id::new({id}).unwrap()"),
+		ArgType::Object { nullable: true, .. } => format!("Id::new({id})"),
+		ArgType::Array => format!("&[{id}, {}, {}]", id + 1, id + 2),
+		ArgType::Fd => return None,
+	})
+}
+
+/// Format an [`ArgType`] as the Rust type used to encode and decode it in round-trip tests.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct TestArgType<'a>(ArgType<'a>);
+
+impl Display for TestArgType<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self.0 {
+			// tests are emitted in a module inside the interface's module
+			ArgType::Enum(name) => write!(f, "super::{}", RustName(name)),
+			ArgType::Object { nullable: true, .. } => f.write_str("Option<Id<AnyObject>>"),
+			ArgType::Object { nullable: false, .. } | ArgType::NewId { .. } => f.write_str("Id<AnyObject>"),
+			ty => RustArgType(ty, TypePosition::RawProtocol).fmt(f),
+		}
+	}
+}
+
+/// Emit a `DESCRIPTOR` static describing an interface's messages, for introspection and trace output.
+fn emit_descriptor(dest: &mut impl Write, iface: &Interface<'_>) -> Result<()> {
+	writeln!(dest, "\tpub static DESCRIPTOR: crate::protocol::InterfaceDesc = crate::protocol::InterfaceDesc {{")?;
+	writeln!(dest, "\t\tname: {:?},", iface.name)?;
+	writeln!(dest, "\t\tversion: {},", iface.version)?;
+	for (field, messages) in [("requests", &iface.requests), ("events", &iface.events)] {
+		writeln!(dest, "\t\t{field}: &[")?;
+		for msg in messages {
+			writeln!(dest, "\t\t\tcrate::protocol::MessageDesc {{")?;
+			writeln!(dest, "\t\t\t\tname: {:?},", msg.name)?;
+			writeln!(dest, "\t\t\t\tsince: {},", msg.since.map_or(1, |since| since.get()))?;
+			writeln!(dest, "\t\t\t\tdestructor: {},", msg.kind == Some("destructor"))?;
+			writeln!(dest, "\t\t\t\targs: &[")?;
+			for arg in &msg.args {
+				let (ty, interface, nullable, en) = match arg.ty {
+					ArgType::Int => ("int", None, false, None),
+					ArgType::Uint => ("uint", None, false, None),
+					ArgType::Enum(en) => ("uint", None, false, Some(en)),
+					ArgType::Fixed => ("fixed", None, false, None),
+					ArgType::String { nullable } => ("string", None, nullable, None),
+					ArgType::Object { interface, nullable } => ("object", interface, nullable, None),
+					ArgType::NewId { interface } => ("new_id", interface, false, None),
+					ArgType::Array => ("array", None, false, None),
+					ArgType::Fd => ("fd", None, false, None),
+				};
+				writeln!(
+					dest,
+					"\t\t\t\t\tcrate::protocol::ArgDesc {{ name: {:?}, ty: {ty:?}, interface: {interface:?}, \
+					 nullable: {nullable}, enum_name: {en:?} }},",
+					arg.name
+				)?;
+			}
+			writeln!(dest, "\t\t\t\t],")?;
+			writeln!(dest, "\t\t\t}},")?;
+		}
+		writeln!(dest, "\t\t],")?;
+	}
+	writeln!(dest, "\t}};")?;
+	Ok(())
+}
+
+/// Emit `INTERFACES`, listing the descriptors of every generated interface.
+pub(crate) fn emit_interface_list<'a>(
+	dest: &mut impl Write,
+	interfaces: impl IntoIterator<Item = &'a str>,
+) -> Result<()> {
+	writeln!(dest, "pub static INTERFACES: &[&crate::protocol::InterfaceDesc] = &[")?;
+	for iface in interfaces {
+		writeln!(dest, "\t&{iface}::DESCRIPTOR,")?;
+	}
+	writeln!(dest, "];")?;
+	Ok(())
+}
+
 /// Emit  `fn handle_request(..) -> Result<()>` for an interface implementation.
 /// The function dispatches requests to the appropriate method by opcode.
-fn emit_request_handler(dest: &mut impl Write, iface: &Interface<'_>) -> Result<()> {
+fn emit_request_handler(dest: &mut impl Write, iface: &Interface<'_>, options: Options) -> Result<()> {
+	if iface.requests.iter().any(|req| req.deprecated_since.is_some()) {
+		writeln!(dest, "\t\t#[allow(deprecated)]")?; // calls to deprecated handlers
+	}
 	writeln!(dest, "\t\t#[allow(unused_mut, clippy::match_single_binding)]")?; // for interfaces with no requests
 	writeln!(
 		dest,
@@ -186,36 +476,55 @@ fn emit_request_handler(dest: &mut impl Write, iface: &Interface<'_>) -> Result<
 	writeln!(dest, "\t\t\tmatch message.opcode() {{")?;
 	for (i, req) in iface.requests.iter().enumerate() {
 		writeln!(dest, "\t\t\t\t{i} => {{")?;
+		writeln!(dest, "\t\t\t\t\tlet decode = tracing::trace_span!(\"decode\", request = {:?}).entered();", req.name)?;
 		for arg in &req.args {
+			if options.arg_tracing {
+				writeln!(
+					dest,
+					"\t\t\t\t\ttrace!(\"decoding argument {} (type: {}) from {{message:?}}\");",
+					arg.name,
+					RustArgType(arg.ty, TypePosition::Handler),
+				)?;
+			}
 			writeln!(
 				dest,
-				"\t\t\t\t\ttrace!(\"decoding argument {} (type: {}) from {{message:?}}\");",
-				arg.name,
-				RustArgType(arg.ty, TypePosition::Handler),
-			)?;
-			writeln!(
-				dest,
-				"\t\t\t\t\tlet {} = <{:#}>::decode_arg(&mut message)?;",
-				arg.name,
-				RustArgType(arg.ty, TypePosition::RawProtocol),
+				"\t\t\t\t\tlet {ident} = <{ty:#}>::decode_arg(&mut message).map_err(|err| \
+				 {{\n\t\t\t\t\t\tInvalidRequest::arguments(err, Self::INTERFACE, self_id, {i}, {req:?}, \
+				 Some({name:?}))\n\t\t\t\t\t}})?;",
+				ident = RustIdent(arg.name),
+				name = arg.name,
+				ty = RustArgType(arg.ty, TypePosition::RawProtocol),
+				req = req.name,
 			)?;
 		}
-		writeln!(dest, "\t\t\t\t\tmessage.finish()?;")?;
+		writeln!(
+			dest,
+			"\t\t\t\t\tmessage.finish().map_err(|err| InvalidRequest::arguments(err, Self::INTERFACE, self_id, {i}, \
+			 {:?}, None))?;",
+			req.name
+		)?;
 		emit_log(dest, "\t\t\t\t\t", "request", req)?;
+		if req.args.iter().any(|arg| is_validated(arg.ty)) {
+			write!(dest, "\t\t\t\t\tSelf::validate_{}(", req.name)?;
+			for arg in req.args.iter().filter(|arg| is_validated(arg.ty)) {
+				write!(dest, "{}, ", RustIdent(arg.name))?;
+			}
+			writeln!(dest, ")?;")?;
+		}
 
 		write!(dest, "\t\t\t\t\tlet [this")?;
 		for arg in &req.args {
 			if matches!(arg.ty, ArgType::Object { .. } | ArgType::NewId { .. }) {
-				write!(dest, ", {}", arg.name)?;
+				write!(dest, ", {}", RustIdent(arg.name))?;
 			}
 		}
 		write!(dest, "] = objects.get_many_mut([Some(self_id)")?;
 		for arg in &req.args {
 			match arg.ty {
 				ArgType::Object { nullable: false, .. } | ArgType::NewId { .. } => {
-					write!(dest, ", Some({})", arg.name)?
+					write!(dest, ", Some({})", RustIdent(arg.name))?
 				},
-				ArgType::Object { nullable: true, .. } => write!(dest, ", {}", arg.name)?,
+				ArgType::Object { nullable: true, .. } => write!(dest, ", {}", RustIdent(arg.name))?,
 				_ => (),
 			}
 		}
@@ -226,10 +535,10 @@ fn emit_request_handler(dest: &mut impl Write, iface: &Interface<'_>) -> Result<
 				ArgType::Object { nullable: false, .. } => writeln!(
 					dest,
 					"\t\t\t\t\tlet {name} = {name}.unwrap().into_occupied()?.downcast()?;",
-					name = arg.name
+					name = RustIdent(arg.name)
 				)?,
 				ArgType::Object { nullable: true, .. } => {
-					writeln!(dest, "\t\t\t\t\tlet {name} = match {name} {{", name = arg.name)?;
+					writeln!(dest, "\t\t\t\t\tlet {name} = match {name} {{", name = RustIdent(arg.name))?;
 					writeln!(dest, "\t\t\t\t\t\tSome(obj) => Some(obj.into_occupied()?.downcast()?),")?;
 					writeln!(dest, "\t\t\t\t\t\tNone => None,")?;
 					writeln!(dest, "\t\t\t\t\t}};")?;
@@ -237,32 +546,63 @@ fn emit_request_handler(dest: &mut impl Write, iface: &Interface<'_>) -> Result<
 				ArgType::NewId { .. } => writeln!(
 					dest,
 					"\t\t\t\t\tlet {name} = {name}.unwrap().into_vacant()?.downcast();",
-					name = arg.name
+					name = RustIdent(arg.name)
 				)?,
 				_ => (),
 			}
 		}
+		writeln!(dest, "\t\t\t\t\tdrop(decode);")?;
+		if let Some(version) = req.deprecated_since {
+			writeln!(
+				dest,
+				"\t\t\t\t\tif this.version() >= {version} && client.first_deprecated_use(Self::INTERFACE, {i}) {{"
+			)?;
+			writeln!(
+				dest,
+				"\t\t\t\t\t\tlog::warn!(\"a client sent {}.{}, which is deprecated since version {version}, to an \
+				 object of version {{}}; further uses by this client are not reported\", this.version());",
+				iface.name, req.name
+			)?;
+			writeln!(dest, "\t\t\t\t\t}}")?;
+		}
+		writeln!(dest, "\t\t\t\t\tlet _span = tracing::trace_span!(\"handle\", request = {:?}).entered();", req.name)?;
 		if req.kind == Some("destructor") {
 			write!(dest, "\t\t\t\t\tthis.take().handle_{}(client, ", req.name)?;
 		} else {
 			write!(dest, "\t\t\t\t\tthis.handle_{}(client, ", req.name)?;
 		}
 		for arg in &req.args {
-			write!(dest, "{}, ", arg.name)?;
+			write!(dest, "{}, ", RustIdent(arg.name))?;
 		}
 		writeln!(dest, ")")?;
 		writeln!(dest, "\t\t\t\t}},")?;
 	}
-	writeln!(dest, "\t\t\t\t_ => {{")?;
+	writeln!(dest, "\t\t\t\topcode => {{")?;
 	// ignore unused_variables for arguments without suppressing the lint for the entire function
-	writeln!(dest, "\t\t\t\t\tlet _ = (objects, client, self_id);")?;
-	writeln!(dest, "\t\t\t\t\tErr(io::Error::new(ErrorKind::InvalidInput, \"unknown request opcode {{opcode}}\"))")?;
+	writeln!(dest, "\t\t\t\t\tlet _ = (objects, client);")?;
+	writeln!(dest, "\t\t\t\t\tErr(InvalidRequest::opcode(Self::INTERFACE, self_id, opcode))")?;
 	writeln!(dest, "\t\t\t\t}},")?; // match arm
 	writeln!(dest, "\t\t\t}}")?; // match body
 	writeln!(dest, "\t\t}}")?; // method body
 	Ok(())
 }
 
+/// Emit a `#[deprecated]` attribute for a message with `deprecated-since`.
+fn emit_deprecated(dest: &mut impl Write, iface: &Interface<'_>, message: &Message<'_>) -> Result<()> {
+	if let Some(version) = message.deprecated_since {
+		writeln!(dest, "\t\t#[deprecated(note = \"deprecated since version {version} of {}\")]", iface.name)?;
+	}
+	Ok(())
+}
+
+/// Whether an argument of this type is passed to request validation hooks.
+///
+/// Validation runs before objects are looked up, so only plain values are validated. File descriptors are also
+/// excluded, since they're moved into the handler.
+fn is_validated(ty: ArgType<'_>) -> bool {
+	!matches!(ty, ArgType::Object { .. } | ArgType::NewId { .. } | ArgType::Fd)
+}
+
 /// Emit code to log a message in WAYLAND_DEBUG-compatible format.
 fn emit_log(dest: &mut impl Write, indent: &str, kind: &str, message: &Message) -> Result<()> {
 	writeln!(dest, "{indent}#[allow(unused_mut)]")?; // messages with no args
@@ -272,6 +612,7 @@ fn emit_log(dest: &mut impl Write, indent: &str, kind: &str, message: &Message)
 		message.name
 	)?;
 	for &Arg { name, ty, .. } in &message.args {
+		let name = RustIdent(name);
 		match ty {
 			ArgType::Uint | ArgType::Int | ArgType::Fixed | ArgType::String { nullable: false } => {
 				writeln!(dest, "{indent}\tlog.arg_debug({name});")?
@@ -309,6 +650,7 @@ fn emit_enum(dest: &mut impl Write, en: &Enum) -> Result<()> {
 	}
 	writeln!(dest, "\t#[repr(u32)]")?;
 	writeln!(dest, "\t#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]")?;
+	writeln!(dest, "\t#[cfg_attr(feature = \"serde\", derive(serde::Serialize, serde::Deserialize))]")?;
 	writeln!(dest, "\tpub enum {name} {{")?;
 	for ent in &en.entries {
 		if let Some(doc) = ent.summary {
@@ -329,7 +671,7 @@ fn emit_enum(dest: &mut impl Write, en: &Enum) -> Result<()> {
 	for ent in &en.entries {
 		writeln!(dest, "\t\t\t\t{} => Ok(Self::{}),", ent.value, RustName(ent.name))?;
 	}
-	writeln!(dest, "\t\t\t\t_ => Err(io::Error::new(ErrorKind::InvalidInput, \"invalid {name}\")),")?;
+	writeln!(dest, "\t\t\t\t_ => Err(ProtocolError::invalid_method(\"invalid {name}\").into()),")?;
 	writeln!(dest, "\t\t\t}}")?; // match
 	writeln!(dest, "\t\t}}")?; // fn
 	writeln!(dest, "\t}}")?; // trait impl
@@ -342,6 +684,12 @@ fn emit_enum(dest: &mut impl Write, en: &Enum) -> Result<()> {
 	writeln!(dest, "\t\t\t(*self as u32).encode(event);")?;
 	writeln!(dest, "\t\t}}")?;
 	writeln!(dest, "\t}}")?;
+
+	writeln!(dest, "\timpl From<{name}> for u32 {{")?;
+	writeln!(dest, "\t\tfn from(value: {name}) -> u32 {{")?;
+	writeln!(dest, "\t\t\tvalue as u32")?;
+	writeln!(dest, "\t\t}}")?;
+	writeln!(dest, "\t}}")?;
 	Ok(())
 }
 
@@ -366,6 +714,28 @@ fn write_multiline<'a>(dest: &mut impl Write, prefix: &str, parts: impl IntoIter
 	Ok(())
 }
 
+/// Format a Wayland name as a Rust identifier, escaping it if it is a keyword.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct RustIdent<'a>(&'a str);
+
+impl Display for RustIdent<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		#[rustfmt::skip]
+		const KEYWORDS: &[&str] = &[
+			"abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn", "else",
+			"enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "macro", "match",
+			"mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static", "struct", "trait", "true",
+			"try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+		];
+		match self.0 {
+			// these can't be raw identifiers
+			"crate" | "self" | "Self" | "super" => write!(f, "{}_", self.0),
+			name if KEYWORDS.contains(&name) => write!(f, "r#{name}"),
+			name => f.write_str(name),
+		}
+	}
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 struct RustName<'a>(&'a str);
 
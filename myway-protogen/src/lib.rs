@@ -1,8 +1,8 @@
 use roxmltree::Document;
 use std::{
-	fs::{self, File},
-	io::{BufWriter, Error, ErrorKind, Result, Write},
-	path::Path,
+	fs,
+	io::{Error, ErrorKind, Result, Write},
+	path::{Path, PathBuf},
 };
 
 macro_rules! bail {
@@ -21,19 +21,165 @@ macro_rules! ensure {
 
 mod build_tree;
 mod codegen;
+mod select;
 mod types;
+mod validate;
 
+/// Settings that affect the generated code itself.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct Options {
+	/// Emit a `#[cfg(test)]` module per interface checking that every request and event survives being encoded and
+	/// decoded again.
+	pub round_trip_tests: bool,
+	/// Store objects as trait objects rather than variants of a generated enum.
+	pub dyn_dispatch: bool,
+	/// Log every argument as it's encoded or decoded, at trace level.
+	pub arg_tracing: bool,
+}
+
+/// Generate Rust code for a set of protocols.
+///
+/// Add protocols with [`protocol`](Self::protocol), then narrow them down to what the compositor implements with
+/// [`skip`](Self::skip) and [`max_version`](Self::max_version).
+#[derive(Clone, Debug, Default)]
+pub struct Generator {
+	protocols: Vec<PathBuf>,
+	skip: Vec<String>,
+	max_versions: Vec<(String, u32)>,
+	options: Options,
+	file_per_protocol: bool,
+}
+
+impl Generator {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Generate code for the protocol defined in the XML file at `path`.
+	pub fn protocol(mut self, path: impl Into<PathBuf>) -> Self {
+		self.protocols.push(path.into());
+		self
+	}
+
+	/// Leave out `interface` entirely. Arguments referring to it from other interfaces become untyped objects.
+	pub fn skip(mut self, interface: impl Into<String>) -> Self {
+		self.skip.push(interface.into());
+		self
+	}
+
+	/// Generate `interface` as if it was defined at `version`, leaving out everything added later.
+	pub fn max_version(mut self, interface: impl Into<String>, version: u32) -> Self {
+		self.max_versions.push((interface.into(), version));
+		self
+	}
+
+	/// Emit a `#[cfg(test)]` module per interface checking that every request and event survives being encoded and
+	/// decoded again.
+	pub fn round_trip_tests(mut self, enable: bool) -> Self {
+		self.options.round_trip_tests = enable;
+		self
+	}
+
+	/// Dispatch requests through `Box<dyn ErasedObject>` trait objects instead of a generated `AnyObject` enum, so
+	/// object types can be added without extending the enum at the cost of a virtual call per request.
+	pub fn dyn_dispatch(mut self, enable: bool) -> Self {
+		self.options.dyn_dispatch = enable;
+		self
+	}
+
+	/// Emit a `trace!` line for every argument of every message as it's encoded or decoded.
+	///
+	/// This is useful when a message fails to decode, but formatting each argument is expensive enough to slow down
+	/// debug builds under load even with trace logging disabled, so it's off by default.
+	pub fn arg_tracing(mut self, enable: bool) -> Self {
+		self.options.arg_tracing = enable;
+		self
+	}
+
+	/// Write each protocol to its own file next to the output file, which `include!`s them. Only files whose contents
+	/// changed are rewritten.
+	pub fn file_per_protocol(mut self, enable: bool) -> Self {
+		self.file_per_protocol = enable;
+		self
+	}
+
+	/// Write the generated code to `code_path`.
+	pub fn generate(&self, code_path: impl AsRef<Path>) -> Result<()> {
+		let paths: Vec<&Path> = self.protocols.iter().map(PathBuf::as_path).collect();
+		let with_path = |path: &Path, err: Error| Error::new(err.kind(), format!("{}: {err}", path.display()));
+		let schemas = paths
+			.iter()
+			.map(|&path| fs::read_to_string(path).map_err(|err| with_path(path, err)))
+			.collect::<Result<Vec<_>>>()?;
+		let docs = paths
+			.iter()
+			.zip(&schemas)
+			.map(|(&path, schema)| {
+				Document::parse(schema).map_err(|err| with_path(path, Error::new(ErrorKind::InvalidData, err)))
+			})
+			.collect::<Result<Vec<_>>>()?;
+		let mut trees = paths
+			.iter()
+			.zip(&docs)
+			.map(|(&path, doc)| build_tree::build_protocol(doc).map_err(|err| with_path(path, err)))
+			.collect::<Result<Vec<_>>>()?;
+		let sources: Vec<_> = paths
+			.iter()
+			.zip(&docs)
+			.zip(&trees)
+			.map(|((&path, doc), protocol)| validate::Source { path, doc, protocol })
+			.collect();
+		validate::validate(&sources)?;
+		select::select(&mut trees, &self.skip, &self.max_versions)?;
+
+		let code_path = code_path.as_ref();
+		let mut output = Vec::new();
+		let mut prelude = Vec::new();
+		writeln!(prelude, "use crate::{{client::{{RecvMessage, SendHalf}}, object_map::{{Object, Objects}}}};")?;
+		writeln!(prelude, "use super::Id;")?;
+		output.extend(pretty_print(prelude));
+		for tree in &trees {
+			let mut code = Vec::new();
+			codegen::emit_protocol(tree, &mut code, self.options)?;
+			if self.file_per_protocol {
+				let stem = code_path.file_stem().unwrap_or_default().to_string_lossy();
+				let file_name = format!("{stem}-{}.rs", tree.name);
+				write_if_changed(&code_path.with_file_name(&file_name), &code)?;
+				// relative to the output file, since that's where the include is expanded
+				writeln!(output, "include!({file_name:?});")?;
+			} else {
+				output.extend(code);
+			}
+		}
+		let mut trailer = Vec::new();
+		let interfaces: Vec<_> = trees.iter().flat_map(|tree| &tree.interfaces).map(|iface| iface.name).collect();
+		codegen::emit_interface_list(&mut trailer, interfaces.iter().copied())?;
+		codegen::emit_anyobject(&mut trailer, &interfaces, self.options)?;
+		output.extend(pretty_print(trailer));
+		write_if_changed(code_path, &output)
+	}
+}
+
+/// Generate code for every protocol in `schema_paths`, with default settings.
 pub fn generate(schema_paths: &[impl AsRef<Path>], code_path: impl AsRef<Path>) -> Result<()> {
-	let mut output = BufWriter::new(File::create(code_path)?);
-	writeln!(output, "use crate::{{client::{{RecvMessage, SendHalf}}, object_map::{{Object, Objects}}}};")?;
-	writeln!(output, "use super::Id;")?;
-	for path in schema_paths {
-		let schema = fs::read_to_string(path)?;
-		let schema = Document::parse(&schema).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
-		let tree = build_tree::build_protocol(&schema)?;
-		codegen::emit_protocol(&tree, &mut output)?;
-	}
-	codegen::emit_anyobject(&mut output)?;
-	output.flush()?;
-	Ok(())
+	let generator = schema_paths.iter().fold(Generator::new(), |generator, path| generator.protocol(path.as_ref()));
+	generator.generate(code_path)
+}
+
+/// Format generated code like rustfmt would.
+///
+/// Code that doesn't parse is returned unchanged, so that rustc can point out what's wrong with it.
+pub(crate) fn pretty_print(code: Vec<u8>) -> Vec<u8> {
+	match std::str::from_utf8(&code).ok().and_then(|code| syn::parse_file(code).ok()) {
+		Some(file) => prettyplease::unparse(&file).into_bytes(),
+		None => code,
+	}
+}
+
+/// Write `contents` to `path`, unless it already holds exactly that.
+fn write_if_changed(path: &Path, contents: &[u8]) -> Result<()> {
+	match fs::read(path) {
+		Ok(old) if old == contents => Ok(()),
+		_ => fs::write(path, contents),
+	}
 }
@@ -0,0 +1,81 @@
+//! Narrowing parsed protocols down to the interfaces and versions a compositor implements.
+
+use crate::types::{ArgType, Interface, Protocol};
+use std::{io::Result, num::NonZeroU32};
+
+/// Remove interfaces named in `skip` and cap the versions of those in `max_versions`.
+///
+/// Object arguments referring to a skipped interface are kept, but become untyped. Every name must refer to an
+/// interface in `protocols`, to catch typos.
+pub(crate) fn select(protocols: &mut [Protocol<'_>], skip: &[String], max_versions: &[(String, u32)]) -> Result<()> {
+	let exists =
+		|name: &str| protocols.iter().flat_map(|protocol| &protocol.interfaces).any(|iface| iface.name == name);
+	for name in skip.iter().chain(max_versions.iter().map(|(name, _)| name)) {
+		ensure!(exists(name), "interface {name} is selected but not defined by any protocol");
+	}
+
+	let is_skipped = |name: &str| skip.iter().any(|skipped| skipped == name);
+	for protocol in &mut *protocols {
+		protocol.interfaces.retain(|iface| !is_skipped(iface.name));
+		for iface in &mut protocol.interfaces {
+			if let Some(&(_, max)) = max_versions.iter().rev().find(|(name, _)| name == iface.name) {
+				let max = NonZeroU32::new(max).ok_or_else(|| {
+					crate::Error::new(
+						crate::ErrorKind::InvalidInput,
+						format!("interface {} capped to version 0", iface.name),
+					)
+				})?;
+				cap_version(iface, max);
+			}
+		}
+	}
+
+	for protocol in &mut *protocols {
+		for iface in &mut protocol.interfaces {
+			let iface_name = iface.name;
+			for msg in iface.requests.iter_mut().chain(&mut iface.events) {
+				for arg in &mut msg.args {
+					match &mut arg.ty {
+						ArgType::Object { interface, .. } | ArgType::NewId { interface }
+							if interface.map_or(false, is_skipped) =>
+						{
+							*interface = None;
+						},
+						ArgType::Enum(name) => {
+							if let Some((target, _)) = name.split_once('.') {
+								ensure!(
+									!is_skipped(target),
+									"{iface_name}.{} uses enum {name} of skipped interface {target}",
+									msg.name
+								);
+							}
+						},
+						_ => (),
+					}
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Drop everything added to `iface` after version `max`.
+///
+/// Messages are ordered by version, so removing the newer ones leaves the opcodes of the rest unchanged.
+fn cap_version(iface: &mut Interface<'_>, max: NonZeroU32) {
+	if iface.version <= max {
+		return;
+	}
+	iface.version = max;
+	let is_supported = |since: Option<NonZeroU32>| since.map_or(true, |since| since <= max);
+	for messages in [&mut iface.requests, &mut iface.events] {
+		messages.retain(|msg| is_supported(msg.since));
+		for msg in messages {
+			msg.deprecated_since = msg.deprecated_since.filter(|&since| since <= max);
+		}
+	}
+	iface.enums.retain(|en| is_supported(en.since));
+	for en in &mut iface.enums {
+		en.entries.retain(|entry| is_supported(entry.since));
+	}
+}
@@ -11,6 +11,8 @@ pub struct Protocol<'doc> {
 
 #[derive(Clone, Debug)]
 pub struct Interface<'doc> {
+	/// Byte offset of the element in the XML source, for diagnostics
+	pub offset: usize,
 	pub name: &'doc str,
 	pub version: NonZeroU32,
 	pub desc: Option<Description<'doc>>,
@@ -21,15 +23,21 @@ pub struct Interface<'doc> {
 
 #[derive(Clone, Debug)]
 pub struct Message<'doc> {
+	/// Byte offset of the element in the XML source, for diagnostics
+	pub offset: usize,
 	pub name: &'doc str,
 	pub kind: Option<&'doc str>,
 	pub since: Option<NonZeroU32>,
+	/// Version from which clients should no longer use this message
+	pub deprecated_since: Option<NonZeroU32>,
 	pub desc: Option<Description<'doc>>,
 	pub args: Vec<Arg<'doc>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Arg<'doc> {
+	/// Byte offset of the element in the XML source, for diagnostics
+	pub offset: usize,
 	pub name: &'doc str,
 	pub ty: ArgType<'doc>,
 	pub summary: Option<&'doc str>,
@@ -50,8 +58,11 @@ pub enum ArgType<'doc> {
 
 #[derive(Clone, Debug)]
 pub struct Enum<'doc> {
+	/// Byte offset of the element in the XML source, for diagnostics
+	pub offset: usize,
 	pub name: &'doc str,
 	pub since: Option<NonZeroU32>,
+	#[allow(dead_code)]
 	pub bitfield: bool,
 	pub desc: Option<Description<'doc>>,
 	pub entries: Vec<Entry<'doc>>,
@@ -59,6 +70,8 @@ pub struct Enum<'doc> {
 
 #[derive(Clone, Debug)]
 pub struct Entry<'doc> {
+	/// Byte offset of the element in the XML source, for diagnostics
+	pub offset: usize,
 	pub name: &'doc str,
 	pub value: u32,
 	pub value_is_hex: bool,
@@ -0,0 +1,219 @@
+//! Semantic checks on parsed protocols.
+//!
+//! Building the tree only enforces the shape of the XML. This pass rejects protocols that are well-formed but would
+//! generate broken code or wire-incompatible bindings, reporting every problem found with its location.
+
+use crate::types::{ArgType, Interface, Message, Protocol};
+use roxmltree::Document;
+use std::{
+	collections::{HashMap, HashSet},
+	io::{Error, ErrorKind, Result},
+	num::NonZeroU32,
+	path::Path,
+};
+
+/// A protocol to validate, with the source it was parsed from.
+pub(crate) struct Source<'a, 'doc> {
+	pub path: &'a Path,
+	pub doc: &'a Document<'doc>,
+	pub protocol: &'a Protocol<'doc>,
+}
+
+pub(crate) fn validate(sources: &[Source<'_, '_>]) -> Result<()> {
+	let mut diag = Diagnostics::default();
+
+	let mut interfaces = HashMap::new();
+	for source in sources {
+		for iface in &source.protocol.interfaces {
+			if let Some(first) = interfaces.insert(iface.name, (source, iface)) {
+				let (first_source, first_iface) = first;
+				let first_at = location(first_source, first_iface.offset);
+				diag.error(
+					source,
+					iface.offset,
+					format!("duplicate interface {}, first defined at {first_at}", iface.name),
+				);
+			}
+		}
+	}
+
+	for source in sources {
+		for iface in &source.protocol.interfaces {
+			check_interface(&mut diag, source, iface, &interfaces);
+		}
+	}
+
+	diag.finish()
+}
+
+fn check_interface(
+	diag: &mut Diagnostics,
+	source: &Source<'_, '_>,
+	iface: &Interface<'_>,
+	interfaces: &HashMap<&str, (&Source<'_, '_>, &Interface<'_>)>,
+) {
+	let version = iface.version.get();
+	for (kind, messages) in [("request", &iface.requests), ("event", &iface.events)] {
+		check_unique(diag, source, iface.name, kind, messages.iter().map(|msg| (msg.name, msg.offset)));
+		ensure_fits_u16(diag, source, iface, kind, messages.len());
+		let mut prev_since = 1;
+		for msg in messages {
+			let since = msg.since.map_or(1, |since| since.get());
+			if since > version {
+				diag.error(
+					source,
+					msg.offset,
+					format!(
+						"{kind} {}.{} is since version {since}, but the interface is version {version}",
+						iface.name, msg.name
+					),
+				);
+			}
+			// opcodes are assigned in document order, so a message added later must not shift older ones
+			if since < prev_since {
+				diag.error(
+					source,
+					msg.offset,
+					format!(
+						"{kind} {}.{} (since version {since}) comes after a {kind} added in version {prev_since}, \
+						 which changes opcodes for older versions",
+						iface.name, msg.name
+					),
+				);
+			}
+			prev_since = prev_since.max(since);
+			if let Some(deprecated) = msg.deprecated_since.map(NonZeroU32::get) {
+				if deprecated <= since || deprecated > version {
+					diag.error(
+						source,
+						msg.offset,
+						format!(
+							"{kind} {}.{} is deprecated since version {deprecated}, which must be after its since \
+							 version {since} and at most the interface version {version}",
+							iface.name, msg.name
+						),
+					);
+				}
+			}
+			check_message(diag, source, iface, msg, interfaces);
+		}
+	}
+
+	check_unique(diag, source, iface.name, "enum", iface.enums.iter().map(|en| (en.name, en.offset)));
+	for en in &iface.enums {
+		let qualified = format!("{}.{}", iface.name, en.name);
+		check_unique(diag, source, &qualified, "entry", en.entries.iter().map(|entry| (entry.name, entry.offset)));
+		let mut values = HashMap::new();
+		for entry in &en.entries {
+			if let Some(first) = values.insert(entry.value, entry.name) {
+				diag.error(
+					source,
+					entry.offset,
+					format!(
+						"entries {qualified}.{first} and {qualified}.{} have the same value {}",
+						entry.name, entry.value
+					),
+				);
+			}
+			if let Some(since) = entry.since.or(en.since).filter(|since| since.get() > version) {
+				diag.error(
+					source,
+					entry.offset,
+					format!(
+						"entry {qualified}.{} is since version {since}, but the interface is version {version}",
+						entry.name
+					),
+				);
+			}
+		}
+	}
+}
+
+fn check_message(
+	diag: &mut Diagnostics,
+	source: &Source<'_, '_>,
+	iface: &Interface<'_>,
+	msg: &Message<'_>,
+	interfaces: &HashMap<&str, (&Source<'_, '_>, &Interface<'_>)>,
+) {
+	let qualified = format!("{}.{}", iface.name, msg.name);
+	check_unique(diag, source, &qualified, "argument", msg.args.iter().map(|arg| (arg.name, arg.offset)));
+	for arg in &msg.args {
+		match arg.ty {
+			ArgType::Object { interface: Some(target), .. } | ArgType::NewId { interface: Some(target) }
+				if !interfaces.contains_key(target) =>
+			{
+				diag.error(
+					source,
+					arg.offset,
+					format!("argument {qualified}({}) refers to unknown interface {target}", arg.name),
+				);
+			},
+			ArgType::Enum(name) => {
+				let (target_iface, enum_name) = name.split_once('.').unwrap_or((iface.name, name));
+				let found = interfaces
+					.get(target_iface)
+					.map_or(false, |(_, target)| target.enums.iter().any(|en| en.name == enum_name));
+				if !found {
+					diag.error(
+						source,
+						arg.offset,
+						format!("argument {qualified}({}) refers to unknown enum {name}", arg.name),
+					);
+				}
+			},
+			_ => (),
+		}
+	}
+}
+
+/// Report names that appear more than once among `items`, given as `(name, offset)`.
+fn check_unique<'a>(
+	diag: &mut Diagnostics,
+	source: &Source<'_, '_>,
+	parent: &str,
+	kind: &str,
+	items: impl IntoIterator<Item = (&'a str, usize)>,
+) {
+	let mut seen = HashSet::new();
+	for (name, offset) in items {
+		if !seen.insert(name) {
+			diag.error(source, offset, format!("duplicate {kind} {name} in {parent}"));
+		}
+	}
+}
+
+fn ensure_fits_u16(diag: &mut Diagnostics, source: &Source<'_, '_>, iface: &Interface<'_>, kind: &str, count: usize) {
+	if count > u16::MAX as usize + 1 {
+		diag.error(
+			source,
+			iface.offset,
+			format!("interface {} has {count} {kind}s, more than opcodes can address", iface.name),
+		);
+	}
+}
+
+/// `path:line:column` of a byte offset in a source.
+fn location(source: &Source<'_, '_>, offset: usize) -> String {
+	let pos = source.doc.text_pos_at(offset);
+	format!("{}:{}:{}", source.path.display(), pos.row, pos.col)
+}
+
+#[derive(Debug, Default)]
+struct Diagnostics {
+	errors: Vec<String>,
+}
+
+impl Diagnostics {
+	fn error(&mut self, source: &Source<'_, '_>, offset: usize, message: String) {
+		self.errors.push(format!("{}: {message}", location(source, offset)));
+	}
+
+	fn finish(self) -> Result<()> {
+		if self.errors.is_empty() {
+			Ok(())
+		} else {
+			Err(Error::new(ErrorKind::InvalidData, self.errors.join("\n")))
+		}
+	}
+}
@@ -0,0 +1,109 @@
+//! Narrowing protocols down with `skip` and `max_version`, checked on the code generated for a small protocol.
+
+use myway_protogen::Generator;
+use std::{fs, io::Result, path::PathBuf};
+
+/// An interface at version 3 whose messages were added over versions 1 to 3, and one it creates objects of.
+const PROTOCOL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<protocol name="select_test">
+  <interface name="test_base" version="3">
+    <request name="make_child">
+      <arg name="id" type="new_id" interface="test_child"/>
+    </request>
+    <request name="attach" since="2">
+      <arg name="child" type="object" interface="test_child"/>
+    </request>
+    <event name="late" since="3">
+      <arg name="mode" type="uint" enum="mode"/>
+    </event>
+    <enum name="mode">
+      <entry name="plain" value="0"/>
+      <entry name="fancy" value="1" since="3"/>
+    </enum>
+  </interface>
+  <interface name="test_child" version="1">
+    <request name="poke"/>
+  </interface>
+</protocol>
+"#;
+
+/// Generate code for `protocol` with the generator `configure` returns, in a directory of the test's own.
+fn generate(test: &str, protocol: &str, configure: impl FnOnce(Generator) -> Generator) -> Result<String> {
+	let dir: PathBuf = [env!("CARGO_TARGET_TMPDIR"), "select", test].iter().collect();
+	fs::create_dir_all(&dir)?;
+	let (xml, code) = (dir.join("protocol.xml"), dir.join("protocol.rs"));
+	fs::write(&xml, protocol)?;
+	configure(Generator::new().protocol(&xml)).generate(&code)?;
+	fs::read_to_string(code)
+}
+
+#[test]
+fn everything_by_default() {
+	let code = generate("default", PROTOCOL, |generator| generator).unwrap();
+	assert!(code.contains("pub mod test_child"));
+	assert!(code.contains(r#"interface: Some("test_child")"#));
+	for name in ["make_child", "attach", "late"] {
+		assert!(code.contains(&format!("{name:?}")), "{name} is missing");
+	}
+	assert!(code.contains("Fancy"));
+}
+
+#[test]
+fn skipped_interface_is_left_out() {
+	let code = generate("skip", PROTOCOL, |generator| generator.skip("test_child")).unwrap();
+	assert!(!code.contains("pub mod test_child"), "skipped interface was generated");
+	assert!(!code.contains("poke"), "request of skipped interface was generated");
+	// arguments referring to it are kept, but untyped
+	assert!(!code.contains(r#"interface: Some("test_child")"#));
+	assert!(code.contains("id: VacantEntry<'_, AnyObject>"));
+	assert!(code.contains("child: OccupiedEntry<'_, AnyObject>"));
+}
+
+#[test]
+fn version_cap_leaves_out_later_messages() {
+	let code = generate("cap-2", PROTOCOL, |generator| generator.max_version("test_base", 2)).unwrap();
+	assert!(code.contains("version: 2,"));
+	assert!(code.contains("\"attach\""), "message from the capped version was left out");
+	assert!(!code.contains("\"late\""), "message from after the capped version was generated");
+	assert!(!code.contains("Fancy"), "enum entry from after the capped version was generated");
+
+	let code = generate("cap-1", PROTOCOL, |generator| generator.max_version("test_base", 1)).unwrap();
+	assert!(code.contains("version: 1,"));
+	assert!(code.contains("\"make_child\""));
+	assert!(!code.contains("\"attach\""), "message from after the capped version was generated");
+
+	// capping above the defined version changes nothing
+	let code = generate("cap-9", PROTOCOL, |generator| generator.max_version("test_base", 9)).unwrap();
+	assert!(code.contains("version: 3,") && code.contains("\"late\""));
+}
+
+#[test]
+fn bad_selections_are_reported() {
+	let err = generate("typo", PROTOCOL, |generator| generator.skip("test_chlid")).unwrap_err();
+	assert_eq!(err.to_string(), "interface test_chlid is selected but not defined by any protocol");
+	let err = generate("zero", PROTOCOL, |generator| generator.max_version("test_base", 0)).unwrap_err();
+	assert_eq!(err.to_string(), "interface test_base capped to version 0");
+}
+
+#[test]
+fn enums_of_skipped_interfaces_are_reported() {
+	let protocol = PROTOCOL.replace(r#"enum="mode""#, r#"enum="test_child.kind""#).replace(
+		r#"<request name="poke"/>"#,
+		r#"<request name="poke"/><enum name="kind"><entry name="one" value="1"/></enum>"#,
+	);
+	let err = generate("skipped-enum", &protocol, |generator| generator.skip("test_child")).unwrap_err();
+	assert_eq!(err.to_string(), "test_base.late uses enum test_child.kind of skipped interface test_child");
+}
+
+#[test]
+fn deprecation_after_the_version_cap_is_dropped() {
+	let protocol = PROTOCOL.replace(r#"name="attach" since="2""#, r#"name="attach" since="2" deprecated-since="3""#);
+	let code = generate("deprecated", &protocol, |generator| generator).unwrap();
+	assert!(code.contains("deprecated since version 3 of test_base"));
+	assert!(code.contains("fn version(&self) -> u32;"), "deprecated request doesn't ask for the bound version");
+
+	let code = generate("deprecated-cap-2", &protocol, |generator| generator.max_version("test_base", 2)).unwrap();
+	assert!(code.contains("\"attach\""));
+	assert!(!code.contains("deprecated since version 3"), "deprecation from after the capped version was kept");
+	assert!(!code.contains("fn version(&self) -> u32;"));
+}
@@ -0,0 +1,54 @@
+//! Protocols the generator rejects, and names it has to escape to make valid Rust.
+
+use myway_protogen::Generator;
+use std::{fs, io::Result, path::PathBuf};
+
+/// Generate code for `protocol`, in a directory of the test's own, returning the code and the protocol's path.
+fn generate(test: &str, protocol: &str) -> (Result<String>, PathBuf) {
+	let dir: PathBuf = [env!("CARGO_TARGET_TMPDIR"), "validate", test].iter().collect();
+	fs::create_dir_all(&dir).unwrap();
+	let (xml, code) = (dir.join("protocol.xml"), dir.join("protocol.rs"));
+	fs::write(&xml, protocol).unwrap();
+	let result = Generator::new().protocol(&xml).generate(&code).and_then(|()| fs::read_to_string(code));
+	(result, xml)
+}
+
+#[test]
+fn bad_references_are_reported_with_their_location() {
+	let protocol = r#"<protocol name="validate_test">
+  <interface name="test_bad" version="1">
+    <request name="link">
+      <arg name="peer" type="object" interface="test_missing"/>
+      <arg name="mode" type="uint" enum="missing_mode"/>
+    </request>
+  </interface>
+</protocol>
+"#;
+	let (result, xml) = generate("bad", protocol);
+	let err = result.unwrap_err().to_string();
+	let path = xml.display();
+	assert_eq!(
+		err,
+		format!(
+			"{path}:4:7: argument test_bad.link(peer) refers to unknown interface test_missing\n{path}:5:7: argument \
+			 test_bad.link(mode) refers to unknown enum missing_mode"
+		)
+	);
+}
+
+#[test]
+fn keyword_names_are_escaped() {
+	let protocol = r#"<protocol name="validate_test">
+  <interface name="test_keywords" version="1">
+    <request name="set">
+      <arg name="type" type="uint"/>
+      <arg name="self" type="int"/>
+    </request>
+  </interface>
+</protocol>
+"#;
+	let code = generate("keywords", protocol).0.unwrap();
+	assert!(code.contains("r#type: u32"), "keyword argument wasn't made a raw identifier");
+	assert!(code.contains("self_: i32"), "self argument wasn't renamed");
+	assert!(!code.contains(" type: u32") && !code.contains("(self: i32"));
+}
@@ -1,33 +1,106 @@
 use crate::cvt_poll;
 use log::{debug, trace, warn};
+use nix::{
+	errno::Errno,
+	fcntl::{flock, FlockArg},
+	unistd::geteuid,
+};
 use std::{
-	fs,
-	io::Result,
+	fs::{self, File, OpenOptions, Permissions},
+	io::{Error, ErrorKind, Result},
 	os::unix::{
-		io::{AsRawFd, RawFd},
+		fs::{MetadataExt, OpenOptionsExt, PermissionsExt},
+		io::{AsRawFd, FromRawFd, RawFd},
 		net::{UnixListener, UnixStream},
 	},
-	path::Path,
+	path::{Path, PathBuf},
 	task::{ready, Poll},
 };
 
+/// Number of `wayland-N` displays tried when picking a free one, as in libwayland.
+const MAX_DISPLAYS: u32 = 32;
+
+/// Mode of sockets the compositor binds: connecting takes write permission, which only the user gets.
+const SOCKET_MODE: u32 = 0o600;
+
 /// Unix domain socket listener that accepts connections on the wayland socket.
 ///
 /// Register with an [`Epoll`](crate::epoll::Epoll) before use.
 #[derive(Debug)]
 pub struct Accept {
 	listener: UnixListener,
+	/// Lock file held for as long as the socket is bound, and its path. `None` if the socket was bound by someone
+	/// else, who is then also responsible for deleting it.
+	lock: Option<(File, PathBuf)>,
 }
 
 impl Accept {
 	/// Create a new acceptor listening on the given socket path.
 	///
+	/// The socket is guarded by a lock file next to it, named like the socket with a `.lock` suffix. If another
+	/// compositor holds the lock, this fails with [`ErrorKind::AddrInUse`]. Otherwise, a socket already at `path` was
+	/// left behind by a compositor that exited without cleaning up, and is replaced.
+	///
+	/// The directory containing `path` must be owned by the user with mode 0700, as for [`bind_free`](Self::bind_free).
+	///
 	/// Before using, register with an [`Epoll`](crate::epoll::Epoll) with interest `EPOLLIN`.
 	pub fn bind(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		// a bare file name is relative to the current directory
+		let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+		check_runtime_dir(dir)?;
+		let lock = lock(path)?;
+		if fs::symlink_metadata(path).is_ok() {
+			debug!("removing stale socket at {path:?}");
+			fs::remove_file(path)?;
+		}
 		let lst = UnixListener::bind(path)?;
+		// the mode bind leaves depends on the umask we were started with
+		fs::set_permissions(path, Permissions::from_mode(SOCKET_MODE))?;
 		lst.set_nonblocking(true)?;
 		trace!("created listener {lst:?}");
-		Ok(Self { listener: lst })
+		Ok(Self { listener: lst, lock: Some(lock) })
+	}
+
+	/// Create a new acceptor from a socket that is already bound and listening, such as one passed in by a test
+	/// harness.
+	///
+	/// # Safety
+	///
+	/// `fd` must be an open Unix stream socket that nothing else owns.
+	pub unsafe fn from_raw_fd(fd: RawFd) -> Result<Self> {
+		let lst = UnixListener::from_raw_fd(fd);
+		lst.set_nonblocking(true)?;
+		trace!("adopted listener {lst:?}");
+		Ok(Self { listener: lst, lock: None })
+	}
+
+	/// Create a new acceptor listening on the first free `wayland-N` socket in `dir`.
+	///
+	/// Returns the acceptor along with the name of the display, for clients to find it through `WAYLAND_DISPLAY`.
+	///
+	/// `dir` is meant to be `XDG_RUNTIME_DIR`, so it must be a directory owned by the user with mode 0700. Anyone else
+	/// able to write there could replace the socket and intercept clients.
+	pub fn bind_free(dir: impl AsRef<Path>) -> Result<(Self, String)> {
+		let dir = dir.as_ref();
+		// an insecure directory fails the first bind, rather than being reported as every display being taken
+		for n in 0..MAX_DISPLAYS {
+			let name = format!("wayland-{n}");
+			match Self::bind(dir.join(&name)) {
+				Ok(accept) => return Ok((accept, name)),
+				Err(err) if err.kind() == ErrorKind::AddrInUse => debug!("display {name} is taken: {err}"),
+				Err(err) => return Err(err),
+			}
+		}
+		let message =
+			format!("no free display in {}: wayland-0 to wayland-{} are taken", dir.display(), MAX_DISPLAYS - 1);
+		Err(Error::new(ErrorKind::AddrInUse, message))
+	}
+
+	/// Path of the socket, if it has one.
+	pub fn path(&self) -> Option<PathBuf> {
+		let addr = self.listener.local_addr().ok()?;
+		addr.as_pathname().map(Path::to_owned)
 	}
 
 	/// Accept a waiting connection, if any.
@@ -50,6 +123,10 @@ impl AsRawFd for Accept {
 
 impl Drop for Accept {
 	fn drop(&mut self) {
+		let (_, lock_path) = match &self.lock {
+			Some(lock) => lock,
+			None => return,
+		};
 		match self.listener.local_addr() {
 			Ok(addr) => match addr.as_pathname() {
 				Some(path) => match fs::remove_file(path) {
@@ -60,5 +137,42 @@ impl Drop for Accept {
 			},
 			Err(err) => warn!("deleting server socket failed: local_addr failed: {err:?}"),
 		}
+		// the socket must go first, or another compositor could take the lock and then have its socket deleted
+		if let Err(err) = fs::remove_file(lock_path) {
+			warn!("deleting lock file failed: {err:?}");
+		}
+	}
+}
+
+/// Refuse to put sockets in `dir` unless only the user can access it.
+fn check_runtime_dir(dir: &Path) -> Result<()> {
+	let meta = fs::metadata(dir).map_err(|err| Error::new(err.kind(), format!("{}: {err}", dir.display())))?;
+	let insecure =
+		|problem: String| Err(Error::new(ErrorKind::PermissionDenied, format!("{}: {problem}", dir.display())));
+	if !meta.is_dir() {
+		return insecure(String::from("not a directory"));
+	}
+	if meta.uid() != geteuid().as_raw() {
+		return insecure(format!("owned by uid {}, not the current user ({})", meta.uid(), geteuid()));
+	}
+	let mode = meta.mode() & 0o7777;
+	if mode != 0o700 {
+		return insecure(format!("has mode {mode:o}, but must only be accessible by its owner (700)"));
+	}
+	Ok(())
+}
+
+/// Take the lock file for the socket at `socket`, without waiting for another process to release it.
+fn lock(socket: &Path) -> Result<(File, PathBuf)> {
+	let mut path = socket.as_os_str().to_owned();
+	path.push(".lock");
+	let path = PathBuf::from(path);
+	let file = OpenOptions::new().write(true).create(true).truncate(false).mode(0o660).open(&path)?;
+	match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+		Ok(()) => Ok((file, path)),
+		Err(Errno::EWOULDBLOCK) => {
+			Err(Error::new(ErrorKind::AddrInUse, format!("{} is held by another compositor", path.display())))
+		},
+		Err(err) => Err(err.into()),
 	}
 }
@@ -0,0 +1,89 @@
+//! Activation tokens, with which a client passes focus on to another: one it launched, or one the compositor launched
+//! through the [supervisor](crate::supervisor).
+//!
+//! A token activates a surface once, and only within [`LIFETIME`] of being issued. Tokens that weren't issued, because
+//! whoever asked for one wasn't allowed to pass focus on, look the same as valid ones but never activate anything.
+
+use crate::clock;
+use log::debug;
+use std::{
+	cell::{Cell, RefCell},
+	collections::hash_map::RandomState,
+	hash::{BuildHasher, Hasher},
+	time::Duration,
+};
+
+/// How long a token can be used for after it's issued.
+pub const LIFETIME: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct Issued {
+	token: String,
+	/// App ID of the client to be activated, if the one asking for the token said, for the logs
+	app_id: Option<Box<str>>,
+	/// Frame time, since the clock's starting point, after which the token can no longer be used
+	expires: Duration,
+}
+
+thread_local! {
+	/// Tokens issued and not used yet, oldest first. They don't refer to the client that asked for them, so there is
+	/// nothing to clean up when it disconnects, and each is added or removed in one step that a panic can't leave
+	/// half done. Unused ones expire after [`LIFETIME`].
+	static ISSUED: RefCell<Vec<Issued>> = RefCell::default();
+	/// Tokens generated so far, to tell them apart
+	static GENERATED: Cell<u64> = Cell::default();
+}
+
+/// Issue a token for activating a surface of the client with `app_id`, if known.
+pub fn issue(app_id: Option<&str>) -> String {
+	let token = generate();
+	let expires = clock::frame_time().since_start() + LIFETIME;
+	debug!("issued activation token {token} for {}", app_id.unwrap_or("any client"));
+	ISSUED.with(|issued| {
+		let mut issued = issued.borrow_mut();
+		prune(&mut issued);
+		issued.push(Issued { token: token.clone(), app_id: app_id.map(Into::into), expires });
+	});
+	token
+}
+
+/// A token that can't activate anything, for requests the compositor refuses without telling the client.
+pub fn refuse() -> String {
+	generate()
+}
+
+/// Use up `token`, returning whether it was issued and hasn't been used or expired yet.
+pub fn redeem(token: &str) -> bool {
+	ISSUED.with(|issued| {
+		let mut issued = issued.borrow_mut();
+		prune(&mut issued);
+		match issued.iter().position(|issued| issued.token == token) {
+			Some(index) => {
+				let issued = issued.remove(index);
+				debug!("redeemed activation token {token} for {}", issued.app_id.as_deref().unwrap_or("any client"));
+				true
+			},
+			None => false,
+		}
+	})
+}
+
+/// Forget tokens that expired.
+fn prune(issued: &mut Vec<Issued>) {
+	let now = clock::frame_time().since_start();
+	issued.retain(|issued| issued.expires >= now);
+}
+
+/// A new token, which clients can't guess from the ones they were given before. It's hashed with keys random to each
+/// run, which keeps tokens from being predicted, but is no secret a client could keep from another.
+fn generate() -> String {
+	let count = GENERATED.with(|generated| generated.replace(generated.get() + 1));
+	let keys = RandomState::new();
+	let [high, low] = [0, 1].map(|half| {
+		let mut hasher = keys.build_hasher();
+		hasher.write_u64(count);
+		hasher.write_u8(half);
+		hasher.finish()
+	});
+	format!("{high:016x}{low:016x}")
+}
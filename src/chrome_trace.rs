@@ -0,0 +1,140 @@
+//! Recording spans in the Chrome trace event format, for finding where latency comes from.
+//!
+//! The output opens in Perfetto or `chrome://tracing`. Each client message shows up as nested `recv`, `dispatch`,
+//! `decode`, `handle` and `encode` spans, with `flush` whenever buffered events are written to a socket.
+
+use std::{
+	fmt::{self, Write as _},
+	fs::File,
+	io::{self, BufWriter, Write},
+	mem,
+	path::Path,
+	process,
+	sync::Mutex,
+	time::Instant,
+};
+use tracing::{
+	field::{Field, Visit},
+	span, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// A [`Layer`] writing a begin event whenever a span is entered, and an end event whenever it's exited.
+///
+/// The file is a JSON array, which is only closed once the layer is dropped. Trace viewers accept it unclosed too, so
+/// a trace is still usable if the compositor crashes.
+pub struct ChromeLayer {
+	start: Instant,
+	out: Mutex<Output>,
+}
+
+struct Output {
+	file: BufWriter<File>,
+	/// Whether no event has been written yet, so the next one needs no separator
+	first: bool,
+}
+
+impl ChromeLayer {
+	/// Create a layer writing to a new file at `path`.
+	pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+		let mut file = BufWriter::new(File::create(path)?);
+		file.write_all(b"[")?;
+		Ok(Self { start: Instant::now(), out: Mutex::new(Output { file, first: true }) })
+	}
+
+	fn event(&self, phase: char, name: &str, args: Option<&Args>) {
+		let ts = self.start.elapsed().as_secs_f64() * 1e6;
+		let mut out = self.out.lock().unwrap_or_else(|err| err.into_inner());
+		let sep = if mem::take(&mut out.first) { "\n" } else { ",\n" };
+		let mut event = format!("{sep}{{\"name\":");
+		json_string(&mut event, name);
+		let _ = write!(event, ",\"ph\":\"{phase}\",\"ts\":{ts:.3},\"pid\":{},\"tid\":1", process::id());
+		if let Some(args) = args.filter(|args| !args.0.is_empty()) {
+			let _ = write!(event, ",\"args\":{{{}}}", args.0);
+		}
+		event.push('}');
+		// a trace is best effort, so a full disk shouldn't take the compositor down
+		let _ = out.file.write_all(event.as_bytes());
+	}
+}
+
+impl Drop for ChromeLayer {
+	fn drop(&mut self) {
+		let out = self.out.get_mut().unwrap_or_else(|err| err.into_inner());
+		let _ = out.file.write_all(b"\n]\n");
+		let _ = out.file.flush();
+	}
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for ChromeLayer {
+	fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+		let mut args = Args(String::new());
+		attrs.record(&mut args);
+		if let Some(span) = ctx.span(id) {
+			span.extensions_mut().insert(args);
+		}
+	}
+
+	fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+		if let Some(span) = ctx.span(id) {
+			self.event('B', span.name(), span.extensions().get::<Args>());
+		}
+	}
+
+	fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+		if let Some(span) = ctx.span(id) {
+			self.event('E', span.name(), None);
+		}
+	}
+}
+
+/// Fields of a span, as the members of a JSON object.
+struct Args(String);
+
+impl Args {
+	fn key(&mut self, field: &Field) {
+		if !self.0.is_empty() {
+			self.0.push(',');
+		}
+		json_string(&mut self.0, field.name());
+		self.0.push(':');
+	}
+}
+
+impl Visit for Args {
+	fn record_u64(&mut self, field: &Field, value: u64) {
+		self.key(field);
+		let _ = write!(self.0, "{value}");
+	}
+
+	fn record_i64(&mut self, field: &Field, value: i64) {
+		self.key(field);
+		let _ = write!(self.0, "{value}");
+	}
+
+	fn record_str(&mut self, field: &Field, value: &str) {
+		self.key(field);
+		json_string(&mut self.0, value);
+	}
+
+	fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+		self.key(field);
+		json_string(&mut self.0, &format!("{value:?}"));
+	}
+}
+
+/// Append `s` to `dest` as a quoted JSON string.
+fn json_string(dest: &mut String, s: &str) {
+	dest.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => dest.push_str("\\\""),
+			'\\' => dest.push_str("\\\\"),
+			c if c.is_control() => {
+				let _ = write!(dest, "\\u{:04x}", c as u32);
+			},
+			c => dest.push(c),
+		}
+	}
+	dest.push('"');
+}
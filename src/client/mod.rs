@@ -1,21 +1,37 @@
 use crate::{
-	object_impls::Display,
+	error,
+	logging::ClientContext,
+	object_impls::{self, shm, Display},
 	object_map::Objects,
-	protocol::{Id, Word, WORD_SIZE},
+	protocol::{AnyObject, Id, Word, WORD_SIZE},
+	state_dump::History,
+};
+use nix::{
+	cmsg_space,
+	sys::socket::{getsockopt, sockopt::PeerCredentials},
 };
-use nix::cmsg_space;
 use std::{
+	cell::RefCell,
+	collections::{HashSet, VecDeque},
 	fmt, mem,
-	os::unix::{io::RawFd, net::UnixStream},
+	os::unix::{
+		io::{AsRawFd, RawFd},
+		net::UnixStream,
+	},
+	rc::Rc,
+	sync::atomic::{AtomicU64, Ordering},
+	task::Poll,
 };
 
 pub use self::{
-	recv::{RecvHalf, RecvMessage},
+	recv::RecvMessage,
 	send::{SendHalf, SendMessage},
 };
 
 mod recv;
 mod send;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 /// Capacity of the buffer on each half of the socket, in bytes.
 const CAP_BYTES: usize = 4096;
@@ -41,8 +57,102 @@ fn div_exact(n: usize, what: &'static str) -> usize {
 	n / WORD_SIZE
 }
 
+/// Identifies a connected client.
+///
+/// Keys are reused once a client disconnects, so this also holds a serial number unique to each connection. An ID never
+/// refers to a client that connected after the one it was created for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ClientId {
+	key: usize,
+	serial: u64,
+}
+
+impl ClientId {
+	/// Key of the client in the main loop's client list, and its epoll userdata.
+	pub fn key(self) -> usize {
+		self.key
+	}
+}
+
+/// Events waiting to be sent to a client, as functions encoding them. See [`ObjectRef::queue`].
+///
+/// [`ObjectRef::queue`]: crate::object_map::ObjectRef::queue
+#[derive(Default)]
+pub struct EventQueue(RefCell<VecDeque<QueuedEvent>>);
+
+/// An event in an [`EventQueue`].
+pub type QueuedEvent = Box<dyn FnOnce(&mut Objects, &mut SendHalf<'_>) -> error::Result<()>>;
+
+impl EventQueue {
+	pub fn push(&self, event: QueuedEvent) {
+		self.0.borrow_mut().push_back(event);
+	}
+
+	fn pop(&self) -> Option<QueuedEvent> {
+		self.0.borrow_mut().pop_front()
+	}
+
+	fn is_empty(&self) -> bool {
+		self.0.borrow().is_empty()
+	}
+
+	fn len(&self) -> usize {
+		self.0.borrow().len()
+	}
+}
+
+impl fmt::Debug for EventQueue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("EventQueue").field("len", &self.0.borrow().len()).finish()
+	}
+}
+
+/// Memory a client holds in the compositor, see [`Client::usage`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Usage {
+	/// Objects in the client's object map
+	pub objects: usize,
+	/// Bytes of shared memory pools mapped for the client's pools and buffers
+	pub shm_bytes: usize,
+	/// Bytes of events encoded but not yet written to the socket
+	pub unsent_bytes: usize,
+	/// Events queued from outside request dispatch, not yet encoded
+	pub queued_events: usize,
+}
+
+impl fmt::Display for Usage {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let Self { objects, shm_bytes, unsent_bytes, queued_events } = self;
+		write!(
+			f,
+			"{objects} objects, {shm_bytes} bytes of shared memory, {unsent_bytes} bytes of unsent events, \
+			 {queued_events} queued events"
+		)
+	}
+}
+
+/// An object whose shared state is still referenced after its client disconnected, see [`Client::close`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Leak {
+	pub interface: &'static str,
+	pub id: Id<AnyObject>,
+	/// Number of references left
+	pub refs: usize,
+}
+
+impl fmt::Display for Leak {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let Self { interface, id, refs } = self;
+		write!(f, "{interface}@{id} is still referenced {refs} times")
+	}
+}
+
+/// Serial number of the next client to connect.
+static NEXT_SERIAL: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug)]
 pub struct Client {
+	id: ClientId,
 	/// Socket used to communicate with the client
 	sock: UnixStream,
 	/// Outgoing message bytes
@@ -57,14 +167,25 @@ pub struct Client {
 	rx_cmsg: Vec<u8>,
 	/// Objects allocated to this client
 	objects: Objects,
+	/// Events queued from outside request dispatch, to be encoded into `tx_bytes` when there's room
+	events: Rc<EventQueue>,
+	/// Recently exchanged messages, for state dumps
+	history: History,
+	/// Deprecated requests the client sent, as `(interface, opcode)`, which are only warned about the first time
+	deprecated: HashSet<(&'static str, u16)>,
+	/// Prefix for messages logged while serving this client
+	log_context: Rc<ClientContext>,
 }
 
 impl Client {
-	/// Create client state wrapping the peer connected to the provided socket.
-	pub fn new(sock: UnixStream) -> Self {
+	/// Create client state wrapping the peer connected to the provided socket, stored at `key` by the main loop.
+	pub fn new(sock: UnixStream, key: usize) -> Self {
 		let mut objects = Objects::new();
 		objects.insert(Id::<Display>::new(1).unwrap(), Display).unwrap();
+		let id = ClientId { key, serial: NEXT_SERIAL.fetch_add(1, Ordering::Relaxed) };
+		let pid = getsockopt(sock.as_raw_fd(), PeerCredentials).ok().map(|cred| cred.pid());
 		Self {
+			id,
 			sock,
 			tx_bytes: Buffer::new(),
 			tx_fds: FdBuffer::new(),
@@ -72,6 +193,88 @@ impl Client {
 			rx_fds: FdBuffer::new(),
 			rx_cmsg: cmsg_space!([RawFd; CAP_FDS]),
 			objects,
+			events: Rc::default(),
+			history: History::default(),
+			deprecated: HashSet::new(),
+			log_context: ClientContext::new(id, pid),
+		}
+	}
+
+	pub fn id(&self) -> ClientId {
+		self.id
+	}
+
+	/// Who this client is, to [enter](ClientContext::enter) while serving it.
+	pub fn log_context(&self) -> &Rc<ClientContext> {
+		&self.log_context
+	}
+
+	pub fn objects(&self) -> &Objects {
+		&self.objects
+	}
+
+	pub fn history(&self) -> &History {
+		&self.history
+	}
+
+	/// How much memory this client holds in the compositor.
+	pub fn usage(&self) -> Usage {
+		Usage {
+			objects: self.objects.iter().count(),
+			shm_bytes: shm::mapped_bytes(self.objects.iter().map(|(_, object)| object)),
+			unsent_bytes: self.tx_bytes.write_idx - self.tx_bytes.read_idx,
+			queued_events: self.events.len(),
+		}
+	}
+
+	/// Drop this client, returning the objects whose state is still referenced from elsewhere in the compositor once
+	/// everything the client owned is gone.
+	///
+	/// Anything returned is a leak: state the compositor keeps beyond a client's objects must only be referenced
+	/// weakly.
+	pub fn close(self) -> Vec<Leak> {
+		let objects = self.objects.iter();
+		let shared: Vec<_> = objects
+			.filter_map(|(id, object)| Some((object.interface(), id, object_impls::shared_state(object)?)))
+			.collect();
+		drop(self);
+		let leaks = shared.into_iter().map(|(interface, id, state)| Leak { interface, id, refs: state.strong_count() });
+		leaks.filter(|leak| leak.refs > 0).collect()
+	}
+
+	/// Whether any events are queued, waiting for [`send_queued`](Self::send_queued).
+	pub fn has_queued(&self) -> bool {
+		!self.events.is_empty()
+	}
+
+	/// Encode queued events into the outgoing buffer and flush it, until either the queue is empty or the socket stops
+	/// accepting data. The remaining events are left queued for when the socket is writable again.
+	///
+	/// This is also when the client is told the IDs of destroyed objects are free, see [`Objects::release_deleted`].
+	pub fn send_queued(&mut self) -> error::Result<()> {
+		let events = Rc::clone(&self.events);
+		let (mut send, _, objects) = self.split_mut();
+		// objects destroyed by requests handled since the last call
+		objects.release_deleted(&mut send)?;
+		loop {
+			if send.is_congested() {
+				if let Poll::Ready(Err(err)) = send.poll_flush() {
+					return Err(err.into());
+				}
+				if send.is_congested() {
+					return Ok(());
+				}
+			}
+			match events.pop() {
+				Some(event) => event(objects, &mut send)?,
+				None => break,
+			}
+		}
+		// and by the events just sent
+		objects.release_deleted(&mut send)?;
+		match send.poll_flush() {
+			Poll::Ready(Err(err)) => Err(err.into()),
+			_ => Ok(()),
 		}
 	}
 
@@ -80,7 +283,7 @@ impl Client {
 	/// The three returned values are:
 	///
 	/// - [`SendHalf`], for sending events to the connected client
-	/// - [`RecvHalf`], for polling requests from the client
+	/// - [`RecvHalf`](recv::RecvHalf), for polling requests from the client
 	/// - [`Objects`], tracking object IDs allocated to this client
 	///
 	/// Splitting with this method allows minimizing copies of protocol data: requests are read into the receiver's
@@ -88,7 +291,15 @@ impl Client {
 	/// in the sender's buffers.
 	pub fn split_mut(&mut self) -> (send::SendHalf<'_>, recv::RecvHalf<'_>, &mut Objects) {
 		(
-			send::SendHalf { sock: &self.sock, bytes: &mut self.tx_bytes, fds: &mut self.tx_fds },
+			send::SendHalf {
+				client: self.id,
+				sock: &self.sock,
+				bytes: &mut self.tx_bytes,
+				fds: &mut self.tx_fds,
+				events: &self.events,
+				history: &mut self.history,
+				deprecated: &mut self.deprecated,
+			},
 			recv::RecvHalf {
 				sock: &self.sock,
 				bytes: &mut self.rx_bytes,
@@ -144,6 +355,29 @@ impl Buffer {
 	}
 }
 
+impl Buffer {
+	/// With the `buffer-checks` feature, assert that the indices are in order and within capacity, and that the
+	/// `aligned` one is on a word boundary.
+	#[track_caller]
+	fn check(&self, aligned: Aligned) {
+		if cfg!(feature = "buffer-checks") {
+			assert!(self.read_idx <= self.write_idx && self.write_idx <= CAP_BYTES, "inconsistent {self:?}");
+			match aligned {
+				Aligned::Read => div_exact(self.read_idx, "read_idx"),
+				Aligned::Write => div_exact(self.write_idx, "write_idx"),
+			};
+		}
+	}
+}
+
+/// Which index of a [`Buffer`] is always on a word boundary. Outgoing messages are written whole but may be sent in
+/// parts, and incoming ones are received in parts but consumed whole.
+#[derive(Copy, Clone, Debug)]
+enum Aligned {
+	Read,
+	Write,
+}
+
 impl fmt::Debug for Buffer {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		f.debug_struct("Buffer")
@@ -167,6 +401,16 @@ impl FdBuffer {
 	}
 }
 
+impl FdBuffer {
+	/// With the `buffer-checks` feature, assert that the indices are in order and within capacity.
+	#[track_caller]
+	fn check(&self) {
+		if cfg!(feature = "buffer-checks") {
+			assert!(self.read_idx <= self.write_idx && self.write_idx <= CAP_FDS, "inconsistent {self:?}");
+		}
+	}
+}
+
 impl fmt::Debug for FdBuffer {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		f.debug_struct("FdBuffer")
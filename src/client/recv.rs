@@ -1,13 +1,15 @@
-use super::{Buffer, FdBuffer, CAP_BYTES, CAP_FDS};
+use super::{Aligned, Buffer, FdBuffer, CAP_BYTES, CAP_FDS};
 use crate::{
 	cvt_poll,
+	error::ProtocolError,
 	protocol::{AnyObject, Id, Word, WORD_SIZE},
 };
+use log::trace;
 use nix::sys::socket::{recvmsg, ControlMessageOwned, MsgFlags};
 use std::{
 	io::{Error, ErrorKind, IoSliceMut, Result},
 	os::unix::{
-		io::{FromRawFd, OwnedFd},
+		io::{FromRawFd, OwnedFd, RawFd},
 		net::UnixStream,
 		prelude::AsRawFd,
 	},
@@ -24,6 +26,7 @@ pub struct RecvHalf<'c> {
 
 impl<'c> RecvHalf<'c> {
 	pub fn poll_recv(&mut self) -> Poll<Result<RecvMessage<'_>>> {
+		let _span = tracing::trace_span!("recv").entered();
 		let byte_len = match ready!(fill_words(self.sock, self.bytes, self.fds, self.cmsg_buf, 2, false))? {
 			&[_obj, len_op] => len_op as usize >> 16,
 			_ => unreachable!(),
@@ -47,7 +50,15 @@ impl<'c> RecvHalf<'c> {
 			};
 		let object_id =
 			Id::new(object_id).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "message target cannot be null"))?;
-		Poll::Ready(Ok(RecvMessage { object_id, opcode, bytes: args, fds: self.fds }))
+		Poll::Ready(Ok(RecvMessage::new(object_id, opcode, args, self.fds)))
+	}
+
+	/// Whether part of a message has been received, but not all of it.
+	///
+	/// When [`poll_recv`](Self::poll_recv) fails with [`ErrorKind::UnexpectedEof`], this tells a client that
+	/// disconnected between messages from one that hung up in the middle of sending one.
+	pub fn has_partial_message(&self) -> bool {
+		self.bytes.write_idx > self.bytes.read_idx
 	}
 }
 
@@ -64,7 +75,20 @@ fn fill_words<'b>(
 	consume: bool,
 ) -> Poll<Result<&'b [Word]>> {
 	let byte_len = word_len * WORD_SIZE;
-	assert!(byte_len < CAP_BYTES, "cannot read {byte_len} bytes into a buffer of {CAP_BYTES} bytes");
+	if byte_len > CAP_BYTES {
+		return Poll::Ready(Err(Error::new(
+			ErrorKind::InvalidInput,
+			format!("message of {byte_len} bytes does not fit in the receive buffer of {CAP_BYTES} bytes"),
+		)));
+	}
+	if CAP_BYTES - buf.read_idx < byte_len {
+		// move the unread bytes, including a partially received word at the end, to the front of the buffer
+		let (start, end) = (buf.read_idx, buf.write_idx);
+		Buffer::bytes_mut(&mut buf.buf).copy_within(start..end, 0);
+		buf.read_idx = 0;
+		buf.write_idx = end - start;
+		trace!("copied bytes {start}..{end} to {}..{}", buf.read_idx, buf.write_idx);
+	}
 	let bytes = Buffer::bytes_mut(&mut buf.buf);
 	while buf.write_idx - buf.read_idx < byte_len {
 		let space = &mut bytes[buf.write_idx..];
@@ -75,18 +99,17 @@ fn fill_words<'b>(
 			Some(cmsg_buf),
 			MsgFlags::MSG_CMSG_CLOEXEC
 		)))?;
-		if msg.flags.contains(MsgFlags::MSG_CTRUNC) {
-			todo!("shut down connection, file descriptor discarded");
-		}
 		for ctl in msg.cmsgs() {
 			if let ControlMessageOwned::ScmRights(msg_fds) = ctl {
-				let n = Ord::min(msg_fds.len(), CAP_FDS - fds.write_idx);
-				fds.buf[fds.write_idx..fds.write_idx + n].copy_from_slice(&msg_fds[..n]);
-				if n < msg_fds.len() {
-					todo!("too many file descriptors");
-				}
+				receive_fds(fds, &msg_fds)?;
 			}
 		}
+		if msg.flags.contains(MsgFlags::MSG_CTRUNC) {
+			return Poll::Ready(Err(Error::new(
+				ErrorKind::InvalidInput,
+				format!("more than {CAP_FDS} file descriptors sent at once, some were discarded"),
+			)));
+		}
 
 		if msg.bytes == 0 {
 			return Poll::Ready(Err(ErrorKind::UnexpectedEof.into()));
@@ -99,9 +122,38 @@ fn fill_words<'b>(
 	if consume {
 		buf.read_idx += word_len * WORD_SIZE;
 	}
+	buf.check(Aligned::Read);
 	Poll::Ready(Ok(&buf.buf[start..start + word_len]))
 }
 
+/// Store file descriptors received alongside a message in `fds`, making room by moving the ones not yet taken to the
+/// front.
+///
+/// If they still don't fit, the ones that don't are closed and this fails.
+fn receive_fds(fds: &mut FdBuffer, received: &[RawFd]) -> Result<()> {
+	if CAP_FDS - fds.write_idx < received.len() {
+		let (start, end) = (fds.read_idx, fds.write_idx);
+		fds.buf.copy_within(start..end, 0);
+		fds.read_idx = 0;
+		fds.write_idx = end - start;
+	}
+	let n = Ord::min(received.len(), CAP_FDS - fds.write_idx);
+	fds.buf[fds.write_idx..fds.write_idx + n].copy_from_slice(&received[..n]);
+	fds.write_idx += n;
+	fds.check();
+	if n < received.len() {
+		for &fd in &received[n..] {
+			// Safety: the kernel just opened these for us, and nothing else refers to them
+			drop(unsafe { OwnedFd::from_raw_fd(fd) });
+		}
+		return Err(Error::new(
+			ErrorKind::InvalidInput,
+			format!("too many file descriptors, only {CAP_FDS} can be waiting to be taken by requests"),
+		));
+	}
+	Ok(())
+}
+
 #[derive(Debug)]
 pub struct RecvMessage<'c> {
 	object_id: Id<AnyObject>,
@@ -111,6 +163,10 @@ pub struct RecvMessage<'c> {
 }
 
 impl<'c> RecvMessage<'c> {
+	pub(super) fn new(object_id: Id<AnyObject>, opcode: u16, bytes: &'c [Word], fds: &'c mut FdBuffer) -> Self {
+		Self { object_id, opcode, bytes, fds }
+	}
+
 	pub fn object_id(&self) -> Id<AnyObject> {
 		self.object_id
 	}
@@ -119,41 +175,42 @@ impl<'c> RecvMessage<'c> {
 		self.opcode
 	}
 
-	pub fn take(&mut self) -> Result<u32> {
+	pub fn take(&mut self) -> crate::error::Result<u32> {
 		match *self.bytes {
 			[arg, ref rest @ ..] => {
 				self.bytes = rest;
 				Ok(arg)
 			},
-			[] => Err(Error::new(ErrorKind::InvalidInput, "too few args")),
+			[] => Err(ProtocolError::invalid_method("too few args").into()),
 		}
 	}
 
-	pub fn split(&mut self, n: usize) -> Result<&'c [u32]> {
+	pub fn split(&mut self, n: usize) -> crate::error::Result<&'c [u32]> {
 		if self.bytes.len() < n {
-			return Err(Error::new(ErrorKind::InvalidInput, "too few args"));
+			return Err(ProtocolError::invalid_method("too few args").into());
 		}
 		let (arg, rest) = self.bytes.split_at(n);
 		self.bytes = rest;
 		Ok(arg)
 	}
 
-	pub fn take_fd(&mut self) -> Result<OwnedFd> {
-		if self.fds.read_idx < self.fds.write_idx {
-			return Err(Error::new(ErrorKind::InvalidInput, "too few file descriptors"));
+	pub fn take_fd(&mut self) -> crate::error::Result<OwnedFd> {
+		if self.fds.read_idx >= self.fds.write_idx {
+			return Err(ProtocolError::invalid_method("too few file descriptors").into());
 		}
 		let fd = self.fds.buf[self.fds.read_idx];
 		self.fds.read_idx += 1;
+		self.fds.check();
 		// Safety: kernel ensures that file descriptors from recvmsg() are valid opened file descriptors, and
 		// incrementing read_idx before returning from this call ensures that file descriptors aren't returned twice
 		Ok(unsafe { OwnedFd::from_raw_fd(fd) })
 	}
 
-	pub fn finish(self) -> Result<()> {
+	pub fn finish(self) -> crate::error::Result<()> {
 		if self.bytes.is_empty() {
 			Ok(())
 		} else {
-			Err(Error::new(ErrorKind::InvalidInput, "too many args"))
+			Err(ProtocolError::invalid_method("too many args").into())
 		}
 	}
 }
@@ -1,24 +1,59 @@
-use super::{div_exact, Buffer, FdBuffer, CAP_BYTES, CAP_FDS};
+use super::{div_exact, Aligned, Buffer, ClientId, EventQueue, FdBuffer, CAP_BYTES, CAP_FDS};
 use crate::{
 	cvt_poll,
+	object_map::ObjectRef,
 	protocol::{AnyObject, Id, Word, WORD_SIZE},
+	state_dump::{Direction, History},
 };
 use log::trace;
 use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags};
 use std::{
+	collections::HashSet,
 	io::{Error, ErrorKind, IoSlice, Result},
 	os::unix::{io::AsRawFd, net::UnixStream},
+	rc::Rc,
 	task::{ready, Poll},
 };
 
 #[derive(Debug)]
 pub struct SendHalf<'c> {
+	pub(super) client: ClientId,
 	pub(super) sock: &'c UnixStream,
 	pub(super) bytes: &'c mut Buffer,
 	pub(super) fds: &'c mut FdBuffer,
+	pub(super) events: &'c Rc<EventQueue>,
+	pub(super) history: &'c mut History,
+	pub(super) deprecated: &'c mut HashSet<(&'static str, u16)>,
 }
 
 impl<'c> SendHalf<'c> {
+	/// The client this sends events to.
+	pub fn client_id(&self) -> ClientId {
+		self.client
+	}
+
+	/// Remember a message exchanged with this client, for state dumps.
+	pub fn record(&mut self, direction: Direction, interface: &'static str, object: Id<AnyObject>, opcode: u16) {
+		self.history.push(direction, interface, object, opcode);
+	}
+
+	/// Remember that the client sent deprecated request `opcode` of `interface`, returning whether it's the first time,
+	/// so each one is only warned about once per client.
+	pub fn first_deprecated_use(&mut self, interface: &'static str, opcode: u16) -> bool {
+		self.deprecated.insert((interface, opcode))
+	}
+
+	/// Create a handle to an object of this client, to send it events outside of request dispatch.
+	pub fn object_ref<T>(&self, id: Id<T>) -> ObjectRef<T> {
+		ObjectRef::new(self.client, id, Rc::downgrade(self.events))
+	}
+
+	/// Whether the outgoing buffer is more than half full, in which case queued events wait for it to drain.
+	pub fn is_congested(&self) -> bool {
+		self.bytes.write_idx - self.bytes.read_idx > CAP_BYTES / 2
+			|| self.fds.write_idx - self.fds.read_idx > CAP_FDS / 2
+	}
+
 	/// Queue a message to be sent to this peer.
 	///
 	/// `object_id` and `opcode` are included in the message header verbatim. `args_len` and `fds_len` count the
@@ -30,6 +65,9 @@ impl<'c> SendHalf<'c> {
 	/// little discards the message and may panic. Dropping or leaking the `SendMessage` without calling `finish`
 	/// discards the message but otherwise leaves this `SendHalf` in a consistent state. At no point is the message
 	/// partially delivered.
+	///
+	/// Fails if there's no room for the message after flushing as much as possible, or if it's larger than the buffers
+	/// could ever hold.
 	pub fn submit(
 		&mut self,
 		object_id: Id<AnyObject>,
@@ -39,7 +77,15 @@ impl<'c> SendHalf<'c> {
 	) -> Result<SendMessage<'_>> {
 		let words_len = args_len + 2;
 		let bytes_len = words_len * WORD_SIZE;
-		assert!(bytes_len <= CAP_BYTES, "message length {bytes_len} exceeds buffer capacity {CAP_BYTES}");
+		if bytes_len > CAP_BYTES || fds_len > CAP_FDS {
+			return Err(Error::new(
+				ErrorKind::InvalidInput,
+				format!(
+					"message of {bytes_len} bytes and {fds_len} file descriptors exceeds buffer capacity of \
+					 {CAP_BYTES} bytes and {CAP_FDS} file descriptors"
+				),
+			));
+		}
 
 		// reserve space by draining as much as possible and moving the rest forward
 		if CAP_BYTES - self.bytes.write_idx < bytes_len || CAP_FDS - self.fds.write_idx < fds_len {
@@ -63,6 +109,8 @@ impl<'c> SendHalf<'c> {
 			self.fds.read_idx = 0;
 			self.fds.write_idx = fds_end - fds_start;
 			trace!("copied fds {fds_start}..{fds_end} to {}..{}", self.fds.read_idx, self.fds.write_idx);
+			self.bytes.check(Aligned::Write);
+			self.fds.check();
 		}
 		if CAP_BYTES - self.bytes.write_idx < bytes_len {
 			// still no room
@@ -92,6 +140,7 @@ impl<'c> SendHalf<'c> {
 
 	/// Send as much data as possible to the connected peer until sending would block or fail.
 	pub fn poll_flush(&mut self) -> Poll<Result<()>> {
+		let _span = tracing::trace_span!("flush").entered();
 		while self.bytes.read_idx < self.bytes.write_idx || self.fds.read_idx < self.fds.write_idx {
 			let buf_bytes = Buffer::bytes(&self.bytes.buf);
 			let bytes = &buf_bytes[self.bytes.read_idx..self.bytes.write_idx];
@@ -106,6 +155,7 @@ impl<'c> SendHalf<'c> {
 			self.bytes.read_idx += n;
 			// XXX can sendmsg send partial ancillary data, and how is that reported?
 			self.fds.read_idx = self.fds.write_idx;
+			self.bytes.check(Aligned::Write);
 		}
 		Poll::Ready(Ok(()))
 	}
@@ -148,6 +198,8 @@ impl<'c> SendMessage<'c> {
 		assert!(self.words_idx == self.words_goal, "message underran requested byte buffers");
 		assert!(self.fds_idx == self.fds_goal, "message underran requested fd buffers");
 		self.bytes.write_idx = self.words_goal * WORD_SIZE;
-		self.fds.write_idx = self.fds_goal * WORD_SIZE;
+		self.fds.write_idx = self.fds_goal;
+		self.bytes.check(Aligned::Write);
+		self.fds.check();
 	}
 }
@@ -0,0 +1,255 @@
+//! In-memory message transport for unit tests and benchmarks, and a client fed raw bytes for fuzzing.
+
+use super::{Aligned, Buffer, Client, ClientId, EventQueue, FdBuffer, RecvMessage, SendHalf};
+use crate::{
+	error,
+	protocol::{Id, WORD_SIZE},
+	state_dump::History,
+};
+use nix::sys::{
+	memfd::{memfd_create, MemFdCreateFlag},
+	socket::{sendmsg, ControlMessage, MsgFlags},
+};
+use std::{
+	collections::HashSet,
+	ffi::CStr,
+	fs::File,
+	io::{self, ErrorKind, IoSlice, Read},
+	os::unix::{
+		io::{AsRawFd, FromRawFd, RawFd},
+		net::UnixStream,
+	},
+	rc::Rc,
+	task::Poll,
+};
+
+/// Messages written through [`send_half`](Self::send_half) are buffered, and can be read back with
+/// [`recv`](Self::recv) without ever being flushed to a socket.
+#[derive(Debug)]
+pub struct Loopback {
+	/// Required by `SendHalf`, but only written to if the buffer runs out of space
+	sock: UnixStream,
+	_peer: UnixStream,
+	bytes: Buffer,
+	fds: FdBuffer,
+	events: Rc<EventQueue>,
+	history: History,
+	deprecated: HashSet<(&'static str, u16)>,
+}
+
+impl Loopback {
+	pub fn new() -> Self {
+		let (sock, _peer) = UnixStream::pair().expect("failed to create socket pair");
+		Self {
+			sock,
+			_peer,
+			bytes: Buffer::new(),
+			fds: FdBuffer::new(),
+			events: Rc::default(),
+			history: History::default(),
+			deprecated: HashSet::new(),
+		}
+	}
+
+	pub fn send_half(&mut self) -> SendHalf<'_> {
+		let client = ClientId { key: usize::MAX, serial: u64::MAX };
+		SendHalf {
+			client,
+			sock: &self.sock,
+			bytes: &mut self.bytes,
+			fds: &mut self.fds,
+			events: &self.events,
+			history: &mut self.history,
+			deprecated: &mut self.deprecated,
+		}
+	}
+
+	/// Read back the oldest message that was written and not yet read.
+	///
+	/// # Panics
+	///
+	/// If no complete message is buffered.
+	pub fn recv(&mut self) -> RecvMessage<'_> {
+		let start = super::div_exact(self.bytes.read_idx, "read_idx");
+		let end = super::div_exact(self.bytes.write_idx, "write_idx");
+		let (object_id, len_op) = match self.bytes.buf[start..end] {
+			[object_id, len_op, ..] => (object_id, len_op),
+			_ => panic!("no message buffered"),
+		};
+		let words_len = (len_op >> 16) as usize / WORD_SIZE;
+		assert!(start + words_len <= end, "message is truncated");
+		self.bytes.read_idx += words_len * WORD_SIZE;
+		self.bytes.check(Aligned::Read);
+		let object_id = Id::new(object_id).expect("message has null target");
+		RecvMessage::new(object_id, len_op as u16, &self.bytes.buf[start + 2..start + words_len], &mut self.fds)
+	}
+}
+
+impl Default for Loopback {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// An event as `(object, opcode, args)`.
+pub type Event = (u32, u16, Vec<u32>);
+
+/// A client whose socket is written to directly, served the way the main loop serves clients except that panics are
+/// not caught.
+#[derive(Debug)]
+pub struct Harness {
+	client: Client,
+	peer: UnixStream,
+	/// Everything sent to the client so far
+	received: Vec<u8>,
+	/// How much of `received` was already returned by [`events`](Self::events)
+	seen: usize,
+}
+
+impl Harness {
+	pub fn new() -> Self {
+		let (sock, peer) = UnixStream::pair().expect("failed to create socket pair");
+		sock.set_nonblocking(true).expect("failed to make socket nonblocking");
+		peer.set_nonblocking(true).expect("failed to make socket nonblocking");
+		Self { client: Client::new(sock, 0), peer, received: Vec::new(), seen: 0 }
+	}
+
+	pub fn client(&mut self) -> &mut Client {
+		&mut self.client
+	}
+
+	/// Stop serving the client, to [close](Client::close) it.
+	pub fn into_client(self) -> Client {
+		self.client
+	}
+
+	/// Send `bytes` from the client with `fds` attached, then handle every complete request and send the replies.
+	///
+	/// Returns the first error, which the main loop would disconnect the client for. The client must not be fed
+	/// anything after that.
+	pub fn feed(&mut self, bytes: &[u8], fds: &[RawFd]) -> error::Result<()> {
+		let rights = [ControlMessage::ScmRights(fds)];
+		let cmsgs: &[ControlMessage<'_>] = if fds.is_empty() { &[] } else { &rights };
+		let mut sent = 0;
+		while sent < bytes.len() {
+			let cmsgs = if sent == 0 { cmsgs } else { &[] };
+			let iov = [IoSlice::new(&bytes[sent..])];
+			sent +=
+				sendmsg::<()>(self.peer.as_raw_fd(), &iov, cmsgs, MsgFlags::empty(), None).map_err(io::Error::from)?;
+			self.serve()?;
+		}
+		Ok(())
+	}
+
+	/// Handle every request received so far and send the replies.
+	fn serve(&mut self) -> error::Result<()> {
+		let (mut send, mut recv, objects) = self.client.split_mut();
+		while let Poll::Ready(msg) = recv.poll_recv() {
+			objects.dispatch_request(&mut send, msg?)?;
+		}
+		self.client.send_queued()?;
+		match self.peer.read_to_end(&mut self.received) {
+			Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(()),
+			Err(err) => Err(err.into()),
+			Ok(_) => unreachable!("the client's socket is never closed while it's being served"),
+		}
+	}
+
+	/// Everything sent to the client so far, in the order it was sent.
+	pub fn received(&self) -> &[u8] {
+		&self.received
+	}
+
+	/// Everything sent to the client so far, as words.
+	pub fn words(&self) -> Vec<u32> {
+		self.received.chunks_exact(WORD_SIZE).map(|word| u32::from_ne_bytes(word.try_into().unwrap())).collect()
+	}
+
+	/// Events sent to the client since the last call.
+	pub fn events(&mut self) -> Vec<Event> {
+		let words = self.words();
+		let mut rest = &words[self.seen / WORD_SIZE..];
+		self.seen = self.received.len();
+		let mut events = Vec::new();
+		while let [object, header, ..] = *rest {
+			let len = (header >> 16) as usize / WORD_SIZE;
+			events.push((object, header as u16, rest[2..len].to_vec()));
+			rest = &rest[len..];
+		}
+		events
+	}
+
+	/// Whether a global implementing `interface` was advertised.
+	pub fn advertised(&self, interface: &str) -> bool {
+		let wanted = string(interface);
+		self.words().windows(wanted.len()).any(|window| window == wanted)
+	}
+
+	/// Bind `interface` into object `id`, looking up its name among the globals advertised on registry 2.
+	///
+	/// # Panics
+	///
+	/// If no such global was advertised, or binding it fails.
+	pub fn bind(&mut self, interface: &str, version: u32, id: u32) {
+		let wanted = string(interface);
+		let words = self.words();
+		let global = words.windows(wanted.len() + 1).find(|window| window[1..] == wanted[..]);
+		let mut args = vec![global.unwrap_or_else(|| panic!("{interface} wasn't advertised"))[0]];
+		args.extend(wanted);
+		args.extend([version, id]);
+		self.feed(&request(2, 0, &args), &[]).unwrap();
+	}
+
+	/// Create a pool `id` of `len` bytes through the `wl_shm` object `shm`.
+	///
+	/// # Panics
+	///
+	/// If creating it fails.
+	pub fn pool(&mut self, shm: u32, id: u32, len: u32) {
+		let file = pool_file(len.into());
+		self.feed(&request(shm, 0, &[id, len]), &[file.as_raw_fd()]).unwrap();
+	}
+}
+
+/// Encode a request to `object` with the given opcode and argument words.
+pub fn request(object: u32, opcode: u16, args: &[u32]) -> Vec<u8> {
+	let len = (2 + args.len()) * WORD_SIZE;
+	let words = [object, (len as u32) << 16 | u32::from(opcode)].into_iter().chain(args.iter().copied());
+	words.flat_map(u32::to_ne_bytes).collect()
+}
+
+/// Encode a string argument as words.
+pub fn string(text: &str) -> Vec<u32> {
+	let mut bytes = text.as_bytes().to_vec();
+	bytes.push(0);
+	let len = bytes.len() as u32;
+	bytes.resize((bytes.len() + WORD_SIZE - 1) / WORD_SIZE * WORD_SIZE, 0);
+	let words = bytes.chunks_exact(WORD_SIZE).map(|word| u32::from_ne_bytes(word.try_into().unwrap()));
+	[len].into_iter().chain(words).collect()
+}
+
+/// Text of a string argument starting at `args[0]`.
+pub fn parse_string(args: &[u32]) -> String {
+	let bytes: Vec<u8> = args[1..].iter().flat_map(|word| word.to_ne_bytes()).take(args[0] as usize - 1).collect();
+	String::from_utf8(bytes).unwrap()
+}
+
+/// An anonymous file of `len` bytes to back a pool.
+///
+/// # Panics
+///
+/// If the file can't be created.
+pub fn pool_file(len: u64) -> File {
+	let name = CStr::from_bytes_with_nul(b"myway-pool\0").unwrap();
+	let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC).expect("failed to create pool file");
+	// Safety: memfd_create returns a newly created file descriptor which we immediately wrap
+	let file = unsafe { File::from_raw_fd(fd) };
+	file.set_len(len).expect("failed to size pool file");
+	file
+}
+
+impl Default for Harness {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -0,0 +1,118 @@
+//! The clock every timestamp sent to clients is read from.
+//!
+//! Frame callbacks, input events and presentation feedback all report times in the same `CLOCK_MONOTONIC` base, so
+//! clients can compare them against each other and against their own `clock_gettime` calls.
+//!
+//! For reproducing timing bugs, frame times can instead be [replayed](replay) from a script, so every run reports the
+//! same times no matter how long handling took.
+
+use nix::time::{clock_gettime, ClockId};
+use std::{
+	cell::{Cell, RefCell},
+	io::{Error, ErrorKind, Result},
+	time::Duration,
+};
+
+/// The clock timestamps are read from, as advertised by `wp_presentation.clock_id`.
+pub const CLOCK_ID: ClockId = ClockId::CLOCK_MONOTONIC;
+
+/// A point in time on [`CLOCK_ID`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Timestamp(Duration);
+
+impl Timestamp {
+	pub fn now() -> Self {
+		let now = clock_gettime(CLOCK_ID).expect("CLOCK_MONOTONIC is always supported");
+		Self(Duration::new(now.tv_sec() as u64, now.tv_nsec() as u32))
+	}
+
+	/// The timestamp in milliseconds, as in the `time` argument of most events. This wraps around every 49.7 days.
+	pub fn millis(self) -> u32 {
+		self.0.as_millis() as u32
+	}
+
+	/// Time elapsed since the clock's unspecified starting point.
+	pub fn since_start(self) -> Duration {
+		self.0
+	}
+}
+
+thread_local! {
+	static FRAME_TIME: Cell<Option<Timestamp>> = Cell::default();
+	static REPLAY: RefCell<Option<Script>> = RefCell::default();
+}
+
+/// Frame times to use instead of the clock, parsed from a script with one time in milliseconds per line.
+///
+/// A line is either a time since the clock's starting point, or `+` followed by a time since the previous frame. Times
+/// may not go backwards. Empty lines and lines starting with `#` are skipped. Once the script runs out, frames keep
+/// coming at the interval between its last two times.
+#[derive(Clone, Debug, Default)]
+pub struct Script {
+	times: Vec<Duration>,
+	next: usize,
+}
+
+impl Script {
+	pub fn parse(script: &str) -> Result<Self> {
+		let mut times = Vec::new();
+		for (number, line) in (1..).zip(script.lines()) {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let invalid = |message: &str| Error::new(ErrorKind::InvalidData, format!("line {number}: {message}"));
+			let previous = times.last().copied().unwrap_or_default();
+			let (relative, millis) = match line.strip_prefix('+') {
+				Some(millis) => (true, millis),
+				None => (false, line),
+			};
+			let millis = millis.parse().map_err(|_| invalid("expected a time in milliseconds"))?;
+			let time = Duration::from_millis(millis);
+			let time = if relative { previous + time } else { time };
+			if time < previous {
+				return Err(invalid("time goes backwards"));
+			}
+			times.push(time);
+		}
+		Ok(Self { times, next: 0 })
+	}
+
+	fn next_time(&mut self) -> Timestamp {
+		let time = match self.times.get(self.next) {
+			Some(&time) => time,
+			None => {
+				let extra = u32::try_from(self.next + 1 - self.times.len()).unwrap_or(u32::MAX);
+				match self.times[..] {
+					[.., before, last] => (last - before).checked_mul(extra).and_then(|step| last.checked_add(step)),
+					[last] => Some(last),
+					[] => Some(Duration::ZERO),
+				}
+				.unwrap_or(Duration::MAX)
+			},
+		};
+		self.next += 1;
+		Timestamp(time)
+	}
+}
+
+/// Take frame times from `script` instead of the clock from now on.
+pub fn replay(script: Script) {
+	REPLAY.with(|replay| *replay.borrow_mut() = Some(script));
+}
+
+/// Start a new frame, sampling the time that [`frame_time`] returns until the next one.
+///
+/// The main loop calls this each time it wakes up, so every callback fired in response to the same batch of requests
+/// reports the same time. If a script is being [replayed](replay), this takes its next time instead.
+pub fn begin_frame() -> Timestamp {
+	let scripted = REPLAY.with(|replay| replay.borrow_mut().as_mut().map(Script::next_time));
+	let now = scripted.unwrap_or_else(Timestamp::now);
+	FRAME_TIME.with(|time| time.set(Some(now)));
+	now
+}
+
+/// The time the current frame started, which frame callbacks report as their presentation time.
+pub fn frame_time() -> Timestamp {
+	FRAME_TIME.with(Cell::get).unwrap_or_else(begin_frame)
+}
@@ -0,0 +1,155 @@
+//! Parsing the EDID a monitor reports, to tell monitors apart and describe them to clients.
+//!
+//! Only the 128 byte base block is read. Extension blocks (usually CTA-861, listing more modes and HDR metadata) are
+//! skipped, so a monitor's modes are those of its detailed and standard timings.
+
+use std::{
+	fmt::{self, Display, Formatter},
+	io::{Error, ErrorKind, Result},
+};
+
+/// Length of the EDID base block.
+const BLOCK_LEN: usize = 128;
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+/// Offset of the four 18 byte descriptors.
+const DESCRIPTORS: usize = 54;
+const DESCRIPTOR_LEN: usize = 18;
+
+/// What a monitor says about itself.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Edid {
+	/// Three letter PNP ID of the manufacturer, such as `DEL` for Dell
+	pub manufacturer: String,
+	/// Manufacturer-assigned product code
+	pub product_code: u16,
+	/// Serial number from the serial number descriptor, or the numeric serial if there is none
+	pub serial: Option<String>,
+	/// Monitor name from the name descriptor, such as `DELL U2720Q`
+	pub name: Option<String>,
+	/// Physical width and height in millimeters, if the monitor has a fixed size
+	pub size_mm: Option<[u32; 2]>,
+	/// Supported modes, with the preferred one first
+	pub modes: Vec<Mode>,
+}
+
+/// A display mode from a timing in the EDID.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Mode {
+	pub width: u32,
+	pub height: u32,
+	/// Refresh rate in mHz, as in `wl_output.mode`
+	pub refresh: u32,
+	/// Whether this is the monitor's native mode
+	pub preferred: bool,
+}
+
+impl Edid {
+	/// Parse an EDID, as read from the connector's `EDID` property blob.
+	pub fn parse(bytes: &[u8]) -> Result<Self> {
+		let block: &[u8; BLOCK_LEN] = bytes
+			.get(..BLOCK_LEN)
+			.and_then(|block| block.try_into().ok())
+			.ok_or_else(|| invalid(format!("EDID is {} bytes, shorter than a block", bytes.len())))?;
+		if block[..8] != HEADER {
+			return Err(invalid("EDID header is missing"));
+		}
+		if block.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+			return Err(invalid("EDID checksum doesn't match"));
+		}
+
+		let id = u16::from_be_bytes([block[8], block[9]]);
+		let manufacturer = [10, 5, 0].iter().map(|shift| char::from(b'A' - 1 + (id >> shift & 0x1f) as u8)).collect();
+		let product_code = u16::from_le_bytes([block[10], block[11]]);
+		let serial_number = u32::from_le_bytes([block[12], block[13], block[14], block[15]]);
+		// sizes are in centimeters, and either being zero means the other is an aspect ratio, as for projectors
+		let size_mm = match [block[21], block[22]] {
+			[0, _] | [_, 0] => None,
+			[width, height] => Some([u32::from(width) * 10, u32::from(height) * 10]),
+		};
+
+		let mut edid = Edid { manufacturer, product_code, serial: None, name: None, size_mm, modes: Vec::new() };
+		for descriptor in block[DESCRIPTORS..DESCRIPTORS + 4 * DESCRIPTOR_LEN].chunks_exact(DESCRIPTOR_LEN) {
+			match descriptor {
+				[0, 0, _, 0xff, _, text @ ..] => edid.serial = descriptor_text(text),
+				[0, 0, _, 0xfc, _, text @ ..] => edid.name = descriptor_text(text),
+				[0, 0, ..] => (),
+				timing => edid.modes.extend(detailed_timing(timing, edid.modes.is_empty())),
+			}
+		}
+		edid.modes.extend(block[38..54].chunks_exact(2).filter_map(|timing| standard_timing([timing[0], timing[1]])));
+		if edid.serial.is_none() && serial_number != 0 {
+			edid.serial = Some(serial_number.to_string());
+		}
+		Ok(edid)
+	}
+
+	/// A name for the monitor model, like `DELL U2720Q`, falling back to the manufacturer and product code.
+	pub fn model(&self) -> String {
+		match &self.name {
+			Some(name) => name.clone(),
+			None => format!("{} 0x{:04x}", self.manufacturer, self.product_code),
+		}
+	}
+
+	/// Identifies this particular monitor, to remember settings for it across hotplugs and restarts.
+	///
+	/// Two monitors of the same model only have different identities if they report serial numbers.
+	pub fn identity(&self) -> Identity<'_> {
+		Identity(self)
+	}
+}
+
+/// A monitor's manufacturer, product code and serial number, as a string usable as a configuration key.
+#[derive(Copy, Clone, Debug)]
+pub struct Identity<'e>(&'e Edid);
+
+impl Display for Identity<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let Edid { manufacturer, product_code, serial, .. } = self.0;
+		write!(f, "{manufacturer}-{product_code:04x}-{}", serial.as_deref().unwrap_or("0"))
+	}
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+	Error::new(ErrorKind::InvalidData, message.into())
+}
+
+/// Text of a display descriptor: up to 13 bytes, ended by a newline and padded with spaces.
+fn descriptor_text(text: &[u8]) -> Option<String> {
+	let text = text.split(|&byte| byte == b'\n').next().unwrap_or_default();
+	let text = String::from_utf8_lossy(text).trim().to_owned();
+	(!text.is_empty()).then_some(text)
+}
+
+/// Decode an 18 byte detailed timing descriptor, skipping interlaced modes.
+fn detailed_timing(timing: &[u8], preferred: bool) -> Option<Mode> {
+	let pixel_clock = u64::from(u16::from_le_bytes([timing[0], timing[1]])) * 10_000;
+	let width = u32::from(timing[2]) | u32::from(timing[4] & 0xf0) << 4;
+	let h_blank = u32::from(timing[3]) | u32::from(timing[4] & 0x0f) << 8;
+	let height = u32::from(timing[5]) | u32::from(timing[7] & 0xf0) << 4;
+	let v_blank = u32::from(timing[6]) | u32::from(timing[7] & 0x0f) << 8;
+	let interlaced = timing[17] & 0x80 != 0;
+	let total = u64::from(width + h_blank) * u64::from(height + v_blank);
+	if interlaced || total == 0 {
+		return None;
+	}
+	let refresh = (pixel_clock * 1000 + total / 2) / total;
+	Some(Mode { width, height, refresh: refresh as u32, preferred })
+}
+
+/// Decode a two byte standard timing, which only has a width, an aspect ratio and a refresh rate in whole Hz.
+fn standard_timing(timing: [u8; 2]) -> Option<Mode> {
+	if timing == [0x01, 0x01] || timing[0] == 0 {
+		return None;
+	}
+	let width = (u32::from(timing[0]) + 31) * 8;
+	// 16:10 was 1:1 before EDID 1.3, which no monitor worth supporting predates
+	let height = match timing[1] >> 6 {
+		0 => width * 10 / 16,
+		1 => width * 3 / 4,
+		2 => width * 4 / 5,
+		_ => width * 9 / 16,
+	};
+	let refresh = (u32::from(timing[1] & 0x3f) + 60) * 1000;
+	Some(Mode { width, height, refresh, preferred: false })
+}
@@ -43,6 +43,8 @@ pub type Interest = EpollFlags;
 pub const EPOLLIN: Interest = EpollFlags::EPOLLIN;
 pub const EPOLLOUT: Interest = EpollFlags::EPOLLOUT;
 // pub const EPOLLPRI: Interest = EpollFlags::EPOLLPRI;
-// pub const EPOLLERR: Interest = EpollFlags::EPOLLERR;
-// pub const EPOLLRDHUP: Interest = EpollFlags::EPOLLRDHUP;
-// pub const EPOLLHUP: Interest = EpollFlags::EPOLLHUP;
+/// Reported whether registered for or not.
+pub const EPOLLERR: Interest = EpollFlags::EPOLLERR;
+pub const EPOLLRDHUP: Interest = EpollFlags::EPOLLRDHUP;
+/// Reported whether registered for or not.
+pub const EPOLLHUP: Interest = EpollFlags::EPOLLHUP;
@@ -0,0 +1,215 @@
+use crate::protocol::{wl_display, AnyObject, Id};
+use std::{
+	any::Any,
+	fmt::{self, Display, Formatter},
+	io,
+};
+
+/// Result of handling a request or otherwise serving a client.
+pub type Result<T, E = ServerError> = std::result::Result<T, E>;
+
+/// Reasons serving a client can fail, distinguished by how the main loop should respond.
+#[derive(Debug)]
+pub enum ServerError {
+	/// The client violated the protocol. The error is posted to the client with `wl_display.error` before
+	/// disconnecting it.
+	Protocol(ProtocolError),
+	/// A request could not be decoded for dispatch. This is reported to the client as
+	/// `wl_display.error.invalid_method` before disconnecting it.
+	InvalidRequest(InvalidRequest),
+	/// Communicating with the client failed. The client is disconnected without notice, since the connection is likely
+	/// unusable anyway.
+	Io(io::Error),
+	/// The compositor reached a state it should never be in. This is a bug in myway, not the client, and crashes the
+	/// compositor.
+	Internal(String),
+	/// Code serving the client panicked, with the panic message. The client is sent `wl_display.error.implementation`
+	/// and disconnected, discarding whatever state the panic left half-updated, but other clients keep running.
+	Panic(String),
+}
+
+impl Display for ServerError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Protocol(err) => Display::fmt(err, f),
+			Self::InvalidRequest(err) => Display::fmt(err, f),
+			Self::Io(err) => write!(f, "I/O error: {err}"),
+			Self::Internal(msg) => write!(f, "internal error: {msg}"),
+			Self::Panic(msg) => write!(f, "panicked: {msg}"),
+		}
+	}
+}
+
+impl ServerError {
+	/// Convert the payload of a caught panic.
+	pub fn panic(payload: Box<dyn Any + Send>) -> Self {
+		let msg = match payload.downcast::<String>() {
+			Ok(msg) => *msg,
+			Err(payload) => match payload.downcast::<&str>() {
+				Ok(msg) => String::from(*msg),
+				Err(_) => String::from("unknown panic payload"),
+			},
+		};
+		Self::Panic(msg)
+	}
+
+	/// Set the object a protocol error occurred on, unless one was already set.
+	pub fn or_on<T>(self, object: Id<T>) -> Self {
+		match self {
+			Self::Protocol(err) => Self::Protocol(err.or_on(object)),
+			other => other,
+		}
+	}
+}
+
+impl std::error::Error for ServerError {}
+
+impl From<ProtocolError> for ServerError {
+	fn from(err: ProtocolError) -> Self {
+		Self::Protocol(err)
+	}
+}
+
+impl From<InvalidRequest> for ServerError {
+	fn from(err: InvalidRequest) -> Self {
+		Self::InvalidRequest(err)
+	}
+}
+
+impl From<io::Error> for ServerError {
+	fn from(err: io::Error) -> Self {
+		Self::Io(err)
+	}
+}
+
+/// A fatal protocol error, to be reported to the client with `wl_display.error`.
+#[derive(Debug)]
+pub struct ProtocolError {
+	/// Object the error occurred on.
+	///
+	/// Handlers usually leave this unset, in which case dispatch fills in the object the request was sent to.
+	pub object: Option<Id<AnyObject>>,
+	/// Error code, from the `error` enum of `object`'s interface.
+	pub code: u32,
+	/// Human-readable description, for debugging.
+	pub message: String,
+}
+
+impl ProtocolError {
+	/// Create an error with an interface-specific error code, occurring on the object the current request was sent
+	/// to.
+	pub fn new(code: impl Into<u32>, message: impl Into<String>) -> Self {
+		Self { object: None, code: code.into(), message: message.into() }
+	}
+
+	/// `wl_display.error.invalid_object`: a request referenced an object that doesn't exist or has the wrong type.
+	pub fn invalid_object(message: impl Into<String>) -> Self {
+		Self::new(wl_display::Error::InvalidObject, message).on(display_id())
+	}
+
+	/// `wl_display.error.invalid_method`: a request was malformed or isn't defined for its target.
+	pub fn invalid_method(message: impl Into<String>) -> Self {
+		Self::new(wl_display::Error::InvalidMethod, message).on(display_id())
+	}
+
+	/// `wl_display.error.implementation`: the compositor could not handle a valid request.
+	pub fn implementation(message: impl Into<String>) -> Self {
+		Self::new(wl_display::Error::Implementation, message).on(display_id())
+	}
+
+	/// Set the object this error occurred on.
+	pub fn on<T>(mut self, object: Id<T>) -> Self {
+		self.object = Some(object.cast());
+		self
+	}
+
+	/// Set the object this error occurred on, unless one was already set.
+	pub fn or_on<T>(mut self, object: Id<T>) -> Self {
+		self.object.get_or_insert(object.cast());
+		self
+	}
+}
+
+impl Display for ProtocolError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self.object {
+			Some(id) => write!(f, "protocol error {} on object {id}: {}", self.code, self.message),
+			None => write!(f, "protocol error {}: {}", self.code, self.message),
+		}
+	}
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// A request whose opcode is unknown for its target, or whose arguments are malformed.
+#[derive(Debug)]
+pub struct InvalidRequest {
+	/// Interface of the object the request was sent to.
+	pub interface: &'static str,
+	/// Object the request was sent to.
+	pub object: Id<AnyObject>,
+	/// Opcode of the request.
+	pub opcode: u16,
+	/// Name of the request, if `opcode` is valid for `interface`.
+	pub request: Option<&'static str>,
+	/// Name of the argument that failed to decode, if the failure is specific to one argument.
+	pub arg: Option<&'static str>,
+	/// Why decoding failed.
+	pub reason: String,
+}
+
+impl InvalidRequest {
+	/// A request was sent with an opcode `interface` doesn't define.
+	pub fn opcode(interface: &'static str, object: Id<AnyObject>, opcode: u16) -> ServerError {
+		let reason = String::from("unknown opcode");
+		Self { interface, object, opcode, request: None, arg: None, reason }.into()
+	}
+
+	/// Decoding the arguments of a request failed with `err`. `arg` is the argument being decoded, or `None` if the
+	/// message as a whole was malformed (e.g. it had trailing data).
+	///
+	/// Errors other than protocol errors are passed through unchanged.
+	pub fn arguments(
+		err: ServerError,
+		interface: &'static str,
+		object: Id<AnyObject>,
+		opcode: u16,
+		request: &'static str,
+		arg: Option<&'static str>,
+	) -> ServerError {
+		match err {
+			ServerError::Protocol(ProtocolError { message: reason, .. }) => {
+				Self { interface, object, opcode, request: Some(request), arg, reason }.into()
+			},
+			other => other,
+		}
+	}
+}
+
+impl Display for InvalidRequest {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let Self { interface, object, opcode, request, arg, reason } = self;
+		match request {
+			Some(request) => write!(f, "invalid arguments for {interface}@{object}.{request}")?,
+			None => write!(f, "invalid method {opcode}, object {interface}@{object}")?,
+		}
+		if let Some(arg) = arg {
+			write!(f, " (argument {arg})")?;
+		}
+		write!(f, ": {reason}")
+	}
+}
+
+impl std::error::Error for InvalidRequest {}
+
+impl From<InvalidRequest> for ProtocolError {
+	fn from(err: InvalidRequest) -> Self {
+		Self::invalid_method(err.to_string())
+	}
+}
+
+/// ID of the `wl_display` singleton. Errors with `wl_display.error` codes are posted on this object, since error codes
+/// are interpreted according to the interface of the object they're posted on.
+fn display_id() -> Id<AnyObject> {
+	Id::new(1).unwrap()
+}
@@ -0,0 +1,419 @@
+//! Points, sizes, rectangles and regions in the compositor's coordinate space.
+//!
+//! Window positions, sizes and configure events are in whole surface-local pixels, as `i32` like the protocol sends
+//! them. Pointer motion and fractional scaling land between pixels, so hit testing takes `f64` points, which convert to
+//! and from the protocol's [`Fixed`] at the edges.
+//!
+//! Buffers have coordinates of their own, which differ from the surface's by the buffer scale and transform. Damage
+//! can come in either, and is converted to surface-local coordinates on commit.
+
+use crate::protocol::{wl_output::Transform, Fixed};
+use std::{
+	fmt::{self, Display, Formatter},
+	ops::{Add, Sub},
+};
+
+/// A position, or a distance to move by.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Point<T = i32> {
+	pub x: T,
+	pub y: T,
+}
+
+impl<T> Point<T> {
+	pub const fn new(x: T, y: T) -> Self {
+		Self { x, y }
+	}
+}
+
+impl Point {
+	pub const ORIGIN: Self = Self::new(0, 0);
+
+	/// Move by `offset`, stopping at the bounds of `i32` rather than wrapping around.
+	pub fn saturating_add(self, offset: Self) -> Self {
+		Self::new(self.x.saturating_add(offset.x), self.y.saturating_add(offset.y))
+	}
+}
+
+impl Point<f64> {
+	/// The pixel this point is in, saturating at the bounds of `i32`.
+	pub fn floor(self) -> Point {
+		Point::new(self.x.floor() as i32, self.y.floor() as i32)
+	}
+}
+
+impl Add for Point<f64> {
+	type Output = Self;
+
+	fn add(self, other: Self) -> Self {
+		Self::new(self.x + other.x, self.y + other.y)
+	}
+}
+
+impl Sub for Point<f64> {
+	type Output = Self;
+
+	fn sub(self, other: Self) -> Self {
+		Self::new(self.x - other.x, self.y - other.y)
+	}
+}
+
+impl<T> From<[T; 2]> for Point<T> {
+	fn from([x, y]: [T; 2]) -> Self {
+		Self::new(x, y)
+	}
+}
+
+impl From<Point> for Point<f64> {
+	fn from(point: Point) -> Self {
+		Self::new(point.x.into(), point.y.into())
+	}
+}
+
+impl From<Point<Fixed>> for Point<f64> {
+	fn from(point: Point<Fixed>) -> Self {
+		Self::new(point.x.into(), point.y.into())
+	}
+}
+
+impl From<Point<f64>> for Point<Fixed> {
+	/// Round to the nearest representable point, as pointer events send it.
+	fn from(point: Point<f64>) -> Self {
+		Self::new(point.x.into(), point.y.into())
+	}
+}
+
+impl<T: Display> Display for Point<T> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{},{}", self.x, self.y)
+	}
+}
+
+/// A width and height. Configure events and size limits use zero for a dimension that isn't set.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Size<T = i32> {
+	pub width: T,
+	pub height: T,
+}
+
+impl<T> Size<T> {
+	pub const fn new(width: T, height: T) -> Self {
+		Self { width, height }
+	}
+}
+
+impl Size {
+	/// Whether the size covers no pixels at all.
+	pub fn is_empty(self) -> bool {
+		self.width <= 0 || self.height <= 0
+	}
+
+	/// Whether both width and height are multiples of `factor`, as a buffer's have to be of its scale.
+	pub fn is_multiple_of(self, factor: i32) -> bool {
+		self.width % factor == 0 && self.height % factor == 0
+	}
+
+	/// Divide by a positive `scale`, rounding down.
+	pub fn scaled_down(self, scale: i32) -> Self {
+		Self::new(self.width / scale, self.height / scale)
+	}
+
+	/// The size after turning by `transform`, which swaps width and height for quarter turns.
+	pub fn transformed(self, transform: Transform) -> Self {
+		if quarter_turn(transform) {
+			Self::new(self.height, self.width)
+		} else {
+			self
+		}
+	}
+}
+
+impl<T> From<[T; 2]> for Size<T> {
+	fn from([width, height]: [T; 2]) -> Self {
+		Self::new(width, height)
+	}
+}
+
+impl From<Size> for Size<f64> {
+	fn from(size: Size) -> Self {
+		Self::new(size.width.into(), size.height.into())
+	}
+}
+
+impl<T: Display> Display for Size<T> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}x{}", self.width, self.height)
+	}
+}
+
+/// A rectangle from its top left corner, including that edge, to the bottom right, excluding it.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Rect<T = i32> {
+	pub origin: Point<T>,
+	pub size: Size<T>,
+}
+
+impl<T> Rect<T> {
+	pub const fn new(origin: Point<T>, size: Size<T>) -> Self {
+		Self { origin, size }
+	}
+}
+
+impl Rect {
+	/// The rectangle from `start` to just before `end`, which is empty unless `end` is below and right of `start`.
+	pub fn from_corners(start: Point, end: Point) -> Self {
+		let size = Size::new(end.x.saturating_sub(start.x).max(0), end.y.saturating_sub(start.y).max(0));
+		Self::new(start, size)
+	}
+
+	/// The corner just past the bottom right one, stopping at the bounds of `i32`.
+	pub fn end(self) -> Point {
+		self.origin.saturating_add(Point::new(self.size.width, self.size.height))
+	}
+
+	/// Whether the rectangle covers no pixels at all.
+	pub fn is_empty(self) -> bool {
+		self.size.is_empty()
+	}
+
+	/// Whether `point` is within the rectangle, for hit testing at fractional positions. A point on the right or bottom
+	/// edge belongs to whatever is next to the rectangle instead.
+	pub fn contains(self, point: Point<f64>) -> bool {
+		Rect::<f64>::from(self).contains(point)
+	}
+
+	/// The pixels in both rectangles, if there are any.
+	pub fn intersection(self, other: Self) -> Option<Self> {
+		let (end, other_end) = (self.end(), other.end());
+		let start = Point::new(self.origin.x.max(other.origin.x), self.origin.y.max(other.origin.y));
+		let rect = Self::from_corners(start, Point::new(end.x.min(other_end.x), end.y.min(other_end.y)));
+		(!rect.is_empty()).then_some(rect)
+	}
+
+	/// The pixels in this rectangle but not in `other`, as up to four rectangles that don't overlap: the full width
+	/// above and below `other`, and what's left and right of it in between.
+	pub fn subtract(self, other: Self) -> impl Iterator<Item = Self> {
+		let pieces = match self.intersection(other) {
+			Some(cut) => {
+				let (start, end, cut_end) = (self.origin, self.end(), cut.end());
+				[
+					Self::from_corners(start, Point::new(end.x, cut.origin.y)),
+					Self::from_corners(Point::new(start.x, cut_end.y), end),
+					Self::from_corners(Point::new(start.x, cut.origin.y), Point::new(cut.origin.x, cut_end.y)),
+					Self::from_corners(Point::new(cut_end.x, cut.origin.y), Point::new(end.x, cut_end.y)),
+				]
+			},
+			None => [self, Self::default(), Self::default(), Self::default()],
+		};
+		pieces.into_iter().filter(|piece| !piece.is_empty())
+	}
+
+	/// Convert from the coordinates of a buffer drawn with `transform` and `scale` to those of its surface, which is
+	/// `surface` large. Whatever is outside the buffer is cut off, and scaling rounds outwards, so the result covers
+	/// every surface pixel that's partly within `self`.
+	pub fn buffer_to_surface(self, transform: Transform, scale: i32, surface: Size) -> Self {
+		let buffer = Rect::new(Point::ORIGIN, surface.transformed(transform));
+		let scaled = Self::from_corners(
+			Point::new(self.origin.x.div_euclid(scale), self.origin.y.div_euclid(scale)),
+			Point::new(ceil_div(self.end().x, scale), ceil_div(self.end().y, scale)),
+		);
+		let rect = match scaled.intersection(buffer) {
+			Some(rect) => rect,
+			None => return Self::new(Point::ORIGIN, Size::default()),
+		};
+		let [a, b] = [rect.origin, rect.end()].map(|point| {
+			let Point { x, y } = point;
+			let Size { width: w, height: h } = surface;
+			match transform {
+				Transform::Normal => Point::new(x, y),
+				Transform::_90 => Point::new(w - y, x),
+				Transform::_180 => Point::new(w - x, h - y),
+				Transform::_270 => Point::new(y, h - x),
+				Transform::Flipped => Point::new(w - x, y),
+				Transform::Flipped90 => Point::new(y, x),
+				Transform::Flipped180 => Point::new(x, h - y),
+				Transform::Flipped270 => Point::new(w - y, h - x),
+			}
+		});
+		Self::from_corners(Point::new(a.x.min(b.x), a.y.min(b.y)), Point::new(a.x.max(b.x), a.y.max(b.y)))
+	}
+
+	/// Convert from the coordinates of a surface `surface` large to those of its buffer, drawn with `transform` and
+	/// `scale`. The inverse of [`buffer_to_surface`](Self::buffer_to_surface), and also cuts off whatever is outside.
+	pub fn surface_to_buffer(self, transform: Transform, scale: i32, surface: Size) -> Self {
+		let rect = match self.intersection(Rect::new(Point::ORIGIN, surface)) {
+			Some(rect) => rect,
+			None => return Self::new(Point::ORIGIN, Size::default()),
+		};
+		let [a, b] = [rect.origin, rect.end()].map(|point| {
+			let Point { x, y } = point;
+			let Size { width: w, height: h } = surface;
+			let point = match transform {
+				Transform::Normal => Point::new(x, y),
+				Transform::_90 => Point::new(y, w - x),
+				Transform::_180 => Point::new(w - x, h - y),
+				Transform::_270 => Point::new(h - y, x),
+				Transform::Flipped => Point::new(w - x, y),
+				Transform::Flipped90 => Point::new(y, x),
+				Transform::Flipped180 => Point::new(x, h - y),
+				Transform::Flipped270 => Point::new(h - y, w - x),
+			};
+			Point::new(point.x.saturating_mul(scale), point.y.saturating_mul(scale))
+		});
+		Self::from_corners(Point::new(a.x.min(b.x), a.y.min(b.y)), Point::new(a.x.max(b.x), a.y.max(b.y)))
+	}
+}
+
+/// Whether `transform` turns by a quarter, one way or the other.
+fn quarter_turn(transform: Transform) -> bool {
+	matches!(transform, Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270)
+}
+
+/// Divide by a positive `divisor`, rounding up.
+fn ceil_div(value: i32, divisor: i32) -> i32 {
+	let quotient = value.div_euclid(divisor);
+	if value.rem_euclid(divisor) == 0 {
+		quotient
+	} else {
+		quotient + 1
+	}
+}
+
+impl Rect<f64> {
+	/// Whether `point` is within the rectangle, see [`Rect::contains`].
+	pub fn contains(self, point: Point<f64>) -> bool {
+		let Self { origin, size } = self;
+		(origin.x..origin.x + size.width).contains(&point.x) && (origin.y..origin.y + size.height).contains(&point.y)
+	}
+}
+
+impl From<Rect> for Rect<f64> {
+	fn from(rect: Rect) -> Self {
+		Self::new(rect.origin.into(), rect.size.into())
+	}
+}
+
+impl<T: Display> Display for Rect<T> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{} at {}", self.size, self.origin)
+	}
+}
+
+/// A set of pixels, such as what's opaque, accepts input or was damaged on a surface, as `wl_region` describes it.
+///
+/// It's kept as rectangles that don't overlap, though the same set can be split into rectangles in different ways, so
+/// regions compare by the pixels they cover.
+#[derive(Clone, Debug, Default)]
+pub struct Region {
+	rects: Vec<Rect>,
+}
+
+impl Region {
+	/// The rectangles that make up the region, which don't overlap and aren't empty, in no particular order.
+	pub fn rects(&self) -> &[Rect] {
+		&self.rects
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.rects.is_empty()
+	}
+
+	/// Number of pixels in the region.
+	pub fn area(&self) -> u64 {
+		self.rects.iter().map(|rect| rect.size.width as u64 * rect.size.height as u64).sum()
+	}
+
+	/// The smallest rectangle the whole region is in, empty if the region is.
+	pub fn extents(&self) -> Rect {
+		let mut rects = self.rects.iter();
+		let first = match rects.next() {
+			Some(&first) => first,
+			None => return Rect::default(),
+		};
+		let (start, end) = rects.fold((first.origin, first.end()), |(start, end), rect| {
+			let rect_end = rect.end();
+			(
+				Point::new(start.x.min(rect.origin.x), start.y.min(rect.origin.y)),
+				Point::new(end.x.max(rect_end.x), end.y.max(rect_end.y)),
+			)
+		});
+		Rect::from_corners(start, end)
+	}
+
+	/// Whether `point` is in the region, see [`Rect::contains`].
+	pub fn contains(&self, point: Point<f64>) -> bool {
+		self.rects.iter().any(|rect| rect.contains(point))
+	}
+
+	/// Add the pixels in `rect`, as `wl_region.add` does.
+	pub fn add(&mut self, rect: Rect) {
+		self.subtract(rect);
+		if !rect.is_empty() {
+			self.rects.push(rect);
+		}
+	}
+
+	/// Remove the pixels in `rect`, as `wl_region.subtract` does.
+	pub fn subtract(&mut self, rect: Rect) {
+		self.rects = self.rects.iter().flat_map(|piece| piece.subtract(rect)).collect();
+	}
+
+	/// Add every pixel in `other`.
+	pub fn union(&mut self, other: &Self) {
+		for &rect in &other.rects {
+			self.add(rect);
+		}
+	}
+
+	/// Keep only the pixels within `rect`.
+	pub fn intersect(&mut self, rect: Rect) {
+		self.rects = self.rects.iter().filter_map(|piece| piece.intersection(rect)).collect();
+	}
+
+	/// The region with `convert` applied to each of its rectangles, which may make them overlap, or split them up.
+	pub fn map(&self, convert: impl Fn(Rect) -> Rect) -> Self {
+		let mut region = Self::default();
+		for &rect in &self.rects {
+			region.add(convert(rect));
+		}
+		region
+	}
+}
+
+impl From<Rect> for Region {
+	fn from(rect: Rect) -> Self {
+		let mut region = Self::default();
+		region.add(rect);
+		region
+	}
+}
+
+impl PartialEq for Region {
+	fn eq(&self, other: &Self) -> bool {
+		let outside = |region: &Self, other: &Self| {
+			let mut rest = region.clone();
+			for &rect in &other.rects {
+				rest.subtract(rect);
+			}
+			rest.is_empty()
+		};
+		outside(self, other) && outside(other, self)
+	}
+}
+
+impl Eq for Region {}
+
+impl Display for Region {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		if self.rects.is_empty() {
+			return f.write_str("empty");
+		}
+		for (index, rect) in self.rects.iter().enumerate() {
+			if index > 0 {
+				f.write_str(", ")?;
+			}
+			write!(f, "{rect}")?;
+		}
+		Ok(())
+	}
+}
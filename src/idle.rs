@@ -0,0 +1,56 @@
+//! Whether the session may go idle, blanking, dimming or locking the screen for lack of user activity.
+//!
+//! Nothing idles the session yet. This collects what keeps it awake, so whatever blanks or locks the screen can ask
+//! [`is_inhibited`] first.
+
+use log::info;
+use std::{
+	cell::{Cell, RefCell},
+	rc::{Rc, Weak},
+};
+
+thread_local! {
+	/// Inhibitors of every client, held weakly: those of a client that's gone, even one disconnected by a panic, stop
+	/// counting as soon as its objects are dropped, and [`is_inhibited`] forgets them.
+	static INHIBITORS: RefCell<Vec<Weak<Inhibitor>>> = RefCell::default();
+	/// Result of the last [`update`], to log only changes
+	static INHIBITED: Cell<bool> = Cell::default();
+}
+
+/// Keeps the session from going idle while its surface is visible, until dropped.
+#[derive(Debug)]
+pub struct Inhibitor {
+	/// Whether the surface is mapped, shared with it
+	visible: Rc<Cell<bool>>,
+}
+
+impl Inhibitor {
+	pub fn new(visible: Rc<Cell<bool>>) -> Rc<Self> {
+		let inhibitor = Rc::new(Self { visible });
+		INHIBITORS.with(|inhibitors| inhibitors.borrow_mut().push(Rc::downgrade(&inhibitor)));
+		inhibitor
+	}
+}
+
+/// Whether the surface of any inhibitor is visible.
+///
+/// Only unmapped surfaces count as invisible, as there is no scene graph to tell whether a surface is occluded.
+pub fn is_inhibited() -> bool {
+	INHIBITORS.with(|inhibitors| {
+		let mut inhibitors = inhibitors.borrow_mut();
+		inhibitors.retain(|inhibitor| inhibitor.strong_count() > 0);
+		inhibitors.iter().filter_map(Weak::upgrade).any(|inhibitor| inhibitor.visible.get())
+	})
+}
+
+/// Log whenever the session starts or stops being kept awake. The main loop calls this after serving clients.
+pub fn update() {
+	let inhibited = is_inhibited();
+	if INHIBITED.with(|last| last.replace(inhibited)) != inhibited {
+		if inhibited {
+			info!("idle inhibited by a visible surface");
+		} else {
+			info!("idle no longer inhibited");
+		}
+	}
+}
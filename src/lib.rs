@@ -0,0 +1,38 @@
+//! A Wayland compositor.
+//!
+//! The compositor is the `myway` binary. This library holds everything but its main loop, so benchmarks can reach the
+//! protocol and dispatch code.
+
+use std::{
+	io::{self, ErrorKind},
+	task::Poll,
+};
+
+pub mod accept;
+pub mod activation;
+pub mod chrome_trace;
+pub mod client;
+pub mod clock;
+pub mod edid;
+pub mod epoll;
+pub mod error;
+pub mod geometry;
+pub mod idle;
+pub mod logging;
+pub mod object_impls;
+pub mod object_map;
+pub mod protocol;
+pub mod shm;
+pub mod signals;
+pub mod state_dump;
+pub mod supervisor;
+pub mod surface_dump;
+pub mod windows;
+
+pub(crate) fn cvt_poll<T, E: Into<io::Error>>(res: Result<T, E>) -> Poll<io::Result<T>> {
+	match res.map_err(E::into) {
+		Ok(x) => Poll::Ready(Ok(x)),
+		Err(err) if err.kind() == ErrorKind::WouldBlock => Poll::Pending,
+		Err(err) => Poll::Ready(Err(err)),
+	}
+}
@@ -1,21 +1,202 @@
+use crate::client::ClientId;
+use log::{Level, Log, Metadata, Record};
 use once_cell::sync::Lazy;
 use std::{
-	cell::Cell,
+	cell::{Cell, RefCell},
 	env,
-	fmt::{Debug, Write as _},
+	fmt::{self, Debug, Display, Formatter, Write as _},
 	io::{stderr, Write as _},
 	num::NonZeroU32,
 	os::unix::io::AsRawFd,
-	time::SystemTime,
+	rc::Rc,
+	sync::Mutex,
+	time::{Duration, Instant, SystemTime},
 };
 
 static WAYLAND_DEBUG: Lazy<bool> = Lazy::new(|| matches!(env::var("WAYLAND_DEBUG").as_deref(), Ok("1" | "server")));
 
+/// Messages per second written at debug level and below, and by `WAYLAND_DEBUG`, unless `MYWAY_LOG_RATE` says
+/// otherwise.
+const DEFAULT_LOG_RATE: u32 = 1000;
+
+/// The limit on low priority messages per second, or `None` if `MYWAY_LOG_RATE=0` turned it off.
+static LOG_RATE: Lazy<Option<u32>> = Lazy::new(|| match env::var("MYWAY_LOG_RATE").map(|rate| rate.parse()) {
+	Ok(Ok(0)) => None,
+	Ok(Ok(rate)) => Some(rate),
+	_ => Some(DEFAULT_LOG_RATE),
+});
+
+static WAYLAND_DEBUG_LIMIT: RateLimit = RateLimit::new();
+
+/// Install a logger configured by `RUST_LOG` like `env_logger::init`, which prefixes messages with the client being
+/// served and drops messages at debug level and below once there are more than `MYWAY_LOG_RATE` of them in a second.
+///
+/// Formatting and writing every message of a busy client takes long enough to miss frames, and nobody reads thousands
+/// of lines a second anyway. Warnings and errors are never dropped.
+pub fn init() {
+	let inner = env_logger::Logger::from_default_env();
+	let max_level = inner.filter();
+	log::set_boxed_logger(Box::new(Logger { inner, limit: RateLimit::new() })).expect("logger initialized twice");
+	log::set_max_level(max_level);
+}
+
+/// A fixed window rate limiter, counting what it dropped so that can be reported once messages are let through again.
+struct RateLimit(Mutex<Option<Window>>);
+
+#[derive(Debug)]
+struct Window {
+	start: Instant,
+	passed: u32,
+	dropped: u32,
+}
+
+impl RateLimit {
+	const fn new() -> Self {
+		Self(Mutex::new(None))
+	}
+
+	/// Whether another message fits in the current one second window, and how many messages were dropped in the
+	/// previous window if this one just started.
+	fn admit(&self) -> (bool, u32) {
+		let rate = match *LOG_RATE {
+			Some(rate) => rate,
+			None => return (true, 0),
+		};
+		let now = Instant::now();
+		let mut window = self.0.lock().unwrap_or_else(|err| err.into_inner());
+		let window = window.get_or_insert(Window { start: now, passed: 0, dropped: 0 });
+		let mut dropped = 0;
+		if now.duration_since(window.start) >= Duration::from_secs(1) {
+			dropped = window.dropped;
+			*window = Window { start: now, passed: 0, dropped: 0 };
+		}
+		if window.passed < rate {
+			window.passed += 1;
+			(true, dropped)
+		} else {
+			window.dropped += 1;
+			(false, dropped)
+		}
+	}
+}
+
+struct Logger<L> {
+	inner: L,
+	limit: RateLimit,
+}
+
+impl<L: Log> Logger<L> {
+	fn log_in_context(&self, record: &Record<'_>) {
+		CLIENT.with(|client| match &*client.borrow() {
+			Some(client) => self.inner.log(
+				&Record::builder()
+					.metadata(record.metadata().clone())
+					.module_path(record.module_path())
+					.file(record.file())
+					.line(record.line())
+					.args(format_args!("[{client}] {}", record.args()))
+					.build(),
+			),
+			None => self.inner.log(record),
+		});
+	}
+}
+
+impl<L: Log> Log for Logger<L> {
+	fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+		self.inner.enabled(metadata)
+	}
+
+	fn log(&self, record: &Record<'_>) {
+		if record.level() <= Level::Info || !self.inner.enabled(record.metadata()) {
+			return self.log_in_context(record);
+		}
+		let (admit, dropped) = self.limit.admit();
+		if dropped > 0 {
+			self.inner.log(
+				&Record::builder()
+					.level(Level::Warn)
+					.target(module_path!())
+					.args(format_args!("dropped {dropped} debug messages over the rate limit"))
+					.build(),
+			);
+		}
+		if admit {
+			self.log_in_context(record);
+		}
+	}
+
+	fn flush(&self) {
+		self.inner.flush();
+	}
+}
+
 thread_local! {
 	/// A reused buffer for building logs. Each log line is written in parts to this buffer before being emitted as a complete line to stderr.
 	///
 	/// Instead of requiring a separate `impl FnOnce` for every request and event to call in `LocalKey::with`, we take the buffer out and put it back when we're done. In case the buffer doesn't get put back for some reason, a usable but empty string is left in its place.
 	static BUFFER: Cell<String> = Cell::default();
+
+	/// The client being served, if any. See [`ClientContext::enter`].
+	static CLIENT: RefCell<Option<Rc<ClientContext>>> = RefCell::default();
+}
+
+/// What's known about a client, prefixed to every message logged while it's being served.
+#[derive(Debug)]
+pub struct ClientContext {
+	id: ClientId,
+	/// Process ID of the peer when it connected, from `SO_PEERCRED`
+	pid: Option<i32>,
+	/// App ID of the client's most recently named toplevel
+	app_id: RefCell<Option<Box<str>>>,
+}
+
+impl ClientContext {
+	pub fn new(id: ClientId, pid: Option<i32>) -> Rc<Self> {
+		Rc::new(Self { id, pid, app_id: RefCell::default() })
+	}
+
+	/// Attach this context to log messages and tracing spans until the guard is dropped.
+	pub fn enter(self: &Rc<Self>) -> ContextGuard {
+		let span = tracing::trace_span!("client", key = self.id.key(), pid = self.pid).entered();
+		let prev = CLIENT.with(|client| client.replace(Some(Rc::clone(self))));
+		ContextGuard { prev, _span: span }
+	}
+}
+
+impl Display for ClientContext {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "client {}", self.id.key())?;
+		if let Some(pid) = self.pid {
+			write!(f, " pid {pid}")?;
+		}
+		if let Some(app_id) = &*self.app_id.borrow() {
+			write!(f, " {app_id:?}")?;
+		}
+		Ok(())
+	}
+}
+
+/// Restores the previous [`ClientContext`] when dropped.
+#[must_use = "the context is left as soon as the guard is dropped"]
+pub struct ContextGuard {
+	prev: Option<Rc<ClientContext>>,
+	_span: tracing::span::EnteredSpan,
+}
+
+impl Drop for ContextGuard {
+	fn drop(&mut self) {
+		CLIENT.with(|client| *client.borrow_mut() = self.prev.take());
+	}
+}
+
+/// Name the client being served by the app ID it gave a toplevel, from now on.
+pub fn set_app_id(app_id: &str) {
+	CLIENT.with(|client| {
+		if let Some(client) = &*client.borrow() {
+			*client.app_id.borrow_mut() = Some(app_id.into());
+		}
+	});
 }
 
 pub fn log_request(interface_name: &'static str, request_name: &'static str, object_id: u32) -> Option<LogMessage> {
@@ -35,6 +216,13 @@ fn log_message(
 	if !*WAYLAND_DEBUG {
 		return None;
 	}
+	let (admit, dropped) = WAYLAND_DEBUG_LIMIT.admit();
+	if dropped > 0 {
+		let _ = writeln!(stderr().lock(), "[{dropped} messages dropped over the rate limit]");
+	}
+	if !admit {
+		return None;
+	}
 
 	let mut buffer = BUFFER.with(|cell| cell.take());
 	buffer.clear();
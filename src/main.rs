@@ -1,35 +1,59 @@
-use self::{
+use clap::Parser;
+use log::{debug, error, info, trace, warn};
+use myway::{
 	accept::Accept,
-	client::Client,
-	epoll::{Epoll, Event, EPOLLIN, EPOLLOUT},
-	signals::catch_sigint,
+	chrome_trace::ChromeLayer,
+	client::{Client, SendHalf},
+	clock,
+	epoll::{Epoll, Event, EPOLLERR, EPOLLHUP, EPOLLIN, EPOLLOUT, EPOLLRDHUP},
+	error::{self, ProtocolError, ServerError},
+	idle, logging,
+	object_impls::{self, Display},
+	protocol::Id,
+	signals::{catch_signals, read_signal},
+	state_dump,
+	supervisor::{Reaped, Supervisor},
+	windows,
 };
-use clap::Parser;
-use log::{debug, info, trace, warn};
+use nix::sys::{signal::Signal, signalfd::SignalFd};
 use slab::Slab;
 use std::{
 	io::{self, ErrorKind},
+	os::unix::io::RawFd,
+	panic::{self, AssertUnwindSafe},
 	path::PathBuf,
+	rc::Rc,
 	task::Poll,
+	time::Instant,
 };
-
-mod accept;
-mod client;
-mod epoll;
-mod logging;
-mod object_impls;
-mod object_map;
-mod protocol;
-mod shm;
-mod signals;
-mod windows;
+use tracing_subscriber::layer::SubscriberExt;
 
 /// Wayland compositor
 #[derive(Debug, Parser)]
 struct CliArgs {
-	/// Unix socket listener to bind on (default: $XDG_RUNTIME_DIR/wayland-0)
-	#[clap(long)]
+	/// Unix socket listener to bind on (default: the first free $XDG_RUNTIME_DIR/wayland-N)
+	#[clap(long, conflicts_with = "socket_fd")]
 	socket_path: Option<PathBuf>,
+	/// Listen on an inherited Unix socket that is already bound and listening, instead of binding one
+	#[clap(long)]
+	socket_fd: Option<RawFd>,
+	/// Record where time is spent handling each message to a file, in the Chrome trace format
+	#[clap(long)]
+	trace_output: Option<PathBuf>,
+	/// Take frame times from a file instead of the clock, one time in milliseconds per line or `+` and a time since
+	/// the previous frame, so timing bugs reproduce the same way every run
+	#[clap(long)]
+	clock_script: Option<PathBuf>,
+	/// Advertise the deprecated wl_shell global, for old clients that don't support xdg_shell
+	#[clap(long)]
+	wl_shell: bool,
+	/// Serve a single client, whose windows are fullscreen, and offer it the fullscreen shell
+	#[clap(long)]
+	kiosk: bool,
+	/// Run a client with `sh -c`, restarting it if it crashes. May be given several times; once the first client exits
+	/// successfully, so does the compositor
+	#[clap(long, value_name = "COMMAND")]
+	exec: Vec<String>,
 }
 
 /// Key (userdata) associated with the UnixListener in epoll
@@ -38,55 +62,154 @@ const ACCEPT_KEY: u64 = u64::MAX;
 const SIGNAL_KEY: u64 = u64::MAX - 1;
 
 fn main() -> io::Result<()> {
-	env_logger::init();
-	let CliArgs { socket_path } = CliArgs::parse();
-	let socket_path = match socket_path {
-		Some(path) => path,
-		None => {
+	logging::init();
+	let CliArgs { socket_path, socket_fd, trace_output, clock_script, wl_shell, kiosk, exec } = CliArgs::parse();
+	if wl_shell {
+		info!("advertising wl_shell");
+		object_impls::shell::enable();
+	}
+	if kiosk {
+		info!("running as a kiosk");
+		windows::enable_kiosk();
+	}
+	if let Some(path) = clock_script {
+		let script = clock::Script::parse(&std::fs::read_to_string(&path)?)
+			.map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))?;
+		info!("replaying frame times from {}", path.display());
+		clock::replay(script);
+	}
+	// the guard uninstalls the layer when main returns, which closes the trace
+	let _trace = match trace_output {
+		Some(path) => {
+			Some(tracing::subscriber::set_default(tracing_subscriber::registry().with(ChromeLayer::create(path)?)))
+		},
+		None => None,
+	};
+	let (accept, display) = match (socket_path, socket_fd) {
+		(_, Some(fd)) => {
+			// Safety: whoever started us handed the socket over, so nothing else in this process owns it
+			let accept = unsafe { Accept::from_raw_fd(fd)? };
+			let display = accept.path().unwrap_or_default().into_os_string();
+			(accept, display)
+		},
+		(Some(path), None) => {
+			let accept = Accept::bind(&path)?;
+			// clients take absolute paths in WAYLAND_DISPLAY as is
+			let display = std::env::current_dir()?.join(path).into_os_string();
+			(accept, display)
+		},
+		(None, None) => {
 			let dir = std::env::var_os("XDG_RUNTIME_DIR")
 				.ok_or_else(|| io::Error::new(ErrorKind::Other, "XDG_RUNTIME_DIR environment variable not set"))?;
-			let mut path = PathBuf::from(dir);
-			path.push("wayland-0");
-			path
+			let (accept, display) = Accept::bind_free(dir)?;
+			(accept, display.into())
 		},
 	};
 	let epoll = Epoll::new()?;
 
-	info!("listening at {}", socket_path.display());
-	let accept = Accept::bind(socket_path)?;
+	if display.is_empty() {
+		info!("listening on an unnamed socket");
+	} else {
+		info!("listening on display {}", display.to_string_lossy());
+		// for clients started by the compositor, and for scripts starting clients next to it
+		std::env::set_var("WAYLAND_DISPLAY", &display);
+		println!("WAYLAND_DISPLAY={}", display.to_string_lossy());
+	}
 	epoll.register(&accept, EPOLLIN, ACCEPT_KEY)?;
 	trace!("registered acceptor with epoll");
 
-	let sigfd = catch_sigint()?;
+	let mut sigfd = catch_signals()?;
 	epoll.register(&sigfd, EPOLLIN, SIGNAL_KEY)?;
 	trace!("registered signalfd with epoll");
 
+	// started once the socket is listening, for them to connect to
+	let now = Instant::now();
+	let mut supervisor = Supervisor::new(exec, now);
+	supervisor.restart_due(now);
+
 	let mut clients = Slab::new();
+	let res = panic::catch_unwind(AssertUnwindSafe(|| run(&epoll, &accept, &mut sigfd, &mut supervisor, &mut clients)));
+	if !matches!(res, Ok(Ok(()))) {
+		dump_state(&clients);
+	}
+	match res {
+		Ok(res) => res,
+		Err(payload) => panic::resume_unwind(payload),
+	}
+}
 
+/// Serve clients until SIGINT, or until the primary supervised client exits.
+fn run(
+	epoll: &Epoll,
+	accept: &Accept,
+	sigfd: &mut SignalFd,
+	supervisor: &mut Supervisor,
+	clients: &mut Slab<Client>,
+) -> io::Result<()> {
 	let mut events = [Event::empty(); 32];
 	'run: loop {
-		for event in epoll.wait_for_activity(&mut events, None)? {
+		let ready = epoll.wait_for_activity(&mut events, supervisor.next_restart(Instant::now()))?;
+		clock::begin_frame();
+		for event in ready {
 			match event.data() {
 				ACCEPT_KEY => {
 					while let Poll::Ready(sock) = accept.poll_accept()? {
+						if windows::kiosk() && !clients.is_empty() {
+							warn!("a kiosk serves one client at a time, rejecting another");
+							continue;
+						}
 						let entry = clients.vacant_entry();
 						let key = entry.key();
-						epoll.register(&sock, EPOLLIN | EPOLLOUT, key as u64)?;
+						epoll.register(&sock, EPOLLIN | EPOLLOUT | EPOLLRDHUP, key as u64)?;
 						trace!("registered socket with epoll (client key {key})");
-						entry.insert(Client::new(sock));
-						poll_client(&mut clients, key); // immediately poll until pending
+						entry.insert(Client::new(sock, key));
+						poll_client(clients, key); // immediately poll until pending
+					}
+				},
+				SIGNAL_KEY => {
+					while let Some(signal) = read_signal(sigfd)? {
+						match signal {
+							Signal::SIGINT => break 'run,
+							Signal::SIGUSR1 => dump_state(clients),
+							Signal::SIGCHLD => {
+								if supervisor.reap(Instant::now()) == Reaped::PrimaryExited {
+									debug!("exiting as the primary client did");
+									return Ok(());
+								}
+							},
+							other => debug!("ignoring unexpected signal {other}"),
+						}
 					}
 				},
-				SIGNAL_KEY => break 'run,
-				key => poll_client(&mut clients, key as usize),
+				// once both directions are closed, nothing the client sent can be answered anyway
+				key if event.events().intersects(EPOLLHUP | EPOLLERR) => {
+					if let Some(client) = clients.get(key as usize) {
+						let _log = client.log_context().enter();
+						info!("hung up");
+						disconnect(clients, key as usize);
+					}
+				},
+				// a half-closed socket is read until EOF, so requests sent before closing are still handled
+				key => poll_client(clients, key as usize),
 			}
 		}
+		send_queued(clients);
+		idle::update();
+		supervisor.restart_due(Instant::now());
 	}
 
 	debug!("exiting on SIGINT");
 	Ok(())
 }
 
+/// Write a [state dump](state_dump) and log where it went.
+fn dump_state(clients: &Slab<Client>) {
+	match state_dump::save(clients) {
+		Ok(path) => info!("wrote state dump to {}", path.display()),
+		Err(err) => error!("writing state dump failed: {err}"),
+	}
+}
+
 fn poll_client(clients: &mut Slab<Client>, key: usize) {
 	let client = match clients.get_mut(key) {
 		Some(c) => c,
@@ -95,41 +218,129 @@ fn poll_client(clients: &mut Slab<Client>, key: usize) {
 			return;
 		},
 	};
+	let _log = client.log_context().enter();
 	let (mut send, mut recv, objects) = client.split_mut();
 	loop {
 		let msg = match recv.poll_recv() {
 			Poll::Ready(Ok(req)) => req,
 			Poll::Ready(Err(err)) => {
-				warn!("client {key} errored, dropping connection: {err:?}");
-				clients.remove(key);
+				if err.kind() == ErrorKind::UnexpectedEof && !recv.has_partial_message() {
+					info!("disconnected");
+					// a client that only shut down its writing end still gets replies to what it sent before
+					let _ = send.poll_flush();
+				} else {
+					warn!("errored, dropping connection: {err:?}");
+				}
+				disconnect(clients, key);
 				return;
 			},
 			Poll::Pending => break,
 		};
-		match objects.dispatch_request(&mut send, msg) {
+		match catch_panic(|| objects.dispatch_request(&mut send, msg)) {
 			Ok(()) => (),
 			Err(err) => {
-				warn!("client {key} errored, dropping connection: {err:?}");
-				clients.remove(key);
+				if let ServerError::Panic(_) = err {
+					debug!("objects when it panicked: {objects:?}");
+				}
+				report_error(&mut send, err);
+				disconnect(clients, key);
 				return;
 			},
 		}
 	}
+	// the socket may have become writable, making room for queued events
+	if let Err(err) = catch_panic(|| client.send_queued()) {
+		report_error(&mut client.split_mut().0, err);
+		disconnect(clients, key);
+		return;
+	}
+	let (mut send, ..) = client.split_mut();
 	trace!("flushing buffers");
 	match send.poll_flush() {
 		Poll::Ready(Ok(())) => (),
 		Poll::Ready(Err(err)) => {
-			warn!("client {key} errored, dropping connection: {err:?}");
-			clients.remove(key);
+			warn!("errored, dropping connection: {err:?}");
+			disconnect(clients, key);
 		},
 		Poll::Pending => (),
 	}
 }
 
-fn cvt_poll<T, E: Into<io::Error>>(res: Result<T, E>) -> Poll<io::Result<T>> {
-	match res.map_err(E::into) {
-		Ok(x) => Poll::Ready(Ok(x)),
-		Err(err) if err.kind() == ErrorKind::WouldBlock => Poll::Pending,
-		Err(err) => Poll::Ready(Err(err)),
+/// Drop a client. Debug builds also check that nothing in the compositor still holds on to the state of its objects.
+fn disconnect(clients: &mut Slab<Client>, key: usize) {
+	let client = clients.remove(key);
+	if cfg!(debug_assertions) {
+		let log = Rc::clone(client.log_context());
+		let _log = log.enter();
+		for leak in client.close() {
+			error!("leaked state after disconnecting: {leak}");
+		}
+	} else {
+		drop(client);
+	}
+	// objects normally clean up after themselves, but not if a panic cut them short
+	windows::forget_unmapped();
+	object_impls::foreign::forget_dropped();
+}
+
+/// Send events queued for any client from outside request dispatch.
+fn send_queued(clients: &mut Slab<Client>) {
+	let mut failed = Vec::new();
+	for (key, client) in clients.iter_mut().filter(|(_, client)| client.has_queued()) {
+		let _log = client.log_context().enter();
+		if let Err(err) = catch_panic(|| client.send_queued()) {
+			report_error(&mut client.split_mut().0, err);
+			failed.push(key);
+		}
+	}
+	for key in failed {
+		disconnect(clients, key);
+	}
+}
+
+/// Log an error from serving a client and, if it was the client's fault, tell it why it's being disconnected.
+///
+/// Call this with the client's log context entered, which identifies the client in the messages.
+fn report_error(send: &mut SendHalf<'_>, err: ServerError) {
+	match err {
+		ServerError::Protocol(err) => {
+			warn!("violated the protocol, dropping connection: {err}");
+			post_error(send, err);
+		},
+		ServerError::InvalidRequest(err) => {
+			warn!("sent an invalid request, dropping connection: {err}");
+			post_error(send, err.into());
+		},
+		ServerError::Io(err) => warn!("errored, dropping connection: {err:?}"),
+		ServerError::Internal(msg) => panic!("internal error while serving client {}: {msg}", send.client_id().key()),
+		ServerError::Panic(msg) => {
+			error!("panicked while serving, dropping connection: {msg}");
+			post_error(send, ProtocolError::implementation("internal compositor error"));
+		},
+	}
+}
+
+/// Run `f`, turning a panic into [`ServerError::Panic`].
+///
+/// Recovering is fine because any error disconnects the client being served, dropping its objects along with
+/// whatever inconsistent state the panic left them in. State shared between clients only refers to their objects
+/// weakly, and [`disconnect`] forgets whatever of it the panic kept the objects from removing.
+fn catch_panic<R>(f: impl FnOnce() -> error::Result<R>) -> error::Result<R> {
+	panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| Err(ServerError::panic(payload)))
+}
+
+/// Send `wl_display.error` to a client and flush it.
+///
+/// This is best effort: the client is about to be disconnected regardless of whether it receives the error.
+fn post_error(send: &mut SendHalf<'_>, err: ProtocolError) {
+	let display = Id::new(1).unwrap();
+	let object = err.object.unwrap_or_else(|| display.cast());
+	let res =
+		Display.send_error(display, send, object, err.code, &err.message).and_then(|()| match send.poll_flush() {
+			Poll::Ready(Err(err)) => Err(err.into()),
+			Poll::Ready(Ok(())) | Poll::Pending => Ok(()),
+		});
+	if let Err(err) = res {
+		debug!("sending wl_display.error failed: {err}");
 	}
 }
@@ -0,0 +1,113 @@
+use super::window::Surface;
+use crate::{
+	activation,
+	client::SendHalf,
+	error::{ProtocolError, Result},
+	object_map::{OccupiedEntry, VacantEntry},
+	protocol::{
+		xdg_activation_token_v1::{self, XdgActivationTokenV1},
+		xdg_activation_v1::XdgActivationV1,
+		AnyObject, Id,
+	},
+	windows::Mapping,
+};
+use log::{debug, info};
+
+#[derive(Debug)]
+pub struct ActivationGlobal;
+
+impl XdgActivationV1 for ActivationGlobal {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_get_activation_token(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		id: VacantEntry<'_, TokenObject>,
+	) -> Result<()> {
+		let token = id.id();
+		id.insert(TokenObject { id: token, app_id: None, surface: None, committed: false });
+		Ok(())
+	}
+
+	fn handle_activate(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		token: &str,
+		surface: OccupiedEntry<'_, Surface>,
+	) -> Result<()> {
+		info!("xdg_activation_v1.activate(token={token:?}, surface={})", surface.id());
+		// unknown tokens are ignored, as the client can't tell whether the one it was given is any good
+		if !activation::redeem(token) {
+			debug!("activation token {token:?} isn't valid, not activating surface {}", surface.id());
+		} else if surface.mapping().raise() {
+			debug!("surface {} activated, and focused", surface.id());
+		} else {
+			debug!("surface {} isn't mapped, so there's nothing to activate", surface.id());
+		}
+		Ok(())
+	}
+}
+
+/// An `xdg_activation_token_v1`, collecting what a token is for until it's committed.
+#[derive(Debug)]
+pub struct TokenObject {
+	id: Id<TokenObject>,
+	app_id: Option<Box<str>>,
+	/// Whether the surface asking for the token is mapped, as it has to have focus to pass it on
+	surface: Option<Mapping>,
+	committed: bool,
+}
+
+impl TokenObject {
+	/// Fail requests that come after the commit.
+	fn check_pending(&self) -> Result<()> {
+		if self.committed {
+			let message = "activation token was already committed";
+			return Err(ProtocolError::new(xdg_activation_token_v1::Error::AlreadyUsed, message).into());
+		}
+		Ok(())
+	}
+}
+
+impl XdgActivationTokenV1 for TokenObject {
+	fn handle_set_serial(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		_serial: u32,
+		_seat: OccupiedEntry<'_, AnyObject>,
+	) -> Result<()> {
+		// there are no seats to send input events with serials, so the focused surface stands in for them
+		self.check_pending()
+	}
+
+	fn handle_set_app_id(&mut self, _client: &mut SendHalf<'_>, app_id: &str) -> Result<()> {
+		self.check_pending()?;
+		self.app_id = Some(app_id.into());
+		Ok(())
+	}
+
+	fn handle_set_surface(&mut self, _client: &mut SendHalf<'_>, surface: OccupiedEntry<'_, Surface>) -> Result<()> {
+		self.check_pending()?;
+		self.surface = Some(surface.mapping().clone());
+		Ok(())
+	}
+
+	fn handle_commit(&mut self, client: &mut SendHalf<'_>) -> Result<()> {
+		self.check_pending()?;
+		self.committed = true;
+		// only the focused window may pass focus on, so that clients can't steal it
+		let token = if self.surface.as_ref().map_or(false, Mapping::has_focus) {
+			activation::issue(self.app_id.as_deref())
+		} else {
+			debug!("refusing activation token, as the surface asking for it doesn't have focus");
+			activation::refuse()
+		};
+		self.send_done(self.id, client, &token)
+	}
+
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+}
@@ -0,0 +1,74 @@
+use super::window::Surface;
+use crate::{
+	client::SendHalf,
+	error::{ProtocolError, Result},
+	object_map::{OccupiedEntry, VacantEntry},
+	protocol::{
+		wp_alpha_modifier_surface_v1::{self, WpAlphaModifierSurfaceV1},
+		wp_alpha_modifier_v1::{self, WpAlphaModifierV1},
+	},
+};
+use log::info;
+use std::{
+	cell::Cell,
+	rc::{Rc, Weak},
+};
+
+#[derive(Debug)]
+pub struct AlphaModifierManager;
+
+impl WpAlphaModifierV1 for AlphaModifierManager {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_get_surface(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		id: VacantEntry<'_, AlphaModifierObject>,
+		surface: OccupiedEntry<'_, Surface>,
+	) -> Result<()> {
+		info!("wp_alpha_modifier_v1.get_surface(id={}, surface={})", id.id(), surface.id());
+		let multiplier = surface.alpha_multiplier();
+		if multiplier.get().is_some() {
+			return Err(ProtocolError::new(
+				wp_alpha_modifier_v1::Error::AlreadyConstructed,
+				"wl_surface already has an alpha modifier object",
+			)
+			.into());
+		}
+		multiplier.set(Some(u32::MAX));
+		id.insert(AlphaModifierObject(Rc::downgrade(multiplier)));
+		Ok(())
+	}
+}
+
+/// A `wp_alpha_modifier_surface_v1`, holding the pending alpha multiplier of its surface.
+///
+/// Unlike other per-surface hints, the protocol makes using this object after its surface is destroyed an error, so it
+/// only holds a weak reference to tell.
+#[derive(Debug)]
+pub struct AlphaModifierObject(Weak<Cell<Option<u32>>>);
+
+impl AlphaModifierObject {
+	fn multiplier(&self) -> Result<Rc<Cell<Option<u32>>>> {
+		self.0.upgrade().ok_or_else(|| {
+			ProtocolError::new(wp_alpha_modifier_surface_v1::Error::NoSurface, "wl_surface was destroyed").into()
+		})
+	}
+}
+
+impl WpAlphaModifierSurfaceV1 for AlphaModifierObject {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		// the surface may be gone already, which a destructor can't be faulted for
+		if let Some(multiplier) = self.0.upgrade() {
+			multiplier.set(None);
+		}
+		Ok(())
+	}
+
+	fn handle_set_multiplier(&mut self, _client: &mut SendHalf<'_>, factor: u32) -> Result<()> {
+		self.multiplier()?.set(Some(factor));
+		Ok(())
+	}
+}
@@ -0,0 +1,74 @@
+//! Checks on surface state that can only be judged on commit, once every request contributing to it has been seen.
+//!
+//! Each check fails with the error of the interface whose request set the offending state, which is not necessarily
+//! `wl_surface` even though the error is found while handling `wl_surface.commit`.
+
+use super::shm::ShmBuffer;
+use crate::{
+	error::{ProtocolError, Result},
+	protocol::{wl_surface, xdg_surface, xdg_toplevel},
+	windows::{ConfigureSequence, ConfigureState, ToplevelRole, WindowRole},
+};
+use log::debug;
+
+/// Surface version from which a buffer size that isn't a multiple of the scale is an error instead of just logged.
+///
+/// Clients written before the rule was spelled out break it, cursor themes at scale 2 being the usual case, and they
+/// still render fine by rounding.
+const STRICT_BUFFER_SIZE_SINCE: u32 = 6;
+
+/// Require the buffer to cover a whole number of surface-local pixels at the given scale.
+pub fn buffer_size(version: u32, buffer: Option<&ShmBuffer>, scale: i32) -> Result<()> {
+	let buffer = match buffer {
+		Some(buffer) => buffer,
+		None => return Ok(()),
+	};
+	// scale was validated to be positive when set
+	if buffer.size.is_multiple_of(scale) {
+		return Ok(());
+	}
+	let message = format!("buffer size {} is not a multiple of the buffer scale {scale}", buffer.size);
+	if version < STRICT_BUFFER_SIZE_SINCE {
+		debug!("{message}, allowed for wl_surface version {version}");
+		return Ok(());
+	}
+	Err(ProtocolError::new(wl_surface::Error::InvalidSize, message).into())
+}
+
+/// Require the pending minimum size of a toplevel to be no larger than its pending maximum size, where both are set.
+pub fn size_limits(toplevel: &ToplevelRole) -> Result<()> {
+	let limits = toplevel.pending_size_limits;
+	let (min, max) = (limits.min, limits.max);
+	for (axis, min, max) in [("width", min.width, max.width), ("height", min.height, max.height)] {
+		if min > 0 && max > 0 && min > max {
+			return Err(ProtocolError::new(
+				xdg_toplevel::Error::InvalidSize,
+				format!("minimum {axis} {min} is larger than maximum {axis} {max}"),
+			)
+			.on(toplevel.id)
+			.into());
+		}
+	}
+	Ok(())
+}
+
+/// Require an `xdg_surface` to have a role object, and to have acked a configure before a buffer is attached to it.
+/// Returns whether this is the initial commit, which the compositor answers with the first configure.
+pub fn configure_sequence(sequence: &ConfigureSequence, role: &WindowRole, attached: bool) -> Result<bool> {
+	if let WindowRole::Unassigned = role {
+		return Err(ProtocolError::new(
+			xdg_surface::Error::NotConstructed,
+			"xdg_surface committed without a role object",
+		)
+		.on(sequence.id)
+		.into());
+	}
+	if attached && sequence.state != ConfigureState::Configured {
+		let message = match sequence.state {
+			ConfigureState::AwaitingInitialCommit => "buffer attached before the initial commit",
+			_ => "buffer attached before acking a configure",
+		};
+		return Err(ProtocolError::new(xdg_surface::Error::UnconfiguredBuffer, message).on(sequence.id).into());
+	}
+	Ok(sequence.state == ConfigureState::AwaitingInitialCommit)
+}
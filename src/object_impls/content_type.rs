@@ -0,0 +1,59 @@
+use super::window::Surface;
+use crate::{
+	client::SendHalf,
+	error::{ProtocolError, Result},
+	object_map::{OccupiedEntry, VacantEntry},
+	protocol::{
+		wp_content_type_manager_v1::{self, WpContentTypeManagerV1},
+		wp_content_type_v1::{Type, WpContentTypeV1},
+	},
+};
+use log::info;
+use std::{cell::Cell, rc::Rc};
+
+#[derive(Debug)]
+pub struct ContentTypeManager;
+
+impl WpContentTypeManagerV1 for ContentTypeManager {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_get_surface_content_type(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		id: VacantEntry<'_, ContentTypeObject>,
+		surface: OccupiedEntry<'_, Surface>,
+	) -> Result<()> {
+		info!("wp_content_type_manager_v1.get_surface_content_type(id={}, surface={})", id.id(), surface.id());
+		let hint = surface.content_type_hint();
+		if hint.get().is_some() {
+			return Err(ProtocolError::new(
+				wp_content_type_manager_v1::Error::AlreadyConstructed,
+				"wl_surface already has a content type object",
+			)
+			.into());
+		}
+		hint.set(Some(Type::None));
+		id.insert(ContentTypeObject(hint.clone()));
+		Ok(())
+	}
+}
+
+/// A `wp_content_type_v1`, holding the pending content type of its surface.
+///
+/// If the surface is destroyed first, the shared hint is no longer read and this object is inert.
+#[derive(Debug)]
+pub struct ContentTypeObject(Rc<Cell<Option<Type>>>);
+
+impl WpContentTypeV1 for ContentTypeObject {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		self.0.set(None);
+		Ok(())
+	}
+
+	fn handle_set_content_type(&mut self, _client: &mut SendHalf<'_>, content_type: Type) -> Result<()> {
+		self.0.set(Some(content_type));
+		Ok(())
+	}
+}
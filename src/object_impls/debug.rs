@@ -0,0 +1,63 @@
+//! `myway_debug_v1`, the in-tree protocol through which tests and tools inspect the state kept for their client.
+
+use super::window::Surface;
+use crate::{
+	client::SendHalf,
+	error::Result,
+	object_map::{OccupiedEntry, VacantEntry},
+	protocol::{
+		myway_debug_object_list_v1::MywayDebugObjectListV1, myway_debug_surface_stats_v1::MywayDebugSurfaceStatsV1,
+		myway_debug_v1::MywayDebugV1,
+	},
+};
+use log::info;
+
+#[derive(Debug)]
+pub struct DebugGlobal;
+
+impl MywayDebugV1 for DebugGlobal {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_list_objects(&mut self, client: &mut SendHalf<'_>, id: VacantEntry<'_, ObjectList>) -> Result<()> {
+		info!("myway_debug_v1.list_objects(id={})", id.id());
+		let list = id.insert(ObjectList);
+		// request handlers only see the objects named in the request, so the list is sent with the queued events
+		client.object_ref(list.id()).queue_with_objects(|objects, id, client| {
+			let listed: Vec<_> = objects.iter().map(|(id, object)| (u32::from(id), object.interface())).collect();
+			objects.destroy(id, client, |list, id, client| {
+				for (object, interface) in listed {
+					list.send_object(id, client, object, interface)?;
+				}
+				list.send_done(id, client)
+			})
+		});
+		Ok(())
+	}
+
+	fn handle_get_surface_stats(
+		&mut self,
+		client: &mut SendHalf<'_>,
+		id: VacantEntry<'_, SurfaceStats>,
+		surface: OccupiedEntry<'_, Surface>,
+	) -> Result<()> {
+		info!("myway_debug_v1.get_surface_stats(id={}, surface={})", id.id(), surface.id());
+		let (commits, last_commit) = surface.commit_stats();
+		let last_commit = last_commit.map_or(0, |time| time.millis());
+		let stats_id = id.id();
+		id.insert(SurfaceStats).take().send_done(stats_id, client, (commits >> 32) as u32, commits as u32, last_commit)
+	}
+}
+
+/// A `myway_debug_object_list_v1`, alive until the list has been sent.
+#[derive(Debug)]
+pub struct ObjectList;
+
+impl MywayDebugObjectListV1 for ObjectList {}
+
+/// A `myway_debug_surface_stats_v1`, destroyed by the event sent as soon as it's created.
+#[derive(Debug)]
+pub struct SurfaceStats;
+
+impl MywayDebugSurfaceStatsV1 for SurfaceStats {}
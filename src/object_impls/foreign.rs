@@ -0,0 +1,176 @@
+//! `xdg-foreign`: referencing toplevels across clients through exported handles.
+//!
+//! Exported handles are random tokens registered in a compositor-wide table for as long as their `zxdg_exported_v2`
+//! exists. Importing a handle resolves it once; the imported object then tracks the toplevel directly.
+
+use super::window::Surface;
+use crate::{
+	client::SendHalf,
+	error::{ProtocolError, Result},
+	object_map::{OccupiedEntry, VacantEntry},
+	protocol::{
+		zxdg_exported_v2::ZxdgExportedV2,
+		zxdg_exporter_v2::{self, ZxdgExporterV2},
+		zxdg_imported_v2::{self, ZxdgImportedV2},
+		zxdg_importer_v2::ZxdgImporterV2,
+	},
+	windows::{self, WindowRole},
+};
+use log::info;
+use std::{
+	cell::RefCell,
+	collections::HashMap,
+	fmt::Write as _,
+	fs::File,
+	io::Read,
+	rc::{Rc, Weak},
+};
+
+thread_local! {
+	/// Toplevels exported by any client, by handle.
+	static EXPORTS: RefCell<HashMap<Box<str>, Weak<RefCell<WindowRole>>>> = RefCell::default();
+}
+
+/// Forget handles of toplevels that were dropped without their [`Exported`] removing them, which only happens when a
+/// panic interrupts an export. Dropped toplevels can't be imported either way, but this keeps the handles from
+/// piling up.
+pub fn forget_dropped() {
+	EXPORTS.with(|exports| exports.borrow_mut().retain(|_, toplevel| toplevel.strong_count() > 0));
+}
+
+/// Length of exported handles, in random bytes.
+const HANDLE_BYTES: usize = 16;
+
+/// Generate a handle that can't be guessed by other clients.
+///
+/// Failing to read random bytes is the compositor's fault, not the client's, so it's reported as an implementation
+/// error rather than an I/O error on the client's connection.
+fn random_handle() -> Result<Box<str>> {
+	let mut bytes = [0; HANDLE_BYTES];
+	File::open("/dev/urandom")
+		.and_then(|mut file| file.read_exact(&mut bytes))
+		.map_err(|err| ProtocolError::implementation(format!("failed to generate an export handle: {err}")))?;
+	let mut handle = String::with_capacity(HANDLE_BYTES * 2);
+	for byte in bytes {
+		write!(handle, "{byte:02x}").unwrap();
+	}
+	Ok(handle.into())
+}
+
+/// The toplevel role of `surface`, or `None` if it isn't an `xdg_toplevel`.
+fn toplevel_of(surface: &Surface) -> Option<&Rc<RefCell<WindowRole>>> {
+	surface.role().filter(|role| matches!(*role.borrow(), WindowRole::Toplevel(_)))
+}
+
+#[derive(Debug)]
+pub struct Exporter;
+
+impl ZxdgExporterV2 for Exporter {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_export_toplevel(
+		&mut self,
+		client: &mut SendHalf<'_>,
+		id: VacantEntry<'_, Exported>,
+		surface: OccupiedEntry<'_, Surface>,
+	) -> Result<()> {
+		info!("zxdg_exporter_v2.export_toplevel(id={}, surface={})", id.id(), surface.id());
+		let toplevel = toplevel_of(&surface).ok_or_else(|| {
+			ProtocolError::new(zxdg_exporter_v2::Error::InvalidSurface, "only xdg_toplevel surfaces can be exported")
+		})?;
+		let handle = loop {
+			let handle = random_handle()?;
+			if EXPORTS.with(|exports| !exports.borrow().contains_key(&handle)) {
+				break handle;
+			}
+		};
+		EXPORTS.with(|exports| exports.borrow_mut().insert(handle.clone(), Rc::downgrade(toplevel)));
+		let exported = id.insert(Exported { handle });
+		exported.send_handle(exported.id(), client, &exported.handle)
+	}
+}
+
+/// A `zxdg_exported_v2`. The handle stays valid until this object is destroyed, including by its client
+/// disconnecting.
+#[derive(Debug)]
+pub struct Exported {
+	handle: Box<str>,
+}
+
+impl ZxdgExportedV2 for Exported {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+}
+
+impl Drop for Exported {
+	fn drop(&mut self) {
+		EXPORTS.with(|exports| exports.borrow_mut().remove(&self.handle));
+	}
+}
+
+#[derive(Debug)]
+pub struct Importer;
+
+impl ZxdgImporterV2 for Importer {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_import_toplevel(
+		&mut self,
+		client: &mut SendHalf<'_>,
+		id: VacantEntry<'_, Imported>,
+		handle: &str,
+	) -> Result<()> {
+		info!("zxdg_importer_v2.import_toplevel(id={}, handle={handle:?})", id.id());
+		let toplevel = EXPORTS.with(|exports| exports.borrow().get(handle).cloned()).filter(|tl| tl.strong_count() > 0);
+		let imported = id.insert(Imported { toplevel: toplevel.clone().unwrap_or_default(), children: Vec::new() });
+		match toplevel {
+			Some(_) => Ok(()),
+			None => imported.send_destroyed(imported.id(), client),
+		}
+	}
+}
+
+/// A `zxdg_imported_v2`, referencing a toplevel of another client.
+#[derive(Debug)]
+pub struct Imported {
+	toplevel: Weak<RefCell<WindowRole>>,
+	/// Toplevels that were made children of the imported toplevel through this object
+	children: Vec<Weak<RefCell<WindowRole>>>,
+}
+
+impl ZxdgImportedV2 for Imported {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		for child in self.children.iter().filter_map(Weak::upgrade) {
+			if let WindowRole::Toplevel(child) = &mut *child.borrow_mut() {
+				if child.parent.as_ref().map_or(false, |parent| parent.ptr_eq(&self.toplevel)) {
+					child.parent = None;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn handle_set_parent_of(&mut self, _client: &mut SendHalf<'_>, surface: OccupiedEntry<'_, Surface>) -> Result<()> {
+		let child = toplevel_of(&surface).ok_or_else(|| {
+			ProtocolError::new(zxdg_imported_v2::Error::InvalidSurface, "only xdg_toplevel surfaces can have parents")
+		})?;
+		if windows::would_cycle(child, &self.toplevel) {
+			return Err(ProtocolError::new(
+				zxdg_imported_v2::Error::InvalidSurface,
+				"a toplevel can't be its own parent or the parent of its ancestors",
+			)
+			.into());
+		}
+		if let WindowRole::Toplevel(role) = &mut *child.borrow_mut() {
+			role.parent = Some(self.toplevel.clone());
+		}
+		self.children.retain(|child| child.strong_count() > 0);
+		self.children.push(Rc::downgrade(child));
+		Ok(())
+	}
+}
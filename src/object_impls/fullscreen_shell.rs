@@ -0,0 +1,101 @@
+//! `zwp_fullscreen_shell_v1`, for clients that present one surface at a time rather than manage windows. It's only
+//! advertised in [kiosk mode](crate::windows::enable_kiosk).
+//!
+//! There are no outputs yet, so every surface is presented on all of them, and mode switches always fail. A presented
+//! surface is mapped like a toplevel, once it commits a buffer, and unmapped when another surface replaces it.
+
+use super::window::Surface;
+use crate::{
+	client::SendHalf,
+	error::Result,
+	object_map::{OccupiedEntry, VacantEntry},
+	protocol::{
+		zwp_fullscreen_shell_mode_feedback_v1::ZwpFullscreenShellModeFeedbackV1,
+		zwp_fullscreen_shell_v1::{self, PresentMethod, ZwpFullscreenShellV1},
+		AnyObject, Id,
+	},
+	windows::{Mapping, RoleKind, ToplevelRole, WindowRole},
+};
+use log::{debug, info};
+use std::{cell::RefCell, rc::Rc};
+
+#[derive(Debug)]
+pub struct FullscreenShell {
+	/// This object, which role errors are posted on
+	id: Id<FullscreenShell>,
+	/// Window role of the surface presented last, and whether it's mapped
+	presented: Option<(Rc<RefCell<WindowRole>>, Mapping)>,
+}
+
+impl FullscreenShell {
+	pub fn new(id: Id<FullscreenShell>) -> Self {
+		Self { id, presented: None }
+	}
+}
+
+impl ZwpFullscreenShellV1 for FullscreenShell {
+	fn handle_release(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_present_surface(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		surface: Option<OccupiedEntry<'_, Surface>>,
+		_method: PresentMethod,
+		_output: Option<OccupiedEntry<'_, AnyObject>>,
+	) -> Result<()> {
+		info!("zwp_fullscreen_shell_v1.present_surface(surface={:?})", surface.as_ref().map(|surface| surface.id()));
+		let presented = match surface {
+			Some(mut surface) => {
+				let role = match surface.role() {
+					// presented before, so it still has the role object from back then
+					Some(role) if surface.assigned_role() == Some(RoleKind::FullscreenShellSurface) => role.clone(),
+					_ => surface.give_window_role(
+						RoleKind::FullscreenShellSurface,
+						self.id,
+						zwp_fullscreen_shell_v1::Error::Role,
+					)?,
+				};
+				Some((role, surface.mapping().clone()))
+			},
+			None => None,
+		};
+
+		if let Some((previous, mapping)) = self.presented.take() {
+			if !presented.as_ref().map_or(false, |(role, _)| Rc::ptr_eq(role, &previous)) {
+				*previous.borrow_mut() = WindowRole::Unassigned;
+				if mapping.unmap() {
+					debug!("presented surface unmapped by presenting another in its place");
+				}
+			}
+		}
+		if let Some((role, _)) = &presented {
+			let mut role = role.borrow_mut();
+			if matches!(*role, WindowRole::Unassigned) {
+				*role = WindowRole::Toplevel(ToplevelRole::new(self.id.cast()));
+			}
+		}
+		self.presented = presented;
+		Ok(())
+	}
+
+	fn handle_present_surface_for_mode(
+		&mut self,
+		client: &mut SendHalf<'_>,
+		_surface: OccupiedEntry<'_, Surface>,
+		_output: OccupiedEntry<'_, AnyObject>,
+		_framerate: i32,
+		feedback: VacantEntry<'_, ModeFeedback>,
+	) -> Result<()> {
+		// there are no outputs whose mode could be switched, which leaves the previous surface presented
+		let id = feedback.id();
+		feedback.insert(ModeFeedback).take().send_mode_failed(id, client)
+	}
+}
+
+/// A `zwp_fullscreen_shell_mode_feedback_v1`, which only lives until it's told how a mode switch went.
+#[derive(Debug)]
+pub struct ModeFeedback;
+
+impl ZwpFullscreenShellModeFeedbackV1 for ModeFeedback {}
@@ -0,0 +1,43 @@
+use super::window::Surface;
+use crate::{
+	client::SendHalf,
+	error::Result,
+	idle::Inhibitor,
+	object_map::{OccupiedEntry, VacantEntry},
+	protocol::{zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1},
+};
+use log::info;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub struct IdleInhibitManager;
+
+impl ZwpIdleInhibitManagerV1 for IdleInhibitManager {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_create_inhibitor(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		id: VacantEntry<'_, IdleInhibitorObject>,
+		surface: OccupiedEntry<'_, Surface>,
+	) -> Result<()> {
+		info!("zwp_idle_inhibit_manager_v1.create_inhibitor(id={}, surface={})", id.id(), surface.id());
+		id.insert(IdleInhibitorObject { _inhibitor: Inhibitor::new(surface.visible().clone()) });
+		Ok(())
+	}
+}
+
+/// A `zwp_idle_inhibitor_v1`, keeping the session awake while its surface is mapped until it's destroyed.
+#[derive(Debug)]
+pub struct IdleInhibitorObject {
+	/// Only held, as dropping it is what lifts the inhibition
+	_inhibitor: Rc<Inhibitor>,
+}
+
+impl ZwpIdleInhibitorV1 for IdleInhibitorObject {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+}
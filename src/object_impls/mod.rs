@@ -1,13 +1,37 @@
 use crate::{
 	client::SendHalf,
-	object_impls::window::{Compositor, WindowManager},
-	object_map::VacantEntry,
+	error::{ProtocolError, Result, ServerError},
+	object_impls::{
+		activation::ActivationGlobal,
+		alpha_modifier::AlphaModifierManager,
+		content_type::ContentTypeManager,
+		debug::DebugGlobal,
+		foreign::{Exporter, Importer},
+		fullscreen_shell::FullscreenShell,
+		idle_inhibit::IdleInhibitManager,
+		shell::Shell,
+		tearing_control::TearingControlManager,
+		window::{Compositor, WindowManager},
+	},
+	object_map::{ObjectDyn, VacantEntry},
 	protocol::{wl_callback::WlCallback, wl_display::WlDisplay, wl_registry::WlRegistry, AnyObject, Id},
+	windows,
 };
-use log::info;
-use std::io::{Error, ErrorKind, Result};
+use log::{info, warn};
+use std::{any::Any, cell::RefCell, rc::Weak};
 
+pub mod activation;
+pub mod alpha_modifier;
+mod commit;
+pub mod content_type;
+pub mod debug;
+pub mod foreign;
+pub mod fullscreen_shell;
+pub mod idle_inhibit;
+pub mod shell;
 pub mod shm;
+pub mod tearing_control;
+mod validate;
 pub mod window;
 
 #[derive(Debug)]
@@ -32,14 +56,76 @@ pub struct Callback;
 
 impl WlCallback for Callback {}
 
+/// Globals advertised to every client, as `(interface, version)`, unless [disabled](advertised). The global's name is
+/// its index.
+///
+/// Versions are those of the generated bindings, so advertising a newer version only takes updating the protocol XML
+/// once the implementation supports it.
+const GLOBALS: &[(&str, u32)] = &[
+	(shm::ShmGlobal::INTERFACE, shm::ShmGlobal::VERSION),
+	(Compositor::INTERFACE, Compositor::VERSION),
+	(WindowManager::INTERFACE, WindowManager::VERSION),
+	(ContentTypeManager::INTERFACE, ContentTypeManager::VERSION),
+	(TearingControlManager::INTERFACE, TearingControlManager::VERSION),
+	(Exporter::INTERFACE, Exporter::VERSION),
+	(Importer::INTERFACE, Importer::VERSION),
+	(IdleInhibitManager::INTERFACE, IdleInhibitManager::VERSION),
+	(AlphaModifierManager::INTERFACE, AlphaModifierManager::VERSION),
+	(ActivationGlobal::INTERFACE, ActivationGlobal::VERSION),
+	(DebugGlobal::INTERFACE, DebugGlobal::VERSION),
+	(Shell::INTERFACE, Shell::VERSION),
+	(FullscreenShell::INTERFACE, FullscreenShell::VERSION),
+];
+
+/// Whether the global implementing `interface` is advertised. The deprecated `wl_shell` is only once
+/// [enabled](shell::enable), and `zwp_fullscreen_shell_v1` only in [kiosk mode](windows::enable_kiosk).
+fn advertised(interface: &str) -> bool {
+	match interface {
+		Shell::INTERFACE => shell::enabled(),
+		FullscreenShell::INTERFACE => windows::kiosk(),
+		_ => true,
+	}
+}
+
+/// A global implemented with [`ObjectDyn`], added with [`register_global`].
+#[derive(Copy, Clone, Debug)]
+struct DynGlobal {
+	interface: &'static str,
+	version: u32,
+	bind: fn(u32) -> Box<dyn ObjectDyn>,
+}
+
+thread_local! {
+	/// Globals added at runtime. Their names follow those of [`GLOBALS`].
+	static DYN_GLOBALS: RefCell<Vec<DynGlobal>> = RefCell::default();
+}
+
+/// Advertise a global implemented outside the generated bindings, to clients that get the registry afterwards.
+///
+/// `bind` creates the object when a client binds the global, given the version it was bound at.
+pub fn register_global(interface: &'static str, version: u32, bind: fn(u32) -> Box<dyn ObjectDyn>) {
+	DYN_GLOBALS.with(|globals| globals.borrow_mut().push(DynGlobal { interface, version, bind }));
+}
+
+/// State of `object` that can be referenced from outside its client's object map, to check that nothing keeps it alive
+/// once the client is gone.
+pub fn shared_state(object: &AnyObject) -> Option<Weak<dyn Any>> {
+	shm::shared_state(object).or_else(|| window::shared_state(object)).or_else(|| shell::shared_state(object))
+}
+
 #[derive(Debug)]
 pub struct Registry;
 
 impl Registry {
 	fn send_globals(&self, self_id: Id<Self>, client: &mut SendHalf<'_>) -> Result<()> {
-		self.send_global(self_id, client, 0, "wl_shm", 1)?;
-		self.send_global(self_id, client, 1, "wl_compositor", 5)?;
-		self.send_global(self_id, client, 2, "xdg_wm_base", 5)?;
+		let dyn_globals = DYN_GLOBALS.with(|globals| globals.borrow().clone());
+		let dyn_globals = dyn_globals.iter().map(|global| (global.interface, global.version));
+		for (name, (interface, version)) in (0..).zip(GLOBALS.iter().copied().chain(dyn_globals)) {
+			if !advertised(interface) {
+				continue;
+			}
+			self.send_global(self_id, client, name, interface, version)?;
+		}
 		Ok(())
 	}
 }
@@ -54,23 +140,93 @@ impl WlRegistry for Registry {
 		id: VacantEntry<'_, AnyObject>,
 	) -> Result<()> {
 		info!("wl_registry.bind(name={name:?}, interface={interface:?}, version={version:?}, id={:?})", id.id());
-		match (name, interface, version) {
-			(0, "wl_shm", 1) => {
+		let dyn_global = (name as usize)
+			.checked_sub(GLOBALS.len())
+			.and_then(|index| DYN_GLOBALS.with(|globals| globals.borrow().get(index).copied()));
+		let global =
+			GLOBALS.get(name as usize).copied().or(dyn_global.map(|global| (global.interface, global.version)));
+		match global.filter(|&(global, _)| advertised(global)) {
+			Some((global, max_version)) if global == interface && (1..=max_version).contains(&version) => (),
+			_ => {
+				return Err(ProtocolError::invalid_object(format!(
+					"cannot bind global #{name} as {interface} v{version}"
+				))
+				.into())
+			},
+		}
+		if let Some(global) = dyn_global {
+			let object = (global.bind)(version);
+			if object.interface() != interface {
+				return Err(ServerError::Internal(format!(
+					"global {interface} created an object implementing {}",
+					object.interface()
+				)));
+			}
+			id.downcast().insert(object);
+			return Ok(());
+		}
+		match interface {
+			shm::ShmGlobal::INTERFACE => {
 				let shm = id.downcast().insert(shm::ShmGlobal);
 				shm.send_formats(shm.id(), client)
 			},
-			(1, "wl_compositor", 5) => {
-				id.downcast().insert(Compositor);
+			Compositor::INTERFACE => {
+				id.downcast().insert(Compositor { version });
+				Ok(())
+			},
+			WindowManager::INTERFACE => {
+				let id = id.downcast();
+				let wm_base = id.id();
+				id.insert(WindowManager { id: wm_base, version });
+				Ok(())
+			},
+			ContentTypeManager::INTERFACE => {
+				id.downcast().insert(ContentTypeManager);
+				Ok(())
+			},
+			TearingControlManager::INTERFACE => {
+				id.downcast().insert(TearingControlManager);
+				Ok(())
+			},
+			Exporter::INTERFACE => {
+				id.downcast().insert(Exporter);
+				Ok(())
+			},
+			Importer::INTERFACE => {
+				id.downcast().insert(Importer);
+				Ok(())
+			},
+			IdleInhibitManager::INTERFACE => {
+				id.downcast().insert(IdleInhibitManager);
+				Ok(())
+			},
+			AlphaModifierManager::INTERFACE => {
+				id.downcast().insert(AlphaModifierManager);
+				Ok(())
+			},
+			ActivationGlobal::INTERFACE => {
+				id.downcast().insert(ActivationGlobal);
+				Ok(())
+			},
+			DebugGlobal::INTERFACE => {
+				id.downcast().insert(DebugGlobal);
+				Ok(())
+			},
+			Shell::INTERFACE => {
+				warn!("client bound the deprecated wl_shell, which only has basic support");
+				let id = id.downcast();
+				let shell = id.id();
+				id.insert(Shell { id: shell });
 				Ok(())
 			},
-			(2, "xdg_wm_base", 5) => {
-				id.downcast().insert(WindowManager);
+			FullscreenShell::INTERFACE => {
+				let id = id.downcast();
+				let shell = id.id();
+				// no capabilities: there are no output modes to choose from, nor a cursor plane
+				id.insert(FullscreenShell::new(shell));
 				Ok(())
 			},
-			_ => Err(Error::new(
-				ErrorKind::InvalidInput,
-				format!("cannot bind global #{name} as {interface} v{version}"),
-			)),
+			_ => unreachable!("global {interface} is advertised but can't be bound"),
 		}
 	}
 }
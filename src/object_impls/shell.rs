@@ -0,0 +1,203 @@
+//! `wl_shell`, the deprecated predecessor of `xdg_shell`, for old clients that don't support anything newer.
+//!
+//! It's only advertised once [enabled](enable). Shell surfaces get the same window roles as `xdg_toplevel` and
+//! `xdg_popup`, and map the same way: once they have one and commit a buffer. There is no configure sequence to go
+//! through first, and no way to destroy a shell surface short of destroying its `wl_surface`.
+
+use super::window::Surface;
+use crate::{
+	client::SendHalf,
+	error::{ProtocolError, Result},
+	logging,
+	object_map::{Object, OccupiedEntry, VacantEntry},
+	protocol::{
+		wl_shell::{self, WlShell},
+		wl_shell_surface::{FullscreenMethod, Resize, Transient, WlShellSurface},
+		AnyObject, Id,
+	},
+	windows::{self, PopupRole, RoleKind, ToplevelRole, WindowRole},
+};
+use log::{debug, info};
+use std::{
+	any::Any,
+	cell::{Cell, RefCell},
+	rc::{Rc, Weak},
+};
+
+thread_local! {
+	/// Whether `wl_shell` is advertised, see [`enable`]
+	static ENABLED: Cell<bool> = Cell::default();
+}
+
+/// Advertise `wl_shell` to clients that get the registry from now on. Clients that support `xdg_shell` never need it.
+pub fn enable() {
+	ENABLED.with(|enabled| enabled.set(true));
+}
+
+/// Whether `wl_shell` was [enabled](enable).
+pub(super) fn enabled() -> bool {
+	ENABLED.with(Cell::get)
+}
+
+/// The window role of a shell surface, see [`super::shared_state`].
+pub(super) fn shared_state(object: &AnyObject) -> Option<Weak<dyn Any>> {
+	ShellSurface::downcast_ref(object).map(|shell_surface| Rc::downgrade(&shell_surface.role) as Weak<dyn Any>)
+}
+
+#[derive(Debug)]
+pub struct Shell {
+	/// This object, which role errors are posted on
+	pub id: Id<Shell>,
+}
+
+impl WlShell for Shell {
+	fn handle_get_shell_surface(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		id: VacantEntry<'_, ShellSurface>,
+		mut surface: OccupiedEntry<'_, Surface>,
+	) -> Result<()> {
+		info!("wl_shell.get_shell_surface(id={}, surface={})", id.id(), surface.id());
+		let role = surface.give_window_role(RoleKind::WlShellSurface, self.id, wl_shell::Error::Role)?;
+		let shell_surface = id.id();
+		id.insert(ShellSurface { id: shell_surface, role, title: None, class: None });
+		Ok(())
+	}
+}
+
+/// A `wl_shell_surface`, which gives its surface a window role like an `xdg_toplevel` or `xdg_popup` would.
+#[derive(Debug)]
+pub struct ShellSurface {
+	id: Id<ShellSurface>,
+	/// Shared with the surface, which maps once this is assigned and it has a buffer
+	role: Rc<RefCell<WindowRole>>,
+	/// Title and class set so far, which the surface keeps across roles
+	title: Option<Box<str>>,
+	class: Option<Box<str>>,
+}
+
+impl ShellSurface {
+	/// Make the surface a toplevel, unless it already is, stacked above `parent`.
+	fn make_toplevel(&self, parent: Option<Weak<RefCell<WindowRole>>>) {
+		let mut role = self.role.borrow_mut();
+		match &mut *role {
+			WindowRole::Toplevel(toplevel) => toplevel.parent = parent,
+			role => {
+				let mut toplevel = ToplevelRole::new(self.id.cast());
+				toplevel.title = self.title.clone();
+				toplevel.app_id = self.class.clone();
+				toplevel.parent = parent;
+				*role = WindowRole::Toplevel(toplevel);
+				debug!("shell surface {} is now a toplevel", self.id);
+			},
+		}
+	}
+}
+
+impl WlShellSurface for ShellSurface {
+	fn handle_pong(&mut self, _client: &mut SendHalf<'_>, _serial: u32) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_move(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		_seat: OccupiedEntry<'_, AnyObject>,
+		_serial: u32,
+	) -> Result<()> {
+		// the protocol lets the compositor ignore this, and there's no pointer to move the surface with anyway
+		Ok(())
+	}
+
+	fn handle_resize(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		_seat: OccupiedEntry<'_, AnyObject>,
+		_serial: u32,
+		_edges: Resize,
+	) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_set_toplevel(&mut self, _client: &mut SendHalf<'_>) -> Result<()> {
+		self.make_toplevel(None);
+		Ok(())
+	}
+
+	fn handle_set_transient(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		parent: OccupiedEntry<'_, Surface>,
+		_x: i32,
+		_y: i32,
+		_flags: Transient,
+	) -> Result<()> {
+		let parent = parent.role().map(Rc::downgrade);
+		if parent.as_ref().map_or(false, |parent| windows::would_cycle(&self.role, parent)) {
+			// wl_shell_surface defines no errors of its own
+			return Err(ProtocolError::invalid_method(
+				"a shell surface can't be transient for itself or for one of its descendants",
+			)
+			.into());
+		}
+		self.make_toplevel(parent);
+		Ok(())
+	}
+
+	fn handle_set_fullscreen(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		_method: FullscreenMethod,
+		_framerate: u32,
+		_output: Option<OccupiedEntry<'_, AnyObject>>,
+	) -> Result<()> {
+		// without outputs, a fullscreen surface is like any other toplevel
+		self.make_toplevel(None);
+		Ok(())
+	}
+
+	fn handle_set_popup(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		_seat: OccupiedEntry<'_, AnyObject>,
+		_serial: u32,
+		_parent: OccupiedEntry<'_, Surface>,
+		_x: i32,
+		_y: i32,
+		_flags: Transient,
+	) -> Result<()> {
+		let mut role = self.role.borrow_mut();
+		if !matches!(*role, WindowRole::Popup(_)) {
+			*role = WindowRole::Popup(PopupRole);
+			debug!("shell surface {} is now a popup", self.id);
+		}
+		Ok(())
+	}
+
+	fn handle_set_maximized(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		_output: Option<OccupiedEntry<'_, AnyObject>>,
+	) -> Result<()> {
+		self.make_toplevel(None);
+		Ok(())
+	}
+
+	fn handle_set_title(&mut self, _client: &mut SendHalf<'_>, title: &str) -> Result<()> {
+		self.title = Some(title.into());
+		if let WindowRole::Toplevel(toplevel) = &mut *self.role.borrow_mut() {
+			toplevel.title = self.title.clone();
+		}
+		Ok(())
+	}
+
+	fn handle_set_class(&mut self, _client: &mut SendHalf<'_>, class: &str) -> Result<()> {
+		self.class = Some(class.into());
+		if let WindowRole::Toplevel(toplevel) = &mut *self.role.borrow_mut() {
+			toplevel.app_id = self.class.clone();
+		}
+		// the class is the desktop file name, like an xdg_toplevel's app ID
+		logging::set_app_id(class);
+		Ok(())
+	}
+}
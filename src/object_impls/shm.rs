@@ -1,28 +1,63 @@
+use super::validate;
 use crate::{
 	client::SendHalf,
-	object_map::VacantEntry,
+	error::{ProtocolError, Result},
+	geometry::Size,
+	object_map::{Object, VacantEntry},
 	protocol::{
 		wl_buffer::WlBuffer,
-		wl_shm::{Format, WlShm},
+		wl_shm::{Error, Format, WlShm},
 		wl_shm_pool::WlShmPool,
-		Fd, Id,
+		AnyObject, Fd, Id,
 	},
 	shm::ShmBlock,
 };
 use log::info;
 use std::{
+	any::Any,
 	cell::RefCell,
-	io::{Error, ErrorKind, Result},
-	rc::Rc,
+	collections::HashSet,
+	rc::{Rc, Weak},
 };
 
+/// Pixel formats buffers can be created in, with their size in bytes per pixel.
+///
+/// Nothing draws buffers yet, so these are the formats surface dumps can read.
+const FORMATS: &[(Format, u32)] = &[(Format::Argb8888, 4), (Format::Xrgb8888, 4)];
+
+fn bytes_per_pixel(format: Format) -> Option<u32> {
+	FORMATS.iter().find(|&&(supported, _)| supported == format).map(|&(_, bpp)| bpp)
+}
+
+/// The shared memory a pool or buffer keeps mapped.
+fn memory_of(object: &AnyObject) -> Option<&Rc<RefCell<ShmBlock>>> {
+	ShmPool::downcast_ref(object)
+		.map(|pool| &pool.0)
+		.or_else(|| ShmBuffer::downcast_ref(object).map(|buffer| &buffer.memory))
+}
+
+/// Total size of the pools mapped for `objects`, counting each pool once however many buffers it has.
+///
+/// Pools stay mapped while buffers created from them exist, even after the pool object is destroyed.
+pub fn mapped_bytes<'o>(objects: impl IntoIterator<Item = &'o AnyObject>) -> usize {
+	let mut seen = HashSet::new();
+	let pools = objects.into_iter().filter_map(memory_of).filter(|memory| seen.insert(Rc::as_ptr(memory)));
+	pools.map(|memory| memory.borrow().len()).sum()
+}
+
+/// The mapping behind a pool or buffer, see [`super::shared_state`].
+pub(super) fn shared_state(object: &AnyObject) -> Option<Weak<dyn Any>> {
+	memory_of(object).map(|memory| Rc::downgrade(memory) as Weak<dyn Any>)
+}
+
 #[derive(Debug)]
 pub struct ShmGlobal;
 
 impl ShmGlobal {
 	pub(super) fn send_formats(&self, self_id: Id<Self>, client: &mut SendHalf<'_>) -> Result<()> {
-		self.send_format(self_id, client, Format::Argb8888)?;
-		self.send_format(self_id, client, Format::Xrgb8888)?;
+		for &(format, _) in FORMATS {
+			self.send_format(self_id, client, format)?;
+		}
 		Ok(())
 	}
 }
@@ -36,17 +71,22 @@ impl WlShm for ShmGlobal {
 		size: i32,
 	) -> Result<()> {
 		info!("wl_shm.create_pool(id={:?}, fd={fd:?}, size={size:?})", id.id());
-		let size = match size.try_into() {
-			Ok(n) => n,
-			Err(_) => {
-				return Err(Error::new(ErrorKind::InvalidInput, "size must be nonnegative"));
-			},
-		};
 		// XXX does calling mmap have safety preconditions separate from safely using the new memory?
-		let block = ShmBlock::new(fd, size)?;
+		// validate_create_pool ensures size is positive
+		let block = ShmBlock::new(fd, size as usize)
+			.map_err(|err| ProtocolError::new(Error::InvalidFd, format!("mapping shared memory failed: {err}")))?;
 		id.insert(ShmPool(Rc::new(RefCell::new(block))));
 		Ok(())
 	}
+
+	fn validate_create_pool(size: i32) -> Result<()> {
+		validate::positive(size, Error::InvalidStride, "pool size")
+	}
+
+	fn handle_release(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		info!("wl_shm.release()");
+		Ok(())
+	}
 }
 
 #[derive(Debug)]
@@ -68,22 +108,38 @@ impl WlShmPool for ShmPool {
 			 stride={stride:?}, format={format:?})",
 			id.id(),
 		);
-		let offset = offset
-			.try_into()
-			.map_err(|_| Error::new(ErrorKind::InvalidInput, format!("buffer offset {offset} is negative")))?;
-		let width = width
-			.try_into()
-			.map_err(|_| Error::new(ErrorKind::InvalidInput, format!("buffer width {width} is negative")))?;
-		let height = height
-			.try_into()
-			.map_err(|_| Error::new(ErrorKind::InvalidInput, format!("buffer height {height} is negative")))?;
-		let stride = stride
-			.try_into()
-			.map_err(|_| Error::new(ErrorKind::InvalidInput, format!("buffer stride {stride} is negative")))?;
-		if !matches!(format, Format::Argb8888 | Format::Xrgb8888) {
-			return Err(Error::new(ErrorKind::InvalidInput, "unsupported format"));
+		// validate_create_buffer ensures these are nonnegative
+		let (offset, stride) = (offset as u32, stride as u32);
+		let buffer = ShmBuffer { memory: self.0.clone(), offset, size: Size::new(width, height), stride, format };
+		let pool_len = self.0.borrow().len();
+		if offset as usize + buffer.len() > pool_len {
+			return Err(ProtocolError::new(
+				Error::InvalidStride,
+				format!(
+					"buffer at offset {offset} with stride {stride} and height {height} overruns pool of {pool_len} \
+					 bytes"
+				),
+			)
+			.into());
+		}
+		id.insert(buffer);
+		Ok(())
+	}
+
+	fn validate_create_buffer(offset: i32, width: i32, height: i32, stride: i32, format: Format) -> Result<()> {
+		validate::non_negative(offset, Error::InvalidStride, "buffer offset")?;
+		validate::positive(width, Error::InvalidStride, "buffer width")?;
+		validate::positive(height, Error::InvalidStride, "buffer height")?;
+		validate::positive(stride, Error::InvalidStride, "buffer stride")?;
+		let bpp = bytes_per_pixel(format)
+			.ok_or_else(|| ProtocolError::new(Error::InvalidFormat, format!("unsupported format {format:?}")))?;
+		if (stride as u32 / bpp) < width as u32 {
+			return Err(ProtocolError::new(
+				Error::InvalidStride,
+				format!("buffer stride {stride} is too small for width {width}"),
+			)
+			.into());
 		}
-		id.insert(ShmBuffer { memory: self.0.clone(), offset, width, height, stride, format });
 		Ok(())
 	}
 
@@ -94,10 +150,15 @@ impl WlShmPool for ShmPool {
 
 	fn handle_resize(&mut self, _client: &mut SendHalf<'_>, size: i32) -> Result<()> {
 		info!("wl_shm_pool.resize(size={size:?})");
-		match size.try_into() {
-			Ok(size) => self.0.borrow_mut().grow(size),
-			Err(_) => Err(Error::new(ErrorKind::InvalidInput, "size is negative")),
-		}
+		// validate_resize ensures size is positive
+		self.0
+			.borrow_mut()
+			.grow(size as usize)
+			.map_err(|err| ProtocolError::new(Error::InvalidStride, format!("resizing pool failed: {err}")).into())
+	}
+
+	fn validate_resize(size: i32) -> Result<()> {
+		validate::positive(size, Error::InvalidStride, "pool size")
 	}
 }
 
@@ -105,14 +166,19 @@ impl WlShmPool for ShmPool {
 pub struct ShmBuffer {
 	pub(super) memory: Rc<RefCell<ShmBlock>>,
 	pub(super) offset: u32,
-	#[allow(dead_code)]
-	pub(super) width: u32,
-	pub(super) height: u32,
+	/// Width and height in buffer pixels, both positive
+	pub(super) size: Size,
 	pub(super) stride: u32,
-	#[allow(dead_code)]
 	pub(super) format: Format,
 }
 
+impl ShmBuffer {
+	/// Number of bytes the buffer's rows span, from its offset into the pool.
+	pub(super) fn len(&self) -> usize {
+		self.stride as usize * self.size.height as usize
+	}
+}
+
 impl WlBuffer for ShmBuffer {
 	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
 		info!("wl_buffer.destroy()");
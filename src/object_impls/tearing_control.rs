@@ -0,0 +1,59 @@
+use super::window::Surface;
+use crate::{
+	client::SendHalf,
+	error::{ProtocolError, Result},
+	object_map::{OccupiedEntry, VacantEntry},
+	protocol::{
+		wp_tearing_control_manager_v1::{self, WpTearingControlManagerV1},
+		wp_tearing_control_v1::{PresentationHint, WpTearingControlV1},
+	},
+};
+use log::info;
+use std::{cell::Cell, rc::Rc};
+
+#[derive(Debug)]
+pub struct TearingControlManager;
+
+impl WpTearingControlManagerV1 for TearingControlManager {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_get_tearing_control(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		id: VacantEntry<'_, TearingControlObject>,
+		surface: OccupiedEntry<'_, Surface>,
+	) -> Result<()> {
+		info!("wp_tearing_control_manager_v1.get_tearing_control(id={}, surface={})", id.id(), surface.id());
+		let hint = surface.presentation_hint();
+		if hint.get().is_some() {
+			return Err(ProtocolError::new(
+				wp_tearing_control_manager_v1::Error::TearingControlExists,
+				"wl_surface already has a tearing control object",
+			)
+			.into());
+		}
+		hint.set(Some(PresentationHint::Vsync));
+		id.insert(TearingControlObject(hint.clone()));
+		Ok(())
+	}
+}
+
+/// A `wp_tearing_control_v1`, holding the pending presentation hint of its surface.
+///
+/// If the surface is destroyed first, the shared hint is no longer read and this object is inert.
+#[derive(Debug)]
+pub struct TearingControlObject(Rc<Cell<Option<PresentationHint>>>);
+
+impl WpTearingControlV1 for TearingControlObject {
+	fn handle_set_presentation_hint(&mut self, _client: &mut SendHalf<'_>, hint: PresentationHint) -> Result<()> {
+		self.0.set(Some(hint));
+		Ok(())
+	}
+
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		self.0.set(None);
+		Ok(())
+	}
+}
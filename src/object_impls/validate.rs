@@ -0,0 +1,19 @@
+//! Common argument checks for request validation hooks (the `validate_*` methods of generated interface traits).
+
+use crate::error::{ProtocolError, Result};
+
+/// Require `value` to be zero or greater, failing with error `code` otherwise.
+pub fn non_negative(value: i32, code: impl Into<u32>, what: &str) -> Result<()> {
+	if value < 0 {
+		return Err(ProtocolError::new(code, format!("{what} {value} is negative")).into());
+	}
+	Ok(())
+}
+
+/// Require `value` to be greater than zero, failing with error `code` otherwise.
+pub fn positive(value: i32, code: impl Into<u32>, what: &str) -> Result<()> {
+	if value <= 0 {
+		return Err(ProtocolError::new(code, format!("{what} {value} is not positive")).into());
+	}
+	Ok(())
+}
@@ -1,63 +1,289 @@
-use super::{shm::ShmBuffer, Callback};
+use super::{commit, shm::ShmBuffer, validate, Callback};
 use crate::{
 	client::SendHalf,
-	object_map::{OccupiedEntry, VacantEntry},
+	clock,
+	error::{ProtocolError, Result},
+	geometry::{Point, Rect, Region, Size},
+	logging,
+	object_map::{Object, ObjectRef, OccupiedEntry, VacantEntry},
 	protocol::{
 		wl_compositor::WlCompositor,
 		wl_output::Transform,
 		wl_region::WlRegion,
-		wl_surface::WlSurface,
+		wl_surface::{self, WlSurface},
+		wp_content_type_v1::Type as ContentType,
+		wp_tearing_control_v1::PresentationHint,
 		xdg_popup::XdgPopup,
-		xdg_positioner::{Gravity, XdgPositioner},
-		xdg_surface::XdgSurface,
-		xdg_toplevel::XdgToplevel,
-		xdg_wm_base::XdgWmBase,
-		AnyObject,
+		xdg_positioner::{self, Gravity, XdgPositioner},
+		xdg_surface::{self, XdgSurface},
+		xdg_toplevel::{self, XdgToplevel},
+		xdg_wm_base::{self, XdgWmBase},
+		AnyObject, Id, Word,
 	},
-	windows::{PopupRole, ToplevelRole, WindowRole},
+	surface_dump::{Frame, SurfaceDumper},
+	windows::{self, ConfigureSequence, Mapping, PopupRole, RoleKind, SurfaceRole, ToplevelRole, WindowRole},
 };
-use log::info;
+use log::{debug, info};
 use std::{
-	cell::{RefCell, RefMut},
-	io::{Error, ErrorKind, Result},
-	rc::Rc,
+	any::Any,
+	cell::{Cell, RefCell, RefMut},
+	rc::{Rc, Weak},
 };
 
 #[derive(Debug)]
-pub struct Compositor;
+pub struct Compositor {
+	/// Version the client bound the global at, inherited by surfaces created from it
+	pub version: u32,
+}
 
 impl WlCompositor for Compositor {
-	fn handle_create_surface(&mut self, _client: &mut SendHalf<'_>, surface: VacantEntry<'_, Surface>) -> Result<()> {
+	fn handle_create_surface(&mut self, client: &mut SendHalf<'_>, surface: VacantEntry<'_, Surface>) -> Result<()> {
 		info!("wl_compositor.create_surface(surface={})", surface.id());
-		surface.insert(Surface::default());
+		let id = surface.id();
+		let surface = surface.insert(Surface::new(id, self.version));
+		if surface.version >= 6 {
+			// there are no outputs to derive a preference from yet, so these are the defaults and never change
+			surface.send_preferred_buffer_scale(id, client, 1)?;
+			surface.send_preferred_buffer_transform(id, client, Transform::Normal)?;
+		}
 		Ok(())
 	}
 
-	fn handle_create_region(&mut self, _client: &mut SendHalf<'_>, slot: VacantEntry<'_, Region>) -> Result<()> {
+	fn handle_create_region(&mut self, _client: &mut SendHalf<'_>, slot: VacantEntry<'_, RegionObject>) -> Result<()> {
 		info!("wl_compositor.create_region(region={})", slot.id());
-		slot.insert(Region);
+		slot.insert(RegionObject(Region::default()));
 		Ok(())
 	}
 }
 
-#[derive(Debug, Default)]
+/// Number of rectangles pending damage is kept as, beyond which it's merged into the one rectangle it's all in.
+///
+/// Redrawing a little more than what changed is cheaper than keeping track of many small rectangles, which a client
+/// could send without end.
+const MAX_DAMAGE_RECTS: usize = 32;
+
+/// Add a rectangle to `damage`, merging it all once it's in too many pieces.
+fn add_damage(damage: &mut Region, rect: Rect) {
+	damage.add(rect);
+	if damage.rects().len() > MAX_DAMAGE_RECTS {
+		*damage = Region::from(damage.extents());
+	}
+}
+
+/// Surface version from which `wl_surface.attach` must pass a zero offset, as `wl_surface.offset` replaces it.
+const ATTACH_OFFSET_REMOVED_SINCE: u32 = 5;
+
+#[derive(Debug)]
 pub struct Surface {
-	current: BufferedSurfaceState,
-	pending: BufferedSurfaceState,
+	id: Id<Surface>,
+	version: u32,
+	/// Both boxed because every object a client has takes up as much space as a surface otherwise
+	current: Box<BufferedSurfaceState>,
+	pending: Box<BufferedSurfaceState>,
 	role: Option<Rc<RefCell<WindowRole>>>,
+	/// Configure sequence of the surface's `xdg_surface`, which commits have to follow. Owned by the `xdg_surface`, so
+	/// it's gone once that is destroyed.
+	configure: Option<Weak<RefCell<ConfigureSequence>>>,
+	/// Role the surface was given, which it keeps after the role object is gone
+	assigned_role: SurfaceRole,
+	/// Number of times this surface has been committed
+	commits: u64,
+	/// Start of the frame in which the surface was last committed
+	last_commit: Option<clock::Timestamp>,
+	dumper: SurfaceDumper,
+	/// Content type hint set through `wp_content_type_v1`, applied on commit. `None` if the surface has no content
+	/// type object.
+	content_type: Rc<Cell<Option<ContentType>>>,
+	/// Presentation hint set through `wp_tearing_control_v1`, applied on commit. `None` if the surface has no tearing
+	/// control object.
+	presentation_hint: Rc<Cell<Option<PresentationHint>>>,
+	/// Whether the surface is mapped, shared with its role objects and idle inhibitors
+	mapping: Mapping,
+	/// Alpha multiplier set through `wp_alpha_modifier_surface_v1`, applied on commit. `None` if the surface has no
+	/// alpha modifier object.
+	alpha_multiplier: Rc<Cell<Option<u32>>>,
+	/// Where the surface's top left corner is relative to its first commit, moved by the offsets of later commits
+	position: Point,
+}
+
+impl Surface {
+	fn new(id: Id<Surface>, version: u32) -> Self {
+		Self {
+			id,
+			version,
+			current: Box::default(),
+			pending: Box::default(),
+			role: None,
+			configure: None,
+			assigned_role: SurfaceRole::default(),
+			commits: 0,
+			last_commit: None,
+			dumper: SurfaceDumper::default(),
+			content_type: Rc::default(),
+			presentation_hint: Rc::default(),
+			mapping: Mapping::default(),
+			alpha_multiplier: Rc::default(),
+			position: Point::ORIGIN,
+		}
+	}
+
+	/// Where the committed content is, and how large it is in surface-local pixels, once the buffer is scaled and
+	/// turned by its transform. `None` without a buffer.
+	fn bounds(&self) -> Option<Rect> {
+		let buffer = self.current.buffer.as_ref()?;
+		let size = buffer.size.scaled_down(self.current.scale).transformed(self.current.transform);
+		Some(Rect::new(self.position, size))
+	}
+
+	/// What changed in the last commit, in surface-local coordinates, for redrawing only that.
+	pub fn damage(&self) -> &Region {
+		&self.current.damage
+	}
+
+	/// Where the committed content is opaque, in surface-local coordinates. Empty unless the client said.
+	pub fn opaque_region(&self) -> &Region {
+		&self.current.opaque_region
+	}
+
+	/// Whether the surface takes input at `point`, relative to its top left corner: within its content and input
+	/// region.
+	pub fn accepts_input(&self, point: Point<f64>) -> bool {
+		let size = self.bounds().map_or_else(Size::default, |bounds| bounds.size);
+		Rect::new(Point::ORIGIN, size).contains(point)
+			&& self.current.input_region.as_ref().map_or(true, |region| region.contains(point))
+	}
+
+	/// Role object shared with this surface's `xdg_surface` or `wl_shell_surface`, if it has one.
+	pub(super) fn role(&self) -> Option<&Rc<RefCell<WindowRole>>> {
+		self.role.as_ref()
+	}
+
+	/// Pending content type hint, shared with this surface's `wp_content_type_v1` object.
+	pub(super) fn content_type_hint(&self) -> &Rc<Cell<Option<ContentType>>> {
+		&self.content_type
+	}
+
+	/// Pending presentation hint, shared with this surface's `wp_tearing_control_v1` object.
+	pub(super) fn presentation_hint(&self) -> &Rc<Cell<Option<PresentationHint>>> {
+		&self.presentation_hint
+	}
+
+	/// Pending alpha multiplier, shared with this surface's `wp_alpha_modifier_surface_v1` object.
+	pub(super) fn alpha_multiplier(&self) -> &Rc<Cell<Option<u32>>> {
+		&self.alpha_multiplier
+	}
+
+	/// Number of commits so far, and the start of the frame the last one was in.
+	pub(super) fn commit_stats(&self) -> (u64, Option<clock::Timestamp>) {
+		(self.commits, self.last_commit)
+	}
+
+	/// Give the surface `kind` of role, for a role object that shares its window role with the surface rather than
+	/// going through an `xdg_surface`. Fails with error `code` on `object` if the surface already has a role object,
+	/// or a role other than `kind`.
+	pub(super) fn give_window_role<T>(
+		&mut self,
+		kind: RoleKind,
+		object: Id<T>,
+		code: impl Into<u32> + Copy,
+	) -> Result<Rc<RefCell<WindowRole>>> {
+		self.forget_destroyed_xdg_surface();
+		if self.role.is_some() {
+			return Err(ProtocolError::new(code, format!("wl_surface already has a role object, cannot be a {kind}"))
+				.on(object)
+				.into());
+		}
+		self.assigned_role.assign(kind, object, code)?;
+		Ok(self.role.insert(Rc::default()).clone())
+	}
+
+	/// Drop the role object and configure sequence the surface shared with its `xdg_surface`, if that was destroyed.
+	///
+	/// An `xdg_surface` can only be destroyed before it's given a role, so the surface is back to having no role
+	/// object, and may get a new `xdg_surface`.
+	fn forget_destroyed_xdg_surface(&mut self) {
+		if self.configure.as_ref().map_or(false, |configure| configure.strong_count() == 0) {
+			self.configure = None;
+			self.role = None;
+		}
+	}
+
+	/// Role the surface was given, if any, which it keeps after the role object is gone.
+	pub(super) fn assigned_role(&self) -> Option<RoleKind> {
+		self.assigned_role.get()
+	}
+
+	/// Whether the surface is mapped, for role objects that unmap it.
+	pub(super) fn mapping(&self) -> &Mapping {
+		&self.mapping
+	}
+
+	/// Whether the surface is mapped: it has a window role and committed a buffer, and neither was destroyed since.
+	pub(super) fn visible(&self) -> &Rc<Cell<bool>> {
+		self.mapping.flag()
+	}
+}
+
+impl Drop for Surface {
+	fn drop(&mut self) {
+		if self.mapping.unmap() {
+			debug!("surface {} unmapped by destroying it", self.id);
+		}
+	}
+}
+
+/// The state of a surface or xdg object that idle inhibitors and foreign toplevel handles refer to, see
+/// [`super::shared_state`].
+pub(super) fn shared_state(object: &AnyObject) -> Option<Weak<dyn Any>> {
+	if let Some(surface) = Surface::downcast_ref(object) {
+		return Some(Rc::downgrade(surface.mapping.flag()) as Weak<dyn Any>);
+	}
+	let role = XdgSurfaceImpl::downcast_ref(object)
+		.map(|xdg_surface| &xdg_surface.role)
+		.or_else(|| ToplevelObject::downcast_ref(object).map(|toplevel| &toplevel.0))
+		.or_else(|| PopupObject::downcast_ref(object).map(|popup| &popup.0))?;
+	Some(Rc::downgrade(role) as Weak<dyn Any>)
 }
 
 #[derive(Debug)]
 struct BufferedSurfaceState {
 	buffer: Option<ShmBuffer>,
-	offset: [i32; 2],
+	/// How far to move the surface's top left corner on commit, rather than a position
+	offset: Point,
+	/// Damage in surface-local coordinates, and then in buffer coordinates, which is converted to surface-local ones
+	/// and added on commit
+	damage: Region,
+	buffer_damage: Region,
+	/// Where the surface's content is opaque, to skip drawing what's behind it
+	opaque_region: Region,
+	/// Where the surface takes input, within its content. `None` for all of it.
+	input_region: Option<Region>,
 	scale: i32,
 	transform: Transform,
+	content_type: ContentType,
+	presentation_hint: PresentationHint,
+	/// Opacity of the whole surface, multiplied into its alpha when blending, from 0 (transparent) to `u32::MAX`
+	alpha_multiplier: u32,
+	/// Callbacks to notify once the committed content is shown
+	frame_callbacks: Vec<ObjectRef<Callback>>,
 }
 
 impl Default for BufferedSurfaceState {
 	fn default() -> Self {
-		Self { buffer: None, offset: [0; 2], scale: 1, transform: Transform::Normal }
+		Self {
+			buffer: None,
+			offset: Point::ORIGIN,
+			damage: Region::default(),
+			buffer_damage: Region::default(),
+			opaque_region: Region::default(),
+			input_region: None,
+			scale: 1,
+			transform: Transform::Normal,
+			content_type: ContentType::None,
+			presentation_hint: PresentationHint::Vsync,
+			alpha_multiplier: u32::MAX,
+			frame_callbacks: Vec::new(),
+		}
 	}
 }
 
@@ -74,54 +300,146 @@ impl WlSurface for Surface {
 		x: i32,
 		y: i32,
 	) -> Result<()> {
+		if self.version < ATTACH_OFFSET_REMOVED_SINCE {
+			self.pending.offset = Point::new(x, y);
+		} else if [x, y] != [0; 2] {
+			return Err(ProtocolError::new(
+				wl_surface::Error::InvalidOffset,
+				format!("attach offset {x},{y} is not zero, use wl_surface.offset instead"),
+			)
+			.into());
+		}
 		self.pending.buffer = buffer.as_ref().map(|buffer| (**buffer).clone());
-		self.pending.offset = [x, y];
 		Ok(())
 	}
 
-	fn handle_damage(&mut self, _client: &mut SendHalf<'_>, _x: i32, _y: i32, _width: i32, _height: i32) -> Result<()> {
+	fn handle_damage(&mut self, _client: &mut SendHalf<'_>, x: i32, y: i32, width: i32, height: i32) -> Result<()> {
+		add_damage(&mut self.pending.damage, Rect::new(Point::new(x, y), Size::new(width, height)));
 		Ok(())
 	}
 
-	fn handle_frame(&mut self, _client: &mut SendHalf<'_>, callback: VacantEntry<'_, Callback>) -> Result<()> {
-		callback.insert(Callback);
+	fn handle_frame(&mut self, client: &mut SendHalf<'_>, callback: VacantEntry<'_, Callback>) -> Result<()> {
+		let callback = callback.insert(Callback);
+		self.pending.frame_callbacks.push(client.object_ref(callback.id()));
 		Ok(())
 	}
 
 	fn handle_set_opaque_region(
 		&mut self,
 		_client: &mut SendHalf<'_>,
-		_region: Option<OccupiedEntry<'_, Region>>,
+		region: Option<OccupiedEntry<'_, RegionObject>>,
 	) -> Result<()> {
-		todo!()
+		self.pending.opaque_region = region.map(|region| region.0.clone()).unwrap_or_default();
+		Ok(())
 	}
 
 	fn handle_set_input_region(
 		&mut self,
 		_client: &mut SendHalf<'_>,
-		_region: Option<OccupiedEntry<'_, Region>>,
+		region: Option<OccupiedEntry<'_, RegionObject>>,
 	) -> Result<()> {
-		todo!()
-	}
-
-	fn handle_commit(&mut self, _client: &mut SendHalf<'_>) -> Result<()> {
-		self.current = std::mem::take(&mut self.pending);
-
-		if let Some(ref buffer) = self.current.buffer {
-			let path = format!(
-				"/tmp/myway-{pid}-{self:p}-{time}.bin",
-				pid = std::process::id(),
-				time = std::time::SystemTime::UNIX_EPOCH.elapsed().unwrap().as_secs()
-			);
-			let mut f = std::fs::File::create(&path).unwrap();
-
-			let buf = unsafe {
-				let ptr = buffer.memory.borrow().as_ptr().add(buffer.offset as usize);
-				let len = buffer.stride * buffer.height;
-				std::slice::from_raw_parts(ptr, len as usize)
-			};
-			std::io::Write::write_all(&mut f, buf).unwrap();
-			info!("surface contents dumped to {path}");
+		self.pending.input_region = region.map(|region| region.0.clone());
+		Ok(())
+	}
+
+	fn handle_commit(&mut self, client: &mut SendHalf<'_>) -> Result<()> {
+		commit::buffer_size(self.version, self.pending.buffer.as_ref(), self.pending.scale)?;
+		self.forget_destroyed_xdg_surface();
+		if let (Some(sequence), Some(role)) = (self.configure.as_ref().and_then(Weak::upgrade), &self.role) {
+			let (mut sequence, role) = (sequence.borrow_mut(), role.borrow());
+			if commit::configure_sequence(&sequence, &role, self.pending.buffer.is_some())? {
+				match &*role {
+					WindowRole::Toplevel(toplevel) => {
+						let serial = sequence.configure();
+						debug!("surface {} had its initial commit, configuring it with serial {serial}", self.id);
+						// a size of 0x0 leaves the size up to the client, even when fullscreen, as there are no outputs
+						// to fill
+						let states: &[Word] =
+							if windows::kiosk() { &[xdg_toplevel::State::Fullscreen as Word] } else { &[] };
+						client.object_ref(toplevel.id.cast::<ToplevelObject>()).queue(move |toplevel, client| {
+							let size = Size::default();
+							toplevel.send_configure(toplevel.id(), client, size.width, size.height, states)
+						});
+						client.object_ref(sequence.id.cast::<XdgSurfaceImpl>()).queue(move |xdg_surface, client| {
+							xdg_surface.send_configure(xdg_surface.id(), client, serial)
+						});
+					},
+					_ => debug!("surface {} is a popup, which can't be configured without positioning", self.id),
+				}
+			}
+		}
+		if let Some(WindowRole::Toplevel(toplevel)) = self.role.as_deref().map(RefCell::borrow_mut).as_deref_mut() {
+			commit::size_limits(toplevel)?;
+			toplevel.size_limits = toplevel.pending_size_limits;
+		}
+
+		self.pending.content_type = self.content_type.get().unwrap_or(ContentType::None);
+		if self.pending.content_type != self.current.content_type {
+			debug!("surface {} content type is now {:?}", self.id, self.pending.content_type);
+		}
+		self.pending.presentation_hint = self.presentation_hint.get().unwrap_or(PresentationHint::Vsync);
+		if self.pending.presentation_hint != self.current.presentation_hint {
+			debug!("surface {} presentation hint is now {:?}", self.id, self.pending.presentation_hint);
+		}
+		self.pending.alpha_multiplier = self.alpha_multiplier.get().unwrap_or(u32::MAX);
+		if self.pending.alpha_multiplier != self.current.alpha_multiplier {
+			debug!("surface {} alpha multiplier is now {:#x}", self.id, self.pending.alpha_multiplier);
+		}
+		if self.pending.opaque_region != self.current.opaque_region {
+			debug!("surface {} opaque region is now {}", self.id, self.pending.opaque_region);
+		}
+		if self.pending.input_region != self.current.input_region {
+			let region = self.pending.input_region.as_ref();
+			debug!("surface {} input region is now {}", self.id, region.map_or("everything".into(), Region::to_string));
+		}
+		*self.current = std::mem::take(&mut *self.pending);
+		// unlike the rest of the state, regions stay until they're set again
+		self.pending.opaque_region = self.current.opaque_region.clone();
+		self.pending.input_region = self.current.input_region.clone();
+		if let Some(size) = self.bounds().map(|bounds| bounds.size) {
+			let (transform, scale) = (self.current.transform, self.current.scale);
+			let buffer_damage = self.current.buffer_damage.map(|rect| rect.buffer_to_surface(transform, scale, size));
+			self.current.damage.union(&buffer_damage);
+			self.current.damage.intersect(Rect::new(Point::ORIGIN, size));
+		}
+		self.commits += 1;
+		self.last_commit = Some(clock::frame_time());
+		if self.current.offset != Point::ORIGIN {
+			self.position = self.position.saturating_add(self.current.offset);
+			debug!("surface {} moved by {} to {}", self.id, self.current.offset, self.position);
+		}
+		let has_role = self.role.as_ref().map_or(false, |role| !matches!(*role.borrow(), WindowRole::Unassigned));
+		if has_role && self.current.buffer.is_some() {
+			if self.mapping.map() {
+				let bounds = self.bounds().unwrap_or_default();
+				debug!("surface {} mapped as {bounds}, and focused", self.id);
+			}
+		} else if self.mapping.unmap() {
+			debug!("surface {} unmapped by committing without a buffer", self.id);
+		}
+
+		// nothing is drawn yet, so the content counts as shown once the client gets to the queued events
+		for callback in self.current.frame_callbacks.drain(..) {
+			callback.destroy(|callback, id, client| callback.send_done(id, client, clock::frame_time().millis()));
+		}
+
+		if let (Some(buffer), true) = (&self.current.buffer, SurfaceDumper::enabled()) {
+			let memory = buffer.memory.borrow();
+			let start = buffer.offset as usize;
+			let len = buffer.len();
+			if start.checked_add(len).map_or(false, |end| end <= memory.len()) {
+				// Safety: the range was just checked to be within the mapping, which stays mapped while `memory` is
+				// borrowed
+				let pixels = unsafe { std::slice::from_raw_parts(memory.as_ptr().add(start), len) };
+				let frame = Frame {
+					pixels,
+					width: buffer.size.width as u32,
+					height: buffer.size.height as u32,
+					stride: buffer.stride,
+					format: buffer.format,
+				};
+				self.dumper.dump(self.id.into(), self.commits, frame);
+			}
 		}
 
 		Ok(())
@@ -137,53 +455,64 @@ impl WlSurface for Surface {
 		Ok(())
 	}
 
+	fn validate_set_buffer_scale(scale: i32) -> Result<()> {
+		validate::positive(scale, wl_surface::Error::InvalidScale, "buffer scale")
+	}
+
 	fn handle_damage_buffer(
 		&mut self,
 		_client: &mut SendHalf<'_>,
-		_x: i32,
-		_y: i32,
-		_width: i32,
-		_height: i32,
+		x: i32,
+		y: i32,
+		width: i32,
+		height: i32,
 	) -> Result<()> {
-		todo!()
+		add_damage(&mut self.pending.buffer_damage, Rect::new(Point::new(x, y), Size::new(width, height)));
+		Ok(())
 	}
 
 	fn handle_offset(&mut self, _client: &mut SendHalf<'_>, x: i32, y: i32) -> Result<()> {
-		self.pending.offset = [x, y];
+		self.pending.offset = Point::new(x, y);
 		Ok(())
 	}
 }
 
+/// A `wl_region`, which surfaces copy when it's set as one of their regions, so later changes don't affect them.
 #[derive(Debug)]
-pub struct Region;
+pub struct RegionObject(Region);
 
-impl WlRegion for Region {
+impl WlRegion for RegionObject {
 	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
 		Ok(())
 	}
 
-	fn handle_add(&mut self, _client: &mut SendHalf<'_>, _x: i32, _y: i32, _width: i32, _height: i32) -> Result<()> {
+	fn handle_add(&mut self, _client: &mut SendHalf<'_>, x: i32, y: i32, width: i32, height: i32) -> Result<()> {
+		self.0.add(Rect::new(Point::new(x, y), Size::new(width, height)));
 		Ok(())
 	}
 
-	fn handle_subtract(
-		&mut self,
-		_client: &mut SendHalf<'_>,
-		_x: i32,
-		_y: i32,
-		_width: i32,
-		_height: i32,
-	) -> Result<()> {
+	fn handle_subtract(&mut self, _client: &mut SendHalf<'_>, x: i32, y: i32, width: i32, height: i32) -> Result<()> {
+		self.0.subtract(Rect::new(Point::new(x, y), Size::new(width, height)));
 		Ok(())
 	}
 }
 
+/// Capabilities advertised to toplevels in `xdg_toplevel.wm_capabilities`.
+///
+/// Windows aren't laid out anywhere yet, so none of window menus, maximizing, fullscreen or minimizing do anything.
+/// Requests for them, and to move or resize a toplevel interactively, are accepted and ignored.
+const WM_CAPABILITIES: &[Word] = &[];
+
 #[derive(Debug)]
-pub struct WindowManager;
+pub struct WindowManager {
+	pub id: Id<WindowManager>,
+	/// Version the client bound the global at, inherited by xdg_surfaces created from it
+	pub version: u32,
+}
 
 impl XdgWmBase for WindowManager {
 	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_create_positioner(&mut self, _client: &mut SendHalf<'_>, id: VacantEntry<'_, Positioner>) -> Result<()> {
@@ -197,11 +526,22 @@ impl XdgWmBase for WindowManager {
 		id: VacantEntry<'_, XdgSurfaceImpl>,
 		mut surface: OccupiedEntry<'_, Surface>,
 	) -> Result<()> {
+		surface.forget_destroyed_xdg_surface();
 		if surface.role.is_some() {
-			return Err(Error::new(ErrorKind::InvalidInput, "wl_surface already has an xdg_surface"));
+			return Err(ProtocolError::new(xdg_wm_base::Error::Role, "wl_surface already has an xdg_surface").into());
 		}
+		let (assigned_role, mapping) = (surface.assigned_role.clone(), surface.mapping.clone());
+		let configure = Rc::new(RefCell::new(ConfigureSequence::new(id.id().cast())));
+		surface.configure = Some(Rc::downgrade(&configure));
 		let role = surface.role.insert(Default::default());
-		id.insert(XdgSurfaceImpl(role.clone()));
+		id.insert(XdgSurfaceImpl {
+			role: role.clone(),
+			configure,
+			assigned_role,
+			mapping,
+			wm_base: self.id,
+			version: self.version,
+		});
 		Ok(())
 	}
 
@@ -211,25 +551,47 @@ impl XdgWmBase for WindowManager {
 }
 
 #[derive(Debug)]
-pub struct XdgSurfaceImpl(Rc<RefCell<WindowRole>>);
+pub struct XdgSurfaceImpl {
+	role: Rc<RefCell<WindowRole>>,
+	/// Shared with the surface, which follows it on commit
+	configure: Rc<RefCell<ConfigureSequence>>,
+	/// Role of the surface, checked before giving it a window role
+	assigned_role: SurfaceRole,
+	/// Whether the surface is mapped, passed on to the role object, which unmaps it when destroyed
+	mapping: Mapping,
+	/// The `xdg_wm_base` this was created from, which role errors are posted on
+	wm_base: Id<WindowManager>,
+	version: u32,
+}
 
 impl XdgSurface for XdgSurfaceImpl {
 	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
-		if matches!(*self.0.borrow(), WindowRole::Unassigned) {
+		if matches!(*self.role.borrow(), WindowRole::Unassigned) {
 			Ok(())
 		} else {
-			Err(Error::new(ErrorKind::Other, "cannot destroy xdg_surface that has an assigned role"))
+			Err(ProtocolError::new(
+				xdg_surface::Error::AlreadyConstructed,
+				"cannot destroy xdg_surface that has an assigned role",
+			)
+			.into())
 		}
 	}
 
-	fn handle_get_toplevel(&mut self, _client: &mut SendHalf<'_>, id: VacantEntry<'_, ToplevelObject>) -> Result<()> {
-		let mut role = self.0.borrow_mut();
+	fn handle_get_toplevel(&mut self, client: &mut SendHalf<'_>, id: VacantEntry<'_, ToplevelObject>) -> Result<()> {
+		let mut role = self.role.borrow_mut();
 		if matches!(*role, WindowRole::Unassigned) {
-			*role = WindowRole::Toplevel(ToplevelRole { title: None, app_id: None });
-			id.insert(ToplevelObject(self.0.clone()));
+			self.assigned_role.assign(RoleKind::XdgToplevel, self.wm_base, xdg_wm_base::Error::Role)?;
+			self.configure.borrow_mut().reset();
+			let toplevel_id = id.id();
+			*role = WindowRole::Toplevel(ToplevelRole::new(toplevel_id.cast()));
+			let toplevel = id.insert(ToplevelObject(self.role.clone(), self.mapping.clone()));
+			// configure_bounds is left out: without outputs the bounds are unknown, which is what not sending it means
+			if self.version >= 5 {
+				toplevel.send_wm_capabilities(toplevel_id, client, WM_CAPABILITIES)?;
+			}
 			Ok(())
 		} else {
-			Err(Error::new(ErrorKind::Other, "xdg_surface already has a role"))
+			Err(ProtocolError::new(xdg_surface::Error::AlreadyConstructed, "xdg_surface already has a role").into())
 		}
 	}
 
@@ -240,13 +602,15 @@ impl XdgSurface for XdgSurfaceImpl {
 		_parent: Option<OccupiedEntry<'_, XdgSurfaceImpl>>,
 		_positioner: OccupiedEntry<'_, Positioner>,
 	) -> Result<()> {
-		let mut role = self.0.borrow_mut();
+		let mut role = self.role.borrow_mut();
 		if matches!(*role, WindowRole::Unassigned) {
+			self.assigned_role.assign(RoleKind::XdgPopup, self.wm_base, xdg_wm_base::Error::Role)?;
+			self.configure.borrow_mut().reset();
 			*role = WindowRole::Popup(PopupRole);
-			id.insert(PopupObject(self.0.clone()));
+			id.insert(PopupObject(self.role.clone(), self.mapping.clone()));
 			Ok(())
 		} else {
-			Err(Error::new(ErrorKind::Other, "xdg_surface already has a role"))
+			Err(ProtocolError::new(xdg_surface::Error::AlreadyConstructed, "xdg_surface already has a role").into())
 		}
 	}
 
@@ -258,24 +622,36 @@ impl XdgSurface for XdgSurfaceImpl {
 		_width: i32,
 		_height: i32,
 	) -> Result<()> {
-		todo!()
+		// windows aren't drawn, so there is no decoration to leave out of the geometry yet
+		Ok(())
+	}
+
+	fn handle_ack_configure(&mut self, _client: &mut SendHalf<'_>, serial: u32) -> Result<()> {
+		if self.configure.borrow_mut().ack(serial) {
+			Ok(())
+		} else {
+			let message = format!("serial {serial} isn't that of a configure waiting to be acked");
+			Err(ProtocolError::new(xdg_surface::Error::InvalidSerial, message).into())
+		}
 	}
 
-	fn handle_ack_configure(&mut self, _client: &mut SendHalf<'_>, _serial: u32) -> Result<()> {
-		todo!()
+	fn validate_set_window_geometry(_x: i32, _y: i32, width: i32, height: i32) -> Result<()> {
+		validate::positive(width, xdg_surface::Error::InvalidSize, "window geometry width")?;
+		validate::positive(height, xdg_surface::Error::InvalidSize, "window geometry height")
 	}
 }
 
+/// Popups aren't placed anywhere yet, so the positioner's rules are accepted and ignored.
 #[derive(Debug)]
 pub struct Positioner;
 
 impl XdgPositioner for Positioner {
 	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_size(&mut self, _client: &mut SendHalf<'_>, _width: i32, _height: i32) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_anchor_rect(
@@ -286,7 +662,7 @@ impl XdgPositioner for Positioner {
 		_width: i32,
 		_height: i32,
 	) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_anchor(
@@ -294,11 +670,11 @@ impl XdgPositioner for Positioner {
 		_client: &mut SendHalf<'_>,
 		_anchor: crate::protocol::xdg_positioner::Anchor,
 	) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_gravity(&mut self, _client: &mut SendHalf<'_>, _gravity: Gravity) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_constraint_adjustment(
@@ -306,15 +682,15 @@ impl XdgPositioner for Positioner {
 		_client: &mut SendHalf<'_>,
 		_constraint_adjustment: u32,
 	) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_offset(&mut self, _client: &mut SendHalf<'_>, _x: i32, _y: i32) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_reactive(&mut self, _client: &mut SendHalf<'_>) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_parent_size(
@@ -323,16 +699,26 @@ impl XdgPositioner for Positioner {
 		_parent_width: i32,
 		_parent_height: i32,
 	) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_parent_configure(&mut self, _client: &mut SendHalf<'_>, _serial: u32) -> Result<()> {
-		todo!()
+		Ok(())
+	}
+
+	fn validate_set_size(width: i32, height: i32) -> Result<()> {
+		validate::positive(width, xdg_positioner::Error::InvalidInput, "positioner width")?;
+		validate::positive(height, xdg_positioner::Error::InvalidInput, "positioner height")
+	}
+
+	fn validate_set_anchor_rect(_x: i32, _y: i32, width: i32, height: i32) -> Result<()> {
+		validate::non_negative(width, xdg_positioner::Error::InvalidInput, "anchor rect width")?;
+		validate::non_negative(height, xdg_positioner::Error::InvalidInput, "anchor rect height")
 	}
 }
 
 #[derive(Debug)]
-pub struct ToplevelObject(Rc<RefCell<WindowRole>>);
+pub struct ToplevelObject(Rc<RefCell<WindowRole>>, Mapping);
 
 impl ToplevelObject {
 	fn get_mut(&self) -> RefMut<'_, ToplevelRole> {
@@ -345,15 +731,28 @@ impl ToplevelObject {
 
 impl XdgToplevel for ToplevelObject {
 	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
-		todo!()
+		*self.0.borrow_mut() = WindowRole::Unassigned;
+		if self.1.unmap() {
+			debug!("toplevel unmapped by destroying it");
+		}
+		Ok(())
 	}
 
 	fn handle_set_parent(
 		&mut self,
 		_client: &mut SendHalf<'_>,
-		_parent: Option<OccupiedEntry<'_, ToplevelObject>>,
+		parent: Option<OccupiedEntry<'_, ToplevelObject>>,
 	) -> Result<()> {
-		todo!()
+		let parent = parent.map(|parent| Rc::downgrade(&parent.0));
+		if parent.as_ref().map_or(false, |parent| windows::would_cycle(&self.0, parent)) {
+			return Err(ProtocolError::new(
+				xdg_toplevel::Error::InvalidParent,
+				"a toplevel can't be its own parent or the parent of its ancestors",
+			)
+			.into());
+		}
+		self.get_mut().parent = parent;
+		Ok(())
 	}
 
 	fn handle_set_title(&mut self, _client: &mut SendHalf<'_>, title: &str) -> Result<()> {
@@ -363,6 +762,7 @@ impl XdgToplevel for ToplevelObject {
 
 	fn handle_set_app_id(&mut self, _client: &mut SendHalf<'_>, app_id: &str) -> Result<()> {
 		self.get_mut().app_id = Some(app_id.into());
+		logging::set_app_id(app_id);
 		Ok(())
 	}
 
@@ -374,7 +774,7 @@ impl XdgToplevel for ToplevelObject {
 		_x: i32,
 		_y: i32,
 	) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_move(
@@ -383,7 +783,7 @@ impl XdgToplevel for ToplevelObject {
 		_seat: OccupiedEntry<'_, AnyObject>,
 		_serial: u32,
 	) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_resize(
@@ -393,23 +793,25 @@ impl XdgToplevel for ToplevelObject {
 		_serial: u32,
 		_edges: crate::protocol::xdg_toplevel::ResizeEdge,
 	) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
-	fn handle_set_max_size(&mut self, _client: &mut SendHalf<'_>, _width: i32, _height: i32) -> Result<()> {
-		todo!()
+	fn handle_set_max_size(&mut self, _client: &mut SendHalf<'_>, width: i32, height: i32) -> Result<()> {
+		self.get_mut().pending_size_limits.max = Size::new(width, height);
+		Ok(())
 	}
 
-	fn handle_set_min_size(&mut self, _client: &mut SendHalf<'_>, _width: i32, _height: i32) -> Result<()> {
-		todo!()
+	fn handle_set_min_size(&mut self, _client: &mut SendHalf<'_>, width: i32, height: i32) -> Result<()> {
+		self.get_mut().pending_size_limits.min = Size::new(width, height);
+		Ok(())
 	}
 
 	fn handle_set_maximized(&mut self, _client: &mut SendHalf<'_>) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_unset_maximized(&mut self, _client: &mut SendHalf<'_>) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_fullscreen(
@@ -417,24 +819,37 @@ impl XdgToplevel for ToplevelObject {
 		_client: &mut SendHalf<'_>,
 		_output: Option<OccupiedEntry<'_, AnyObject>>,
 	) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_unset_fullscreen(&mut self, _client: &mut SendHalf<'_>) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 
 	fn handle_set_minimized(&mut self, _client: &mut SendHalf<'_>) -> Result<()> {
-		todo!()
+		Ok(())
+	}
+
+	fn validate_set_max_size(width: i32, height: i32) -> Result<()> {
+		validate::non_negative(width, xdg_toplevel::Error::InvalidSize, "maximum width")?;
+		validate::non_negative(height, xdg_toplevel::Error::InvalidSize, "maximum height")
+	}
+
+	fn validate_set_min_size(width: i32, height: i32) -> Result<()> {
+		validate::non_negative(width, xdg_toplevel::Error::InvalidSize, "minimum width")?;
+		validate::non_negative(height, xdg_toplevel::Error::InvalidSize, "minimum height")
 	}
 }
 
 #[derive(Debug)]
-pub struct PopupObject(Rc<RefCell<WindowRole>>);
+pub struct PopupObject(Rc<RefCell<WindowRole>>, Mapping);
 
 impl XdgPopup for PopupObject {
 	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
 		*self.0.borrow_mut() = WindowRole::Unassigned;
+		if self.1.unmap() {
+			debug!("popup unmapped by destroying it");
+		}
 		Ok(())
 	}
 
@@ -444,7 +859,8 @@ impl XdgPopup for PopupObject {
 		_seat: OccupiedEntry<'_, AnyObject>,
 		_serial: u32,
 	) -> Result<()> {
-		todo!()
+		// there is no input yet, so there is nothing to grab
+		Ok(())
 	}
 
 	fn handle_reposition(
@@ -453,6 +869,6 @@ impl XdgPopup for PopupObject {
 		_positioner: OccupiedEntry<'_, Positioner>,
 		_token: u32,
 	) -> Result<()> {
-		todo!()
+		Ok(())
 	}
 }
@@ -1,11 +1,16 @@
 use crate::{
-	client::{self, RecvMessage},
+	client::{self, ClientId, EventQueue, RecvMessage, SendHalf},
+	error::{ProtocolError, Result},
+	object_impls::Display,
 	protocol::{AnyObject, Id},
+	state_dump::Direction,
 };
 use std::{
-	fmt,
-	io::{Error, ErrorKind, Result},
+	any::Any,
+	cell::RefCell,
+	fmt, mem,
 	ops::{Deref, DerefMut},
+	rc::Weak,
 };
 
 /// Server-side representation and state backing a Wayland object.
@@ -34,13 +39,231 @@ impl Object for AnyObject {
 	}
 }
 
+/// An object implemented outside the generated bindings, stored as [`AnyObject::Generic`].
+///
+/// This trades the type safety of the generated traits for being able to add implementations (e.g. experimental
+/// ones) without regenerating the protocol code. Requests are decoded by the implementation itself.
+pub trait ObjectDyn: Any + fmt::Debug {
+	/// Name of the interface this object implements.
+	fn interface(&self) -> &'static str;
+
+	/// Handle a request sent to this object, returning whether the object is still alive afterwards.
+	///
+	/// The object is taken out of `objects` while it handles the request, and put back afterwards unless the request
+	/// destroyed it. It is put back if handling fails too, like objects of the generated bindings are left in place.
+	fn handle_request(
+		&mut self,
+		self_id: Id<AnyObject>,
+		objects: &mut Objects,
+		client: &mut SendHalf<'_>,
+		message: RecvMessage<'_>,
+	) -> Result<bool>;
+}
+
+/// Function handling requests to objects of one type, as used by [`Objects::dispatch_request`].
+pub type RequestHandler = fn(&mut Objects, &mut SendHalf<'_>, RecvMessage<'_>) -> Result<()>;
+
+/// How [`AnyObject`] stores objects with the `dyn-dispatch` feature: type-erased, with the handler for their requests.
+///
+/// This is storage only, unlike [`ObjectDyn`], which objects outside the generated bindings implement to handle their
+/// own requests (and which are stored through this in turn). Implementations for the generated bindings are generated
+/// too. [`Object`] is implemented for every `ErasedObject`, with downcasts going through [`Any`].
+#[cfg(feature = "dyn-dispatch")]
+pub trait ErasedObject: Any + fmt::Debug {
+	fn request_handler(&self) -> RequestHandler;
+	fn interface(&self) -> &'static str;
+	fn as_any(&self) -> &dyn Any;
+	fn as_any_mut(&mut self) -> &mut dyn Any;
+	fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+#[cfg(feature = "dyn-dispatch")]
+impl<T: ErasedObject> Object for T {
+	fn upcast(self) -> AnyObject {
+		AnyObject(Box::new(self))
+	}
+
+	fn downcast(object: AnyObject) -> Option<Self> {
+		object.0.into_any().downcast().ok().map(|obj| *obj)
+	}
+
+	fn downcast_ref(object: &AnyObject) -> Option<&Self> {
+		object.0.as_any().downcast_ref()
+	}
+
+	fn downcast_mut(object: &mut AnyObject) -> Option<&mut Self> {
+		object.0.as_any_mut().downcast_mut()
+	}
+}
+
+#[cfg(feature = "dyn-dispatch")]
+impl ErasedObject for Box<dyn ObjectDyn> {
+	fn request_handler(&self) -> RequestHandler {
+		dispatch_dyn
+	}
+
+	fn interface(&self) -> &'static str {
+		ObjectDyn::interface(&**self)
+	}
+
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+
+	fn into_any(self: Box<Self>) -> Box<dyn Any> {
+		self
+	}
+}
+
+#[cfg(not(feature = "dyn-dispatch"))]
+impl Object for Box<dyn ObjectDyn> {
+	fn upcast(self) -> AnyObject {
+		AnyObject::Generic(self)
+	}
+
+	fn downcast(object: AnyObject) -> Option<Self> {
+		match object {
+			AnyObject::Generic(obj) => Some(obj),
+			_ => None,
+		}
+	}
+
+	fn downcast_ref(object: &AnyObject) -> Option<&Self> {
+		match object {
+			AnyObject::Generic(obj) => Some(obj),
+			_ => None,
+		}
+	}
+
+	fn downcast_mut(object: &mut AnyObject) -> Option<&mut Self> {
+		match object {
+			AnyObject::Generic(obj) => Some(obj),
+			_ => None,
+		}
+	}
+}
+
+/// Request handler for [`AnyObject::Generic`], forwarding to [`ObjectDyn::handle_request`].
+pub fn dispatch_dyn(objects: &mut Objects, client: &mut SendHalf<'_>, message: RecvMessage<'_>) -> Result<()> {
+	let self_id = message.object_id();
+	let [this] = objects.get_many_mut([Some(self_id)])?;
+	// moved out while handling, which doesn't destroy it unless the handler says so
+	let mut this = this.unwrap().into_occupied()?.downcast::<Box<dyn ObjectDyn>>()?.remove();
+	match this.handle_request(self_id, objects, client, message) {
+		Ok(true) => objects.insert(self_id.cast(), this).map(drop),
+		Ok(false) => {
+			objects.deleted.get_mut().push(self_id);
+			Ok(())
+		},
+		Err(err) => {
+			// the handler's error is the one to report, even if its ID was taken meanwhile
+			let _ = objects.insert(self_id.cast(), this);
+			Err(err)
+		},
+	}
+}
+
+/// Handle to an object of some client, for sending it events outside of request dispatch (e.g. configures or frame
+/// callbacks), where the object isn't otherwise reachable.
+///
+/// Events are sent by [queueing](Self::queue) them, for the main loop to run when it next sends the client events.
+/// Nothing is sent once the client disconnects or the object is destroyed, but since clients reuse IDs, a handle may
+/// also resolve to a later object of the same type.
+pub struct ObjectRef<T> {
+	client: ClientId,
+	id: Id<T>,
+	events: Weak<EventQueue>,
+}
+
+impl<T> ObjectRef<T> {
+	pub fn new(client: ClientId, id: Id<T>, events: Weak<EventQueue>) -> Self {
+		Self { client, id, events }
+	}
+
+	pub fn client(&self) -> ClientId {
+		self.client
+	}
+
+	pub fn id(&self) -> Id<T> {
+		self.id
+	}
+}
+
+impl<T: Object + 'static> ObjectRef<T> {
+	/// Queue `f` to be run with the object and its client's send half, once the main loop gets to sending events to
+	/// the client.
+	///
+	/// Nothing happens if the client has disconnected, and `f` is skipped if the object is gone by the time it would
+	/// run.
+	pub fn queue(&self, f: impl FnOnce(OccupiedEntry<'_, T>, &mut SendHalf<'_>) -> Result<()> + 'static) {
+		let events = match self.events.upgrade() {
+			Some(events) => events,
+			None => return,
+		};
+		let id = self.id;
+		events.push(Box::new(move |objects, client| {
+			let [entry] = objects.get_many_mut([Some(id.cast())])?;
+			match entry.and_then(|entry| entry.into_occupied().ok()).and_then(|entry| entry.downcast().ok()) {
+				Some(entry) => f(entry, client),
+				None => Ok(()),
+			}
+		}));
+	}
+
+	/// Queue the destruction of the object with [`Objects::destroy`], once the main loop gets to sending events to the
+	/// client.
+	pub fn destroy(&self, teardown: impl FnOnce(T, Id<T>, &mut SendHalf<'_>) -> Result<()> + 'static) {
+		if let Some(events) = self.events.upgrade() {
+			let id = self.id;
+			events.push(Box::new(move |objects, client| objects.destroy(id, client, teardown)));
+		}
+	}
+
+	/// Queue `f` to be run like with [`queue`](Self::queue), but with all objects of the client rather than just this
+	/// one, for events describing the others.
+	pub fn queue_with_objects(&self, f: impl FnOnce(&mut Objects, Id<T>, &mut SendHalf<'_>) -> Result<()> + 'static) {
+		if let Some(events) = self.events.upgrade() {
+			let id = self.id;
+			events.push(Box::new(move |objects, client| f(objects, id, client)));
+		}
+	}
+}
+
+impl<T> Clone for ObjectRef<T> {
+	fn clone(&self) -> Self {
+		Self { client: self.client, id: self.id, events: self.events.clone() }
+	}
+}
+
+impl<T> fmt::Debug for ObjectRef<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("ObjectRef").field("client", &self.client).field("id", &self.id).finish()
+	}
+}
+
 pub struct Objects {
 	vec: Vec<Option<AnyObject>>,
+	/// IDs of objects destroyed since the last [`release_deleted`](Self::release_deleted). The client may not reuse
+	/// them until it's sent `wl_display.delete_id` for them.
+	deleted: RefCell<Vec<Id<AnyObject>>>,
 }
 
 impl Objects {
 	pub fn new() -> Self {
-		Self { vec: Vec::with_capacity(2) } // ensure we at least have the capacity for the Display at ID 1
+		// ensure we at least have the capacity for the Display at ID 1, after the slot of the null ID
+		let mut vec = Vec::with_capacity(2);
+		vec.push(None);
+		Self { vec, deleted: RefCell::default() }
+	}
+
+	/// Iterate over the objects that currently exist, in order of ID.
+	pub fn iter(&self) -> impl Iterator<Item = (Id<AnyObject>, &AnyObject)> {
+		let objects = self.vec.iter().enumerate();
+		objects.filter_map(|(id, slot)| Some((Id::new(id as u32)?, slot.as_ref()?)))
 	}
 
 	pub fn insert<T: Object>(&mut self, id: Id<T>, obj: T) -> Result<OccupiedEntry<'_, T>> {
@@ -57,14 +280,23 @@ impl Objects {
 			if let Some(id) = id {
 				for id2 in ids[..i].iter().copied().flatten() {
 					if id == id2 {
-						return Err(Error::new(ErrorKind::InvalidInput, format!("requested id {id} multiple times")));
+						return Err(ProtocolError::invalid_object(format!("requested id {id} multiple times")).into());
 					}
 				}
+				// like libwayland, only allow growing the map one ID at a time, so a client can't make it allocate
+				// space for billions of objects by naming a single large ID
+				if id.into_usize() > new_len {
+					return Err(ProtocolError::invalid_object(format!(
+						"id {id} is neither in use nor the next unused id {new_len}"
+					))
+					.into());
+				}
 				new_len = new_len.max(id.into_usize() + 1);
 			}
 		}
 		// new_len starts at `self.vec.len()` and only goes up, so this will never shrink the vec
 		self.vec.resize_with(new_len, || None);
+		let deleted = &self.deleted;
 		let ret = unsafe {
 			let (slice_ptr, slice_len) = (self.vec.as_mut_ptr(), self.vec.len());
 			let mut ret: [Option<Entry<'_, AnyObject>>; N] = std::array::from_fn(|_| None);
@@ -77,7 +309,7 @@ impl Objects {
 					// the nested loop ensures no index is present twice and so at most one mutable reference is created
 					// for each element of the slice.
 					let object_ref = &mut *slice_ptr.add(object_idx);
-					ret[ret_idx] = Some(Entry::new(id, object_ref));
+					ret[ret_idx] = Some(Entry::new(id, object_ref, deleted));
 				}
 			}
 			ret
@@ -88,11 +320,53 @@ impl Objects {
 	pub fn dispatch_request(&mut self, client: &mut client::SendHalf<'_>, message: RecvMessage<'_>) -> Result<()> {
 		let id = message.object_id();
 		match self.vec.get(id.into_usize()) {
-			Some(Some(obj)) => (obj.request_handler())(self, client, message),
+			Some(Some(obj)) => {
+				let interface = obj.interface();
+				let _span = tracing::trace_span!("dispatch", interface, object = u32::from(id)).entered();
+				client.record(Direction::Request, interface, id, message.opcode());
+				(obj.request_handler())(self, client, message).map_err(|err| err.or_on(id))
+			},
 			Some(None) => Ok(()), // ignore requests to an object that existed but was deleted
-			None => Err(Error::new(ErrorKind::InvalidInput, format!("object {id} does not exist"))),
+			None => Err(ProtocolError::invalid_object(format!("object {id} does not exist")).into()),
 		}
 	}
+
+	/// Destroy object `id` from the compositor's side, if it exists and has type `T`. `teardown` is given the object to
+	/// send the destructor event telling the client it's gone, after which its ID is freed like that of an object the
+	/// client destroyed.
+	///
+	/// Only objects with a destructor event can be destroyed this way: the client owns the others, and would go on
+	/// using their IDs.
+	pub fn destroy<T: Object>(
+		&mut self,
+		id: Id<T>,
+		client: &mut SendHalf<'_>,
+		teardown: impl FnOnce(T, Id<T>, &mut SendHalf<'_>) -> Result<()>,
+	) -> Result<()> {
+		let [entry] = self.get_many_mut([Some(id.cast())])?;
+		match entry.and_then(|entry| entry.into_occupied().ok()).map(OccupiedEntry::downcast::<T>) {
+			Some(Ok(object)) => teardown(object.take(), id, client),
+			_ => Ok(()),
+		}
+	}
+
+	/// Send `wl_display.delete_id` for the objects destroyed since the last call, allowing the client to reuse their
+	/// IDs.
+	///
+	/// Until then, the client can't know the IDs are free, so creating an object with one of them is a protocol error.
+	pub fn release_deleted(&mut self, client: &mut SendHalf<'_>) -> Result<()> {
+		let display = Id::new(1).unwrap();
+		for id in mem::take(self.deleted.get_mut()) {
+			Display.send_delete_id(display, client, id.into())?;
+		}
+		Ok(())
+	}
+}
+
+impl Default for Objects {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl fmt::Debug for Objects {
@@ -113,11 +387,11 @@ pub enum Entry<'a, T> {
 }
 
 impl<'a> Entry<'a, AnyObject> {
-	fn new(id: Id<AnyObject>, slot: &'a mut Option<AnyObject>) -> Self {
+	fn new(id: Id<AnyObject>, slot: &'a mut Option<AnyObject>, deleted: &'a RefCell<Vec<Id<AnyObject>>>) -> Self {
 		if slot.is_some() {
-			Self::Occupied(OccupiedEntry { id, slot })
+			Self::Occupied(OccupiedEntry { id, slot, deleted })
 		} else {
-			Self::Vacant(VacantEntry { id, slot })
+			Self::Vacant(VacantEntry { id, slot, deleted })
 		}
 	}
 }
@@ -126,15 +400,22 @@ impl<'a, T> Entry<'a, T> {
 	pub fn into_occupied(self) -> Result<OccupiedEntry<'a, T>> {
 		match self {
 			Self::Occupied(entry) => Ok(entry),
-			Self::Vacant(entry) => Err(Error::new(ErrorKind::NotFound, format!("id {} does not exist", entry.id))),
+			Self::Vacant(entry) => Err(ProtocolError::invalid_object(format!("id {} does not exist", entry.id)).into()),
 		}
 	}
 
 	pub fn into_vacant(self) -> Result<VacantEntry<'a, T>> {
-		match self {
-			Self::Occupied(entry) => Err(Error::new(ErrorKind::AlreadyExists, format!("id {} exists", entry.id))),
-			Self::Vacant(entry) => Ok(entry),
-		}
+		let message = match self {
+			Self::Occupied(entry) => {
+				let interface = entry.slot.as_ref().map_or("?", |obj| obj.interface());
+				format!("id {} is already in use by {interface}", entry.id)
+			},
+			Self::Vacant(entry) if entry.deleted.borrow().contains(&entry.id.cast()) => {
+				format!("id {} was reused before wl_display.delete_id was sent for it", entry.id)
+			},
+			Self::Vacant(entry) => return Ok(entry),
+		};
+		Err(ProtocolError::invalid_object(message).into())
 	}
 }
 
@@ -142,14 +423,15 @@ impl<'a, T> Entry<'a, T> {
 pub struct OccupiedEntry<'a, T> {
 	id: Id<T>,
 	slot: &'a mut Option<AnyObject>,
+	deleted: &'a RefCell<Vec<Id<AnyObject>>>,
 }
 
 impl<'a> OccupiedEntry<'a, AnyObject> {
 	pub fn downcast<T: Object>(self) -> Result<OccupiedEntry<'a, T>> {
 		if T::downcast_ref(&self).is_some() {
-			Ok(OccupiedEntry { id: self.id.cast(), slot: self.slot })
+			Ok(OccupiedEntry { id: self.id.cast(), slot: self.slot, deleted: self.deleted })
 		} else {
-			Err(Error::new(ErrorKind::InvalidInput, format!("ID {} is not the correct type", self.id)))
+			Err(ProtocolError::invalid_object(format!("ID {} is not the correct type", self.id)).into())
 		}
 	}
 }
@@ -159,8 +441,15 @@ impl<'a, T: Object> OccupiedEntry<'a, T> {
 		self.id
 	}
 
-	#[allow(dead_code)]
+	/// Remove the object, destroying it. Its ID is freed once the client is told with
+	/// [`Objects::release_deleted`].
 	pub fn take(self) -> T {
+		self.deleted.borrow_mut().push(self.id.cast());
+		self.remove()
+	}
+
+	/// Remove the object without destroying it, leaving its ID free to put it back.
+	fn remove(self) -> T {
 		match self.slot.take() {
 			Some(obj) => T::downcast(obj).unwrap(),
 			None => panic!("OccupiedEntry created from empty slot (id={})", self.id),
@@ -192,11 +481,12 @@ impl<'a, T: Object> DerefMut for OccupiedEntry<'a, T> {
 pub struct VacantEntry<'a, T> {
 	id: Id<T>,
 	slot: &'a mut Option<AnyObject>,
+	deleted: &'a RefCell<Vec<Id<AnyObject>>>,
 }
 
 impl<'a> VacantEntry<'a, AnyObject> {
 	pub fn downcast<T: Object>(self) -> VacantEntry<'a, T> {
-		VacantEntry { id: self.id.cast(), slot: self.slot }
+		VacantEntry { id: self.id.cast(), slot: self.slot, deleted: self.deleted }
 	}
 }
 
@@ -208,6 +498,6 @@ impl<'a, T: Object> VacantEntry<'a, T> {
 	pub fn insert(self, obj: T) -> OccupiedEntry<'a, T> {
 		debug_assert!(self.slot.is_none(), "Vacant Entry created from occupied slot (id={})", self.id);
 		*self.slot = Some(obj.upcast());
-		OccupiedEntry { id: self.id, slot: self.slot }
+		OccupiedEntry { id: self.id, slot: self.slot, deleted: self.deleted }
 	}
 }
@@ -1,8 +1,10 @@
-use crate::client::RecvMessage;
+use crate::{
+	client::RecvMessage,
+	error::{ProtocolError, Result},
+};
 
 use super::{Fd, Word, WORD_SIZE};
 use log::trace;
-use std::io::{Error, ErrorKind, Result};
 
 pub trait DecodeArg<'a>: Sized {
 	fn decode_arg(message: &mut RecvMessage<'a>) -> Result<Self>;
@@ -24,7 +26,7 @@ impl<'a> DecodeArg<'a> for &'a str {
 	fn decode_arg(message: &mut RecvMessage<'a>) -> Result<Self> {
 		let byte_len = u32::decode_arg(message)?;
 		match byte_len {
-			0 => Err(Error::new(ErrorKind::InvalidInput, "string argument must not be null")),
+			0 => Err(ProtocolError::invalid_method("string argument must not be null").into()),
 			n => split_string_common(n, message),
 		}
 	}
@@ -50,19 +52,26 @@ fn split_string_common<'a>(byte_len: u32, message: &mut RecvMessage<'a>) -> Resu
 		unsafe { std::slice::from_raw_parts(arg_words.as_ptr().cast(), arg_words.len() * WORD_SIZE) };
 	let bytes = match arg_bytes[..byte_len as usize] {
 		[ref s @ .., 0] => s,
-		_ => return Err(Error::new(ErrorKind::InvalidInput, "string argument not NUL-terminated")),
+		_ => return Err(ProtocolError::invalid_method("string argument not NUL-terminated").into()),
 	};
-	if bytes.iter().any(|&b| b == 0) {
-		return Err(Error::new(ErrorKind::InvalidInput, "string argument has interior NULs"));
+	if bytes.contains(&0) {
+		return Err(ProtocolError::invalid_method("string argument has interior NULs").into());
 	}
-	let string = std::str::from_utf8(bytes).map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+	let string = std::str::from_utf8(bytes)
+		.map_err(|err| ProtocolError::invalid_method(format!("string argument is not UTF-8: {err}")))?;
 	Ok(string)
 }
 
 impl<'a> DecodeArg<'a> for &'a [Word] {
 	fn decode_arg(message: &mut RecvMessage<'a>) -> Result<Self> {
-		let word_len = u32::decode_arg(message)?;
-		message.split(word_len as usize)
+		// the length is in bytes on the wire, and arrays of words have to be made of whole ones
+		let byte_len = u32::decode_arg(message)?;
+		if byte_len as usize % WORD_SIZE != 0 {
+			return Err(
+				ProtocolError::invalid_method(format!("array of {byte_len} bytes is not made of whole words")).into()
+			);
+		}
+		message.split(byte_len as usize / WORD_SIZE)
 	}
 }
 
@@ -37,7 +37,7 @@ impl EncodeArg for i32 {
 	}
 }
 
-impl<'a> EncodeArg for &'a str {
+impl EncodeArg for &str {
 	fn encoded_len(&self) -> u16 {
 		assert!(self.len() < u16::MAX as usize, "string is too large to serialize");
 		let byte_len = self.len() as u16 + 1; // nul terminator
@@ -64,7 +64,7 @@ impl<'a> EncodeArg for &'a str {
 	}
 }
 
-impl<'a> EncodeArg for Option<&'a str> {
+impl EncodeArg for Option<&str> {
 	fn encoded_len(&self) -> u16 {
 		match self {
 			Some(s) => s.encoded_len(),
@@ -80,14 +80,15 @@ impl<'a> EncodeArg for Option<&'a str> {
 	}
 }
 
-impl<'a> EncodeArg for &'a [Word] {
+impl EncodeArg for &[Word] {
 	fn encoded_len(&self) -> u16 {
-		assert!(self.len() < u16::MAX as usize, "string is too large to serialize");
+		assert!(self.len() < u16::MAX as usize, "array is too large to serialize");
 		self.len() as u16 + 1
 	}
 
 	fn encode(&self, event: &mut SendMessage<'_>) {
-		(self.len() as u32).encode(event);
+		// the length is in bytes on the wire
+		((self.len() * WORD_SIZE) as u32).encode(event);
 		event.write_all(self);
 	}
 }
@@ -1,12 +1,34 @@
-use crate::client::{RecvMessage, SendMessage};
+use crate::{
+	client::{RecvMessage, SendMessage},
+	error::Result,
+};
 
 use super::{DecodeArg, EncodeArg};
-use std::io::Result;
+use std::fmt::{self, Debug, Formatter};
 
 /// A signed fixed-point rational number with sign bit, 23 bit integer precision, and 8 bit fractional precision.
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Fixed(i32);
 
+impl From<f64> for Fixed {
+	/// Convert to the nearest representable value, saturating at the bounds of the 24-bit integer part.
+	fn from(value: f64) -> Self {
+		Self((value * 256.0).round() as i32)
+	}
+}
+
+impl From<Fixed> for f64 {
+	fn from(value: Fixed) -> Self {
+		value.0 as f64 / 256.0
+	}
+}
+
+impl Debug for Fixed {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		Debug::fmt(&f64::from(*self), f)
+	}
+}
+
 impl<'a> DecodeArg<'a> for Fixed {
 	fn decode_arg(message: &mut RecvMessage<'a>) -> Result<Self> {
 		i32::decode_arg(message).map(Fixed)
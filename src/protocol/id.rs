@@ -1,11 +1,13 @@
-use crate::client::{RecvMessage, SendMessage};
+use crate::{
+	client::{RecvMessage, SendMessage},
+	error::{ProtocolError, Result},
+};
 
 use super::{DecodeArg, EncodeArg};
 use std::{
 	cmp::Ordering,
 	fmt::{self, Debug, Display, Formatter},
 	hash::{Hash, Hasher},
-	io::{Error, ErrorKind, Result},
 	marker::PhantomData,
 	num::NonZeroU32,
 };
@@ -64,7 +66,7 @@ impl<T> Eq for Id<T> {}
 
 impl<T> PartialOrd for Id<T> {
 	fn partial_cmp(&self, rhs: &Self) -> Option<Ordering> {
-		self.0.partial_cmp(&rhs.0)
+		Some(self.cmp(rhs))
 	}
 }
 
@@ -90,7 +92,7 @@ impl<'a, T> DecodeArg<'a> for Id<T> {
 	fn decode_arg(message: &mut RecvMessage<'a>) -> Result<Self> {
 		match <Option<Self>>::decode_arg(message)? {
 			Some(arg) => Ok(arg),
-			None => Err(Error::new(ErrorKind::InvalidInput, "ID may not be null")),
+			None => Err(ProtocolError::invalid_method("ID may not be null").into()),
 		}
 	}
 }
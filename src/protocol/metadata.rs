@@ -0,0 +1,48 @@
+//! Static descriptions of protocol interfaces and their messages, generated alongside the bindings.
+//!
+//! These mirror the protocol XML closely enough to label traces and answer introspection queries without the XML at
+//! hand. With the `serde` feature, they serialize to JSON-friendly structures.
+
+/// An interface and all of its messages.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
+pub struct InterfaceDesc {
+	pub name: &'static str,
+	/// Highest version of the interface the bindings were generated from
+	pub version: u32,
+	/// Requests, indexed by opcode
+	pub requests: &'static [MessageDesc],
+	/// Events, indexed by opcode
+	pub events: &'static [MessageDesc],
+}
+
+/// A request or event.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
+pub struct MessageDesc {
+	pub name: &'static str,
+	/// Interface version the message was introduced in
+	pub since: u32,
+	/// Whether the message destroys the object it is sent to or from
+	pub destructor: bool,
+	pub args: &'static [ArgDesc],
+}
+
+/// An argument of a message.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
+pub struct ArgDesc {
+	pub name: &'static str,
+	/// Wire type, as spelled in the protocol XML (`"int"`, `"new_id"`, ...)
+	pub ty: &'static str,
+	/// Interface of `object` and `new_id` arguments, if it is fixed
+	pub interface: Option<&'static str>,
+	/// Whether `object` and `string` arguments may be null
+	pub nullable: bool,
+	/// Enum the value of a `uint` or `int` argument is drawn from
+	#[cfg_attr(feature = "serde", serde(rename = "enum"))]
+	pub enum_name: Option<&'static str>,
+}
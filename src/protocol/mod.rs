@@ -4,8 +4,15 @@ mod args;
 mod event;
 mod fixed;
 mod id;
+mod metadata;
 
-pub use self::{args::DecodeArg, event::EncodeArg, fixed::Fixed, id::Id};
+pub use self::{
+	args::DecodeArg,
+	event::EncodeArg,
+	fixed::Fixed,
+	id::Id,
+	metadata::{ArgDesc, InterfaceDesc, MessageDesc},
+};
 
 /// A single protocol word. Messages are always a multiple of this size.
 pub type Word = u32;
@@ -16,7 +23,7 @@ pub const WORD_SIZE: usize = std::mem::size_of::<Word>();
 /// An owned file descriptor, passed over the socket for shared memory or bulk data transfer.
 pub type Fd = OwnedFd;
 
-#[allow(unused_imports, dead_code, clippy::enum_variant_names)]
+#[allow(unused_imports, dead_code, clippy::enum_variant_names, clippy::doc_lazy_continuation)]
 mod generated {
 	include!(concat!(env!("OUT_DIR"), "/wayland_protocol.rs"));
 }
@@ -65,6 +65,15 @@ impl ShmBlock {
 	pub fn as_ptr(&self) -> *const u8 {
 		self.ptr.cast()
 	}
+
+	/// Size of the mapped memory, in bytes.
+	pub fn len(&self) -> usize {
+		self.length
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.length == 0
+	}
 }
 
 impl Drop for ShmBlock {
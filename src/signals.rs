@@ -1,19 +1,32 @@
 use nix::sys::{
 	signal::{SigSet, Signal},
-	signalfd::{signalfd, SfdFlags},
+	signalfd::{SfdFlags, SignalFd},
 };
-use std::os::unix::io::{FromRawFd, OwnedFd};
 
-/// Intercept SIGINT on the current thread, and return a file descriptor that will become readable when a signal is
-/// caught.
+/// Intercept SIGINT, SIGUSR1 and SIGCHLD on the current thread, and return a file descriptor that will become readable
+/// when a signal is caught.
 ///
-/// The returned [`Fd`] is in nonblocking mode and should be registered with an [`Epoll`](crate::epoll::Epoll) with
-/// interest `EPOLLIN` before use.
-pub fn catch_sigint() -> nix::Result<OwnedFd> {
+/// The returned [`SignalFd`] is in nonblocking mode and should be registered with an [`Epoll`](crate::epoll::Epoll)
+/// with interest `EPOLLIN` before use. Since epoll is edge-triggered, [`read_signal`] must be called until it returns
+/// `None` every time the file descriptor becomes readable.
+pub fn catch_signals() -> nix::Result<SignalFd> {
 	let mut signals = SigSet::empty();
 	signals.add(Signal::SIGINT);
+	signals.add(Signal::SIGUSR1);
+	signals.add(Signal::SIGCHLD);
 	signals.thread_block()?;
-	let fd = signalfd(-1, &signals, SfdFlags::SFD_CLOEXEC | SfdFlags::SFD_NONBLOCK)?;
-	// Safety: signalfd returns a new valid file descriptor which we immediately wrap
-	Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+	SignalFd::with_flags(&signals, SfdFlags::SFD_CLOEXEC | SfdFlags::SFD_NONBLOCK)
+}
+
+/// Read the next caught signal, or `None` if no signals are pending.
+pub fn read_signal(fd: &mut SignalFd) -> nix::Result<Option<Signal>> {
+	loop {
+		match fd.read_signal()? {
+			Some(info) => match Signal::try_from(info.ssi_signo as i32) {
+				Ok(signal) => return Ok(Some(signal)),
+				Err(_) => continue, // not a signal we know how to name, so not one we asked for
+			},
+			None => return Ok(None),
+		}
+	}
 }
@@ -0,0 +1,114 @@
+//! Snapshots of compositor state, to make bug reports actionable.
+//!
+//! A dump lists every client with its objects and the last protocol messages it exchanged. It's written to a file when
+//! the main loop fails, and on SIGUSR1 to look at a compositor that is still running.
+
+use crate::{
+	client::Client,
+	protocol::{AnyObject, Id, INTERFACES},
+};
+use slab::Slab;
+use std::{
+	collections::{BTreeMap, VecDeque},
+	env,
+	fmt::{self, Display, Formatter},
+	fs::File,
+	io::{self, BufWriter, Write},
+	path::PathBuf,
+	process,
+	time::SystemTime,
+};
+
+/// Number of protocol messages remembered per client.
+const HISTORY_LEN: usize = 64;
+
+/// Whether a message was sent by the client or the compositor.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Direction {
+	Request,
+	Event,
+}
+
+/// The last [`HISTORY_LEN`] messages exchanged with a client.
+///
+/// Only the target and opcode of each message are kept, so recording is cheap enough to always be on.
+#[derive(Debug, Default)]
+pub struct History(VecDeque<Record>);
+
+impl History {
+	pub fn push(&mut self, direction: Direction, interface: &'static str, object: Id<AnyObject>, opcode: u16) {
+		if self.0.len() == HISTORY_LEN {
+			self.0.pop_front();
+		}
+		self.0.push_back(Record { direction, interface, object, opcode });
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Record {
+	direction: Direction,
+	interface: &'static str,
+	object: Id<AnyObject>,
+	opcode: u16,
+}
+
+impl Display for Record {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let Self { direction, interface, object, opcode } = *self;
+		let desc = INTERFACES.iter().find(|desc| desc.name == interface);
+		let messages = desc.map(|desc| match direction {
+			Direction::Request => desc.requests,
+			Direction::Event => desc.events,
+		});
+		// same layout as WAYLAND_DEBUG, minus the arguments
+		let prefix = if direction == Direction::Event { " -> " } else { "" };
+		match messages.and_then(|messages| messages.get(usize::from(opcode))) {
+			Some(message) => write!(f, "{prefix}{interface}@{object}.{}", message.name),
+			None => write!(f, "{prefix}{interface}@{object}.[opcode {opcode}]"),
+		}
+	}
+}
+
+/// Write a dump of every client to a new file, returning its path.
+///
+/// The file goes in `XDG_RUNTIME_DIR`, or the temporary directory if that isn't set.
+pub fn save(clients: &Slab<Client>) -> io::Result<PathBuf> {
+	let time = SystemTime::UNIX_EPOCH.elapsed().map_or(0, |time| time.as_secs());
+	let dir = env::var_os("XDG_RUNTIME_DIR").map_or_else(env::temp_dir, PathBuf::from);
+	let path = dir.join(format!("myway-{}-{time}.dump", process::id()));
+	let mut file = BufWriter::new(File::create(&path)?);
+	write(&mut file, clients, time)?;
+	file.flush()?;
+	Ok(path)
+}
+
+fn write(dest: &mut impl Write, clients: &Slab<Client>, time: u64) -> io::Result<()> {
+	writeln!(dest, "myway state dump, pid {}, at {time} seconds since the epoch", process::id())?;
+	writeln!(dest, "{} clients", clients.len())?;
+	for (key, client) in clients {
+		writeln!(dest)?;
+		writeln!(dest, "client {key} ({:?})", client.id())?;
+		writeln!(dest, "  memory: {}", client.usage())?;
+
+		let mut per_interface = BTreeMap::new();
+		for (_, object) in client.objects().iter() {
+			*per_interface.entry(object.interface()).or_insert(0) += 1;
+		}
+		writeln!(dest, "  objects per interface:")?;
+		for (interface, count) in per_interface {
+			writeln!(dest, "    {interface}: {count}")?;
+		}
+
+		// surfaces print their window role, so this doubles as the window tree
+		writeln!(dest, "  objects:")?;
+		for (id, object) in client.objects().iter() {
+			writeln!(dest, "    {}@{id}: {object:?}", object.interface())?;
+		}
+
+		writeln!(dest, "  last messages, oldest first:")?;
+		for record in &client.history().0 {
+			writeln!(dest, "    {record}")?;
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,131 @@
+//! Clients the compositor launches itself, for a session or kiosk that runs a fixed set of programs.
+//!
+//! Each client is a shell command, started once the socket is listening so it finds the compositor through
+//! `WAYLAND_DISPLAY`, and with an activation token in `XDG_ACTIVATION_TOKEN`. A client that crashes is restarted,
+//! waiting longer after each crash in a row. The first client is the primary one: once it exits cleanly, the session is
+//! over.
+
+use crate::activation;
+use log::{info, warn};
+use std::{
+	io,
+	process::{Child, Command},
+	time::{Duration, Instant},
+};
+
+/// Wait before restarting a client that crashed for the first time in a row.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest wait before restarting a client, however often it crashed.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How long a client has to run for its next crash to count as the first in a row again.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// What happened to the supervised clients, from [`Supervisor::reap`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Reaped {
+	/// Nothing that ends the session: no client exited, or only ones that are restarted or weren't primary
+	Continue,
+	/// The primary client exited cleanly, which ends the session
+	PrimaryExited,
+}
+
+#[derive(Debug)]
+struct Supervised {
+	command: String,
+	/// The running process, or `None` while waiting to restart it, or for good after it exited cleanly
+	child: Option<Child>,
+	started: Instant,
+	/// Crashes in a row, each of which doubles the wait before restarting
+	crashes: u32,
+	restart_at: Option<Instant>,
+}
+
+/// Launches clients and restarts them with backoff when they crash.
+#[derive(Debug, Default)]
+pub struct Supervisor {
+	/// The first one is the primary client
+	clients: Vec<Supervised>,
+}
+
+impl Supervisor {
+	/// Supervise `commands`, the first one of which is the primary client. Each is due to start at `now`, see
+	/// [`restart_due`](Self::restart_due).
+	pub fn new(commands: impl IntoIterator<Item = String>, now: Instant) -> Self {
+		let clients = commands
+			.into_iter()
+			.map(|command| Supervised { command, child: None, started: now, crashes: 0, restart_at: Some(now) })
+			.collect();
+		Self { clients }
+	}
+
+	/// Check which clients exited, after `SIGCHLD` or at `now`, and schedule restarts for those that crashed.
+	pub fn reap(&mut self, now: Instant) -> Reaped {
+		let mut reaped = Reaped::Continue;
+		for (index, client) in self.clients.iter_mut().enumerate() {
+			let status = match client.child.as_mut().map(Child::try_wait) {
+				Some(Ok(Some(status))) => status,
+				Some(Ok(None)) | None => continue,
+				Some(Err(err)) => {
+					warn!("checking on client `{}` failed: {err}", client.command);
+					continue;
+				},
+			};
+			client.child = None;
+			if status.success() {
+				info!("client `{}` exited", client.command);
+				if index == 0 {
+					reaped = Reaped::PrimaryExited;
+				}
+			} else {
+				client.crashed(now, &format!("crashed ({status})"));
+			}
+		}
+		reaped
+	}
+
+	/// How long until the next restart is due, if any is scheduled, to wake up for it.
+	pub fn next_restart(&self, now: Instant) -> Option<Duration> {
+		self.clients.iter().filter_map(|client| client.restart_at).min().map(|at| at.saturating_duration_since(now))
+	}
+
+	/// Start the clients whose restarts are due at `now`. A client that fails to start is retried like one that
+	/// crashed.
+	pub fn restart_due(&mut self, now: Instant) {
+		for client in &mut self.clients {
+			if client.restart_at.map_or(false, |at| at <= now) {
+				client.restart_at = None;
+				match client.spawn() {
+					Ok(child) => {
+						info!("started client `{}` as process {}", client.command, child.id());
+						client.child = Some(child);
+						client.started = now;
+					},
+					Err(err) => client.crashed(now, &format!("failed to start: {err}")),
+				}
+			}
+		}
+	}
+}
+
+impl Supervised {
+	/// Start the client with an activation token, so the first window it maps can take focus.
+	fn spawn(&self) -> io::Result<Child> {
+		let token = activation::issue(None);
+		let mut command = Command::new("/bin/sh");
+		command.arg("-c").arg(&self.command);
+		// the variable toolkits look for, and the one older startup notification code looks for
+		command.env("XDG_ACTIVATION_TOKEN", &token).env("DESKTOP_STARTUP_ID", &token);
+		command.spawn()
+	}
+
+	/// Schedule a restart after a crash at `now`, described by `how`.
+	fn crashed(&mut self, now: Instant, how: &str) {
+		if now.saturating_duration_since(self.started) >= STABLE_AFTER {
+			self.crashes = 0;
+		}
+		let backoff = MIN_BACKOFF.saturating_mul(1 << self.crashes.min(16)).min(MAX_BACKOFF);
+		self.crashes = self.crashes.saturating_add(1);
+		warn!("client `{}` {how}, restarting it in {}s", self.command, backoff.as_secs());
+		self.restart_at = Some(now + backoff);
+	}
+}
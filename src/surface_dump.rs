@@ -0,0 +1,188 @@
+//! Opt-in debug facility for inspecting committed surface contents.
+//!
+//! Set `MYWAY_DUMP_SURFACES` to a directory and every committed shm buffer is written there as a PNG, at most once
+//! per [`MIN_INTERVAL`] per surface. Surface id, size, format, and commit count are recorded in the file name and in
+//! `tEXt` chunks of the image.
+
+use crate::protocol::wl_shm::Format;
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use std::{
+	env,
+	fs::File,
+	io::{BufWriter, Error, ErrorKind, Result, Write},
+	path::PathBuf,
+	time::{Duration, Instant},
+};
+
+static DUMP_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| env::var_os("MYWAY_DUMP_SURFACES").map(PathBuf::from));
+
+/// Minimum time between two dumps of the same surface.
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pixel data of a committed buffer, as laid out in shared memory.
+#[derive(Copy, Clone, Debug)]
+pub struct Frame<'a> {
+	pub pixels: &'a [u8],
+	pub width: u32,
+	pub height: u32,
+	pub stride: u32,
+	pub format: Format,
+}
+
+/// Per-surface dump state, used to rate limit dumps.
+#[derive(Debug, Default)]
+pub struct SurfaceDumper {
+	last_dump: Option<Instant>,
+}
+
+impl SurfaceDumper {
+	/// Whether surface dumping is enabled at all.
+	///
+	/// Callers can check this before mapping buffer contents to avoid the work when it would be discarded.
+	pub fn enabled() -> bool {
+		DUMP_DIR.is_some()
+	}
+
+	/// Write `frame` to the dump directory, unless dumping is disabled or this surface was dumped too recently.
+	///
+	/// Failures are logged and otherwise ignored, since a broken debug facility should not take clients down with it.
+	pub fn dump(&mut self, surface_id: u32, commit: u64, frame: Frame<'_>) {
+		let dir = match &*DUMP_DIR {
+			Some(dir) => dir,
+			None => return,
+		};
+		let now = Instant::now();
+		if self.last_dump.map_or(false, |last| now.duration_since(last) < MIN_INTERVAL) {
+			return;
+		}
+		self.last_dump = Some(now);
+
+		let mut path = dir.clone();
+		path.push(format!("myway-{pid}-surface{surface_id}-commit{commit}.png", pid = std::process::id()));
+		match write_png(&path, surface_id, commit, frame) {
+			Ok(()) => debug!("surface {surface_id} contents dumped to {}", path.display()),
+			Err(err) => warn!("dumping surface {surface_id} to {} failed: {err}", path.display()),
+		}
+	}
+}
+
+fn write_png(path: &std::path::Path, surface_id: u32, commit: u64, frame: Frame<'_>) -> Result<()> {
+	let mut out = BufWriter::new(File::create(path)?);
+	encode_png(&mut out, surface_id, commit, frame)?;
+	out.flush()
+}
+
+/// Encode `frame` as a PNG, with the surface's details in `tEXt` chunks.
+///
+/// Only [`Format::Argb8888`] and [`Format::Xrgb8888`] are supported, becoming RGBA and RGB images respectively.
+pub fn encode_png(out: &mut impl Write, surface_id: u32, commit: u64, frame: Frame<'_>) -> Result<()> {
+	let Frame { pixels, width, height, stride, format } = frame;
+	let row_len = width as usize * 4;
+	if (stride as usize) < row_len || pixels.len() < stride as usize * height as usize {
+		return Err(Error::new(ErrorKind::InvalidInput, "buffer is smaller than its dimensions"));
+	}
+	let has_alpha = match format {
+		Format::Argb8888 => true,
+		Format::Xrgb8888 => false,
+		other => return Err(Error::new(ErrorKind::Unsupported, format!("cannot encode {other:?} as PNG"))),
+	};
+
+	// filter type 0 (None) followed by RGB(A) samples for each scanline
+	let channels = if has_alpha { 4 } else { 3 };
+	let mut raw = Vec::with_capacity((1 + width as usize * channels) * height as usize);
+	for row in pixels.chunks(stride as usize).take(height as usize) {
+		raw.push(0);
+		// [AX]RGB8888 is a little-endian u32, so the bytes in memory are B, G, R, A
+		for px in row[..row_len].chunks_exact(4) {
+			raw.extend_from_slice(&[px[2], px[1], px[0]]);
+			if has_alpha {
+				raw.push(px[3]);
+			}
+		}
+	}
+
+	out.write_all(b"\x89PNG\r\n\x1a\n")?;
+	let mut ihdr = Vec::with_capacity(13);
+	ihdr.extend_from_slice(&width.to_be_bytes());
+	ihdr.extend_from_slice(&height.to_be_bytes());
+	// bit depth 8, color type 6 (RGBA) or 2 (RGB), default compression/filter/interlace
+	ihdr.extend_from_slice(&[8, if has_alpha { 6 } else { 2 }, 0, 0, 0]);
+	write_chunk(out, b"IHDR", &ihdr)?;
+	for (key, value) in [
+		("myway:surface", surface_id.to_string()),
+		("myway:size", format!("{width}x{height}")),
+		("myway:format", format!("{format:?}")),
+		("myway:commit", commit.to_string()),
+	] {
+		write_chunk(out, b"tEXt", format!("{key}\0{value}").as_bytes())?;
+	}
+	write_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+	write_chunk(out, b"IEND", &[])
+}
+
+fn write_chunk(out: &mut impl Write, ty: &[u8; 4], data: &[u8]) -> Result<()> {
+	let len = u32::try_from(data.len()).map_err(|_| Error::new(ErrorKind::InvalidInput, "PNG chunk too large"))?;
+	out.write_all(&len.to_be_bytes())?;
+	out.write_all(ty)?;
+	out.write_all(data)?;
+	let crc = crc32(crc32(!0, ty), data);
+	out.write_all(&(!crc).to_be_bytes())
+}
+
+/// Wrap `data` in a zlib stream of uncompressed deflate blocks.
+///
+/// Dumps are a debugging aid, so file size is traded for not needing a compression library.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+	const MAX_BLOCK: usize = u16::MAX as usize;
+	let mut out = Vec::with_capacity(data.len() + data.len() / MAX_BLOCK * 5 + 11);
+	out.extend_from_slice(&[0x78, 0x01]);
+	let mut blocks = data.chunks(MAX_BLOCK).peekable();
+	if blocks.peek().is_none() {
+		out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+	}
+	while let Some(block) = blocks.next() {
+		let last = blocks.peek().is_none();
+		let len = block.len() as u16;
+		out.push(last as u8);
+		out.extend_from_slice(&len.to_le_bytes());
+		out.extend_from_slice(&(!len).to_le_bytes());
+		out.extend_from_slice(block);
+	}
+	out.extend_from_slice(&adler32(data).to_be_bytes());
+	out
+}
+
+/// Adler-32 checksum of `data`, as ends a zlib stream.
+pub fn adler32(data: &[u8]) -> u32 {
+	let (mut a, mut b) = (1u32, 0u32);
+	// 5552 is the largest n such that 255n(n+1)/2 + (n+1)(65520) fits in a u32
+	for chunk in data.chunks(5552) {
+		for &byte in chunk {
+			a += byte as u32;
+			b += a;
+		}
+		a %= 65521;
+		b %= 65521;
+	}
+	(b << 16) | a
+}
+
+/// Continue a CRC-32 (ISO-HDLC, as used by PNG) over `data`. Start from `!0` and invert the final result.
+pub fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+	static TABLE: Lazy<[u32; 256]> = Lazy::new(|| {
+		let mut table = [0; 256];
+		for (n, entry) in table.iter_mut().enumerate() {
+			let mut c = n as u32;
+			for _ in 0..8 {
+				c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+			}
+			*entry = c;
+		}
+		table
+	});
+	for &byte in data {
+		crc = TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+	}
+	crc
+}
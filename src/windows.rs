@@ -1,3 +1,204 @@
+use crate::{
+	error::ProtocolError,
+	geometry::Size,
+	protocol::{AnyObject, Id},
+};
+use std::{
+	cell::{Cell, RefCell},
+	collections::HashSet,
+	fmt, ptr,
+	rc::{Rc, Weak},
+};
+
+thread_local! {
+	/// Mapped surfaces, in the order they were mapped. The last one has focus.
+	static STACK: RefCell<Vec<Weak<Cell<bool>>>> = RefCell::default();
+	/// Last serial handed out by [`next_serial`]
+	static SERIAL: Cell<u32> = Cell::default();
+	/// Whether the compositor runs as a kiosk, see [`enable_kiosk`]
+	static KIOSK: Cell<bool> = Cell::default();
+}
+
+/// Run as a kiosk from now on: serve a single client at a time, tell its toplevels they're fullscreen, and advertise
+/// `zwp_fullscreen_shell_v1` for clients that present surfaces rather than manage windows.
+pub fn enable_kiosk() {
+	KIOSK.with(|kiosk| kiosk.set(true));
+}
+
+/// Whether the compositor runs as a [kiosk](enable_kiosk).
+pub fn kiosk() -> bool {
+	KIOSK.with(Cell::get)
+}
+
+/// A new serial for an event the client refers back to, such as a configure it acks.
+pub fn next_serial() -> u32 {
+	SERIAL.with(|serial| {
+		serial.set(serial.get().wrapping_add(1));
+		serial.get()
+	})
+}
+
+/// Where an `xdg_surface` is in the sequence that has to come before it gets a buffer: a commit without one, to which
+/// the compositor responds with a configure, which the client acks.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ConfigureState {
+	#[default]
+	AwaitingInitialCommit,
+	AwaitingAck,
+	Configured,
+}
+
+/// Configure sequence of an `xdg_surface`, shared between it and its `wl_surface`, whose commits follow it.
+#[derive(Debug)]
+pub struct ConfigureSequence {
+	/// The `xdg_surface`, which errors about the sequence are posted on
+	pub id: Id<AnyObject>,
+	pub state: ConfigureState,
+	/// Serials of the configures sent but not acked yet, oldest first
+	pub unacked: Vec<u32>,
+}
+
+impl ConfigureSequence {
+	pub fn new(id: Id<AnyObject>) -> Self {
+		Self { id, state: ConfigureState::default(), unacked: Vec::new() }
+	}
+
+	/// Start over, for a new role object.
+	pub fn reset(&mut self) {
+		self.state = ConfigureState::AwaitingInitialCommit;
+		self.unacked.clear();
+	}
+
+	/// Record that a configure was sent, returning its serial.
+	pub fn configure(&mut self) -> u32 {
+		let serial = next_serial();
+		self.unacked.push(serial);
+		if self.state == ConfigureState::AwaitingInitialCommit {
+			self.state = ConfigureState::AwaitingAck;
+		}
+		serial
+	}
+
+	/// Ack the configure with `serial`, along with every older one. Returns whether it was sent and not acked yet.
+	pub fn ack(&mut self, serial: u32) -> bool {
+		match self.unacked.iter().position(|&unacked| unacked == serial) {
+			Some(index) => {
+				self.unacked.drain(..=index);
+				self.state = ConfigureState::Configured;
+				true
+			},
+			None => false,
+		}
+	}
+}
+
+/// Whether a surface is mapped, shared between the surface and its role objects, since destroying either unmaps it.
+///
+/// Mapping a surface puts it on top of the stack of windows, giving it focus. Unmapping it takes it off the stack, and
+/// focus goes to the window below. There are no outputs to place windows on yet.
+#[derive(Clone, Debug, Default)]
+pub struct Mapping(Rc<Cell<bool>>);
+
+impl Mapping {
+	pub fn is_mapped(&self) -> bool {
+		self.0.get()
+	}
+
+	/// Shared flag that's set while the surface is mapped, for state that only cares whether it's shown.
+	pub fn flag(&self) -> &Rc<Cell<bool>> {
+		&self.0
+	}
+
+	/// Map the surface, unless it already is. Returns whether it wasn't.
+	pub fn map(&self) -> bool {
+		if self.0.replace(true) {
+			return false;
+		}
+		STACK.with(|stack| stack.borrow_mut().push(Rc::downgrade(&self.0)));
+		true
+	}
+
+	/// Unmap the surface, if it's mapped. Returns whether it was.
+	pub fn unmap(&self) -> bool {
+		if !self.0.replace(false) {
+			return false;
+		}
+		STACK.with(|stack| stack.borrow_mut().retain(|mapped| !ptr::eq(mapped.as_ptr(), Rc::as_ptr(&self.0))));
+		true
+	}
+
+	/// Put the surface on top of the stack, giving it focus, if it's mapped. Returns whether it is.
+	pub fn raise(&self) -> bool {
+		if !self.0.get() {
+			return false;
+		}
+		STACK.with(|stack| {
+			let mut stack = stack.borrow_mut();
+			stack.retain(|mapped| !ptr::eq(mapped.as_ptr(), Rc::as_ptr(&self.0)));
+			stack.push(Rc::downgrade(&self.0));
+		});
+		true
+	}
+
+	/// Whether the surface is the topmost mapped one.
+	pub fn has_focus(&self) -> bool {
+		STACK.with(|stack| stack.borrow().last().map_or(false, |top| ptr::eq(top.as_ptr(), Rc::as_ptr(&self.0))))
+	}
+}
+
+/// Take surfaces off the stack that were dropped or unmapped without [`Mapping::unmap`] getting to it, which only
+/// happens when a panic interrupts serving their client. Left there, they could keep focus from the window below.
+pub fn forget_unmapped() {
+	STACK.with(|stack| stack.borrow_mut().retain(|mapped| mapped.upgrade().map_or(false, |mapped| mapped.get())));
+}
+
+/// A role a surface can be given, named after the interface of the object that gives it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RoleKind {
+	XdgToplevel,
+	XdgPopup,
+	WlShellSurface,
+	FullscreenShellSurface,
+}
+
+impl fmt::Display for RoleKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::XdgToplevel => "xdg_toplevel",
+			Self::XdgPopup => "xdg_popup",
+			Self::WlShellSurface => "wl_shell_surface",
+			Self::FullscreenShellSurface => "zwp_fullscreen_shell_v1",
+		})
+	}
+}
+
+/// The role of a surface, shared with the objects that can give it one.
+///
+/// A surface keeps the first role it's given for good: after the role object is destroyed, it can only be given the
+/// same role again.
+#[derive(Clone, Debug, Default)]
+pub struct SurfaceRole(Rc<Cell<Option<RoleKind>>>);
+
+impl SurfaceRole {
+	pub fn get(&self) -> Option<RoleKind> {
+		self.0.get()
+	}
+
+	/// Give the surface `role`, or fail with error `code` on `object` if it already has a different one. Requests that
+	/// assign roles name the error to raise, and the object to raise it on, for this case.
+	pub fn assign<T>(&self, role: RoleKind, object: Id<T>, code: impl Into<u32>) -> Result<(), ProtocolError> {
+		match self.0.get() {
+			Some(previous) if previous != role => {
+				Err(ProtocolError::new(code, format!("surface already has the {previous} role, not {role}")).on(object))
+			},
+			_ => {
+				self.0.set(Some(role));
+				Ok(())
+			},
+		}
+	}
+}
+
 #[derive(Debug, Default)]
 pub enum WindowRole {
 	#[default]
@@ -8,8 +209,55 @@ pub enum WindowRole {
 
 #[derive(Debug)]
 pub struct ToplevelRole {
+	/// The `xdg_toplevel` or `wl_shell_surface` object, which errors about this role are posted on
+	pub id: Id<AnyObject>,
 	pub title: Option<Box<str>>,
 	pub app_id: Option<Box<str>>,
+	/// Toplevel this one is stacked above, set with `xdg_toplevel.set_parent` or `zxdg_imported_v2.set_parent_of`
+	pub parent: Option<Weak<RefCell<WindowRole>>>,
+	pub size_limits: SizeLimits,
+	/// Size limits to apply on the next commit
+	pub pending_size_limits: SizeLimits,
+}
+
+impl ToplevelRole {
+	pub fn new(id: Id<AnyObject>) -> Self {
+		Self {
+			id,
+			title: None,
+			app_id: None,
+			parent: None,
+			size_limits: SizeLimits::default(),
+			pending_size_limits: SizeLimits::default(),
+		}
+	}
+}
+
+/// Whether making `parent` the parent of the toplevel `child` would make `child` its own ancestor, either because it
+/// is `parent` itself or because `parent` descends from it.
+///
+/// Parents are checked with this as they're set, so `parent`'s ancestors shouldn't cycle already. If they do anyway,
+/// that counts as a cycle too, rather than walking around it forever.
+pub fn would_cycle(child: &Rc<RefCell<WindowRole>>, parent: &Weak<RefCell<WindowRole>>) -> bool {
+	let mut seen = HashSet::new();
+	let mut ancestor = parent.upgrade();
+	while let Some(role) = ancestor {
+		if Rc::ptr_eq(&role, child) || !seen.insert(Rc::as_ptr(&role)) {
+			return true;
+		}
+		ancestor = match &*role.borrow() {
+			WindowRole::Toplevel(toplevel) => toplevel.parent.as_ref().and_then(Weak::upgrade),
+			_ => None,
+		};
+	}
+	false
+}
+
+/// Minimum and maximum size of a toplevel, in window geometry coordinates. Zero means no limit in that dimension.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SizeLimits {
+	pub min: Size,
+	pub max: Size,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,46 @@
+//! Binding the listening socket, which only goes in directories that nobody but the user can get into.
+
+use myway::accept::Accept;
+use std::{
+	fs::{DirBuilder, Permissions},
+	io::ErrorKind,
+	os::unix::fs::{DirBuilderExt, PermissionsExt},
+	path::PathBuf,
+};
+
+/// A fresh directory named after the test, with the given mode.
+fn dir(name: &str, mode: u32) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("myway-accept-{}-{name}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	DirBuilder::new().mode(0o700).create(&dir).unwrap();
+	// set explicitly, as the mode given to mkdir is masked by the umask
+	std::fs::set_permissions(&dir, Permissions::from_mode(mode)).unwrap();
+	dir
+}
+
+#[test]
+fn bind_in_private_dir() {
+	let dir = dir("private", 0o700);
+	let accept = Accept::bind(dir.join("wayland")).unwrap();
+	assert_eq!(accept.path(), Some(dir.join("wayland")));
+	drop(accept);
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn bind_refuses_shared_dir() {
+	let dir = dir("shared", 0o777);
+	let err = Accept::bind(dir.join("wayland")).unwrap_err();
+	assert_eq!(err.kind(), ErrorKind::PermissionDenied, "{err}");
+	// nothing was left behind, not even the lock file
+	assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn bind_free_refuses_shared_dir() {
+	let dir = dir("shared-free", 0o755);
+	let err = Accept::bind_free(&dir).unwrap_err();
+	assert_eq!(err.kind(), ErrorKind::PermissionDenied, "{err}");
+	std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,143 @@
+//! Activation tokens: only the focused window can hand out ones that work, each works once and not for long, and
+//! clients the compositor launches get one.
+
+mod common;
+
+use myway::{
+	activation,
+	client::testing::{request, string, Harness},
+	clock::{self, Script},
+	error::ServerError,
+	object_impls::shell,
+	supervisor::Supervisor,
+};
+use std::time::{Duration, Instant};
+
+/// Bind `wl_shm` as object 3, `wl_compositor` as 4, `wl_shell` as 5 and `xdg_activation_v1` as 6, and create a pool 7
+/// with a 16x16 buffer 8. Then map surfaces 9 and 10 as toplevels through shell surfaces 11 and 12, in that order, so
+/// 10 has focus.
+fn setup() -> Harness {
+	shell::enable();
+	let mut harness = common::registry();
+	harness.bind("wl_shm", 1, 3);
+	harness.bind("wl_compositor", 6, 4);
+	harness.bind("wl_shell", 1, 5);
+	harness.bind("xdg_activation_v1", 1, 6);
+	harness.pool(3, 7, 1024);
+	harness.feed(&request(7, 0, &[8, 0, 16, 16, 64, 0]), &[]).unwrap();
+	for surface in [9, 10] {
+		harness.feed(&request(4, 0, &[surface]), &[]).unwrap();
+	}
+	for (shell_surface, surface) in [(11, 9), (12, 10)] {
+		harness.feed(&request(5, 0, &[shell_surface, surface]), &[]).unwrap();
+		harness.feed(&request(shell_surface, 3, &[]), &[]).unwrap();
+		harness.feed(&request(surface, 1, &[8, 0, 0]), &[]).unwrap();
+		harness.feed(&request(surface, 6, &[]), &[]).unwrap();
+	}
+	harness
+}
+
+/// Ask for a token as object `id`, on behalf of `surface` if given, and return it.
+fn token(harness: &mut Harness, id: u32, surface: Option<u32>) -> String {
+	harness.feed(&request(6, 1, &[id]), &[]).unwrap();
+	if let Some(surface) = surface {
+		harness.feed(&request(id, 2, &[surface]), &[]).unwrap();
+	}
+	let before = harness.words().len();
+	harness.feed(&request(id, 3, &[]), &[]).unwrap();
+	// xdg_activation_token_v1.done(token)
+	match harness.words()[before..] {
+		[object, header, len, ref token @ ..] if object == id && header & 0xffff == 0 => {
+			let bytes: Vec<u8> = token.iter().flat_map(|word| word.to_ne_bytes()).collect();
+			String::from_utf8(bytes[..len as usize - 1].to_vec()).unwrap()
+		},
+		ref other => panic!("expected xdg_activation_token_v1.done, got {other:?}"),
+	}
+}
+
+/// Activate `surface` with `token`.
+fn activate(harness: &mut Harness, token: &str, surface: u32) {
+	let mut args = string(token);
+	args.push(surface);
+	harness.feed(&request(6, 2, &args), &[]).unwrap();
+}
+
+#[test]
+fn only_the_focused_window_passes_focus_on() {
+	common::isolated(|| {
+		let mut harness = setup();
+		let refused = token(&mut harness, 13, Some(9));
+		assert!(!activation::redeem(&refused), "unfocused surface got a token");
+		let anonymous = token(&mut harness, 14, None);
+		assert!(!activation::redeem(&anonymous), "token without a surface");
+
+		let handed_on = token(&mut harness, 15, Some(10));
+		activate(&mut harness, &handed_on, 9);
+		// surface 9 has focus now, so it can hand it on in turn
+		assert!(activation::redeem(&token(&mut harness, 16, Some(9))));
+		assert!(!activation::redeem(&token(&mut harness, 17, Some(10))));
+
+		// the token was used up
+		activate(&mut harness, &handed_on, 10);
+		assert!(activation::redeem(&token(&mut harness, 18, Some(9))));
+	});
+}
+
+#[test]
+fn tokens_work_once() {
+	common::isolated(|| {
+		let token = activation::issue(Some("org.example.app"));
+		assert!(activation::redeem(&token));
+		assert!(!activation::redeem(&token));
+		assert!(!activation::redeem(&activation::refuse()));
+	});
+}
+
+#[test]
+fn tokens_expire() {
+	common::isolated(|| {
+		let lifetime = activation::LIFETIME.as_millis();
+		clock::replay(Script::parse(&format!("0\n+{lifetime}\n+1\n")).unwrap());
+		clock::begin_frame();
+		let (kept, expiring) = (activation::issue(None), activation::issue(None));
+		clock::begin_frame();
+		assert!(activation::redeem(&kept), "token expired early");
+		clock::begin_frame();
+		assert!(!activation::redeem(&expiring), "token used after it expired");
+	});
+}
+
+#[test]
+fn tokens_are_committed_once() {
+	common::isolated(|| {
+		let mut harness = setup();
+		token(&mut harness, 13, Some(10));
+		for (opcode, args) in [(1, string("org.example.app")), (2, vec![9]), (3, vec![])] {
+			match harness.feed(&request(13, opcode, &args), &[]) {
+				Err(ServerError::Protocol(err)) => assert_eq!(err.code, 0, "not already_used: {err:?}"),
+				other => panic!("expected a protocol error, got {other:?}"),
+			}
+			harness = setup();
+			token(&mut harness, 13, Some(10));
+		}
+	});
+}
+
+#[test]
+fn launched_clients_get_a_token() {
+	common::isolated(|| {
+		let path = std::env::temp_dir().join(format!("myway-activation-{}-token", std::process::id()));
+		let now = Instant::now();
+		let mut supervisor =
+			Supervisor::new([format!("printf %s \"$XDG_ACTIVATION_TOKEN\" > {}", path.display())], now);
+		supervisor.restart_due(now);
+		let deadline = Instant::now() + Duration::from_secs(10);
+		while supervisor.reap(now) == myway::supervisor::Reaped::Continue {
+			assert!(Instant::now() < deadline, "client didn't exit");
+			std::thread::sleep(Duration::from_millis(5));
+		}
+		let token = std::fs::read_to_string(&path).unwrap();
+		std::fs::remove_file(path).unwrap();
+		assert!(activation::redeem(&token), "launched client's token {token:?} doesn't work");
+	});
+}
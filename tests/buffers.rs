@@ -0,0 +1,203 @@
+//! Property tests of the socket buffers: whatever sizes the reads and writes on the socket come in, every message and
+//! file descriptor arrives whole, once, and in order. Run with `--features buffer-checks` to also check the buffers'
+//! indices after every step.
+
+mod common;
+
+use common::connect;
+use myway::{client::Client, protocol::Id};
+use nix::{
+	cmsg_space,
+	sys::socket::{recvmsg, sendmsg, setsockopt, sockopt::SndBuf, ControlMessage, ControlMessageOwned, MsgFlags},
+};
+use proptest::{collection::vec, prelude::*};
+use std::{
+	fs::File,
+	io::{IoSlice, IoSliceMut},
+	os::unix::{
+		fs::MetadataExt,
+		io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+		net::UnixStream,
+	},
+	task::Poll,
+};
+
+/// A message as it goes over the wire, with the files whose descriptors it carries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Message {
+	object: u32,
+	opcode: u16,
+	args: Vec<u32>,
+	files: Vec<usize>,
+}
+
+impl Message {
+	fn encode(&self) -> Vec<u8> {
+		let len = 8 + 4 * self.args.len() as u32;
+		let words = [self.object, len << 16 | u32::from(self.opcode)].into_iter().chain(self.args.iter().copied());
+		words.flat_map(u32::to_ne_bytes).collect()
+	}
+}
+
+fn message(max_args: usize, max_files: usize) -> impl Strategy<Value = Message> {
+	(1..100u32, any::<u16>(), vec(any::<u32>(), 0..max_args), vec(0..FILES, 0..=max_files))
+		.prop_map(|(object, opcode, args, files)| Message { object, opcode, args, files })
+}
+
+/// Number of distinct files messages carry descriptors of.
+const FILES: usize = 4;
+
+/// Files told apart by their inodes.
+fn files() -> Vec<File> {
+	(0..FILES)
+		.map(|n| {
+			let path = std::env::temp_dir().join(format!("myway-buffers-{}-{n}", std::process::id()));
+			let file = File::create(&path).unwrap();
+			std::fs::remove_file(path).unwrap();
+			file
+		})
+		.collect()
+}
+
+fn inode(fd: RawFd) -> u64 {
+	// borrow the descriptor as a file without closing it
+	let file = std::mem::ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+	file.metadata().unwrap().ino()
+}
+
+/// Receive whatever the client has been sent, up to `len` bytes, appending the bytes to `bytes` and the inodes of the
+/// descriptors to `inodes`.
+fn read_some(peer: &UnixStream, len: usize, bytes: &mut Vec<u8>, inodes: &mut Vec<u64>) {
+	let mut buf = vec![0; len];
+	let mut cmsg = cmsg_space!([RawFd; 16]);
+	let iov = &mut [IoSliceMut::new(&mut buf)];
+	let msg = match recvmsg::<()>(peer.as_raw_fd(), iov, Some(&mut cmsg), MsgFlags::MSG_CMSG_CLOEXEC) {
+		Ok(msg) => msg,
+		Err(nix::errno::Errno::EAGAIN) => return,
+		Err(err) => panic!("recvmsg failed: {err}"),
+	};
+	assert!(!msg.flags.contains(MsgFlags::MSG_CTRUNC), "file descriptors were discarded");
+	for cmsg in msg.cmsgs() {
+		if let ControlMessageOwned::ScmRights(fds) = cmsg {
+			for fd in fds {
+				let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+				inodes.push(inode(fd.as_raw_fd()));
+			}
+		}
+	}
+	let n = msg.bytes;
+	bytes.extend_from_slice(&buf[..n]);
+}
+
+proptest! {
+	/// Requests split into chunks at arbitrary points, each chunk carrying the descriptors of the requests it
+	/// completes. Their total is several times the receive buffer.
+	#[test]
+	fn requests_arrive_whole(
+		requests in vec(message(200, 1), 1..40),
+		chunks in vec(1..64usize, 1..64),
+	) {
+		let files = files();
+		let (mut client, peer) = connect();
+		let stream: Vec<u8> = requests.iter().flat_map(Message::encode).collect();
+		// byte offset each request ends at
+		let ends: Vec<usize> = requests
+			.iter()
+			.scan(0, |end, request| {
+				*end += 8 + 4 * request.args.len();
+				Some(*end)
+			})
+			.collect();
+
+		let mut received = Vec::new();
+		let mut sent = 0;
+		for &chunk in chunks.iter().cycle() {
+			if sent == stream.len() {
+				break;
+			}
+			let end = Ord::min(sent + chunk, stream.len());
+			let fds: Vec<RawFd> = requests
+				.iter()
+				.zip(&ends)
+				.filter(|&(_, &request_end)| sent < request_end && request_end <= end)
+				.flat_map(|(request, _)| request.files.iter().map(|&i| files[i].as_raw_fd()))
+				.collect();
+			let rights = [ControlMessage::ScmRights(&fds)];
+			let cmsgs: &[ControlMessage<'_>] = if fds.is_empty() { &[] } else { &rights };
+			let iov = [IoSlice::new(&stream[sent..end])];
+			let n = sendmsg::<()>(peer.as_raw_fd(), &iov, cmsgs, MsgFlags::empty(), None).unwrap();
+			prop_assert_eq!(n, end - sent);
+			sent = end;
+
+			let (_, mut recv, _) = client.split_mut();
+			while let Poll::Ready(message) = recv.poll_recv() {
+				let mut message = message.unwrap();
+				let mut args = Vec::new();
+				while let Ok(arg) = message.take() {
+					args.push(arg);
+				}
+				let expected = &requests[received.len()];
+				let inodes: Vec<u64> =
+					expected.files.iter().map(|_| inode(message.take_fd().unwrap().as_raw_fd())).collect();
+				// the test keeps the files open, so the client closing the received descriptors doesn't matter
+				received.push((u32::from(message.object_id()), message.opcode(), args, inodes));
+			}
+		}
+
+		prop_assert_eq!(received.len(), requests.len());
+		for (request, (object, opcode, args, inodes)) in requests.iter().zip(received) {
+			prop_assert_eq!((request.object, request.opcode, &request.args), (object, opcode, &args));
+			let expected: Vec<u64> = request.files.iter().map(|&i| inode(files[i].as_raw_fd())).collect();
+			prop_assert_eq!(expected, inodes);
+		}
+	}
+
+	/// Events submitted while the client reads in chunks of arbitrary sizes, or doesn't read at all, so the outgoing
+	/// buffer is flushed in parts and compacted around what's left.
+	#[test]
+	fn events_are_sent_whole(
+		events in vec(message(300, 2), 1..60),
+		reads in vec(0..2048usize, 1..32),
+	) {
+		let files = files();
+		let (server, peer) = UnixStream::pair().unwrap();
+		server.set_nonblocking(true).unwrap();
+		peer.set_nonblocking(true).unwrap();
+		// as little socket buffer as the kernel allows, so sends are partial
+		setsockopt(server.as_raw_fd(), SndBuf, &1).unwrap();
+		let mut client = Client::new(server, 0);
+		let (mut send, ..) = client.split_mut();
+		let stream: Vec<u8> = events.iter().flat_map(Message::encode).collect();
+		let expected_inodes: Vec<u64> =
+			events.iter().flat_map(|event| event.files.iter().map(|&i| inode(files[i].as_raw_fd()))).collect();
+		let (mut bytes, mut inodes) = (Vec::new(), Vec::new());
+		let mut reads = reads.iter().cycle();
+
+		for event in &events {
+			let object = Id::new(event.object).unwrap();
+			let mut message = loop {
+				// the buffer may be full until the client reads some
+				match send.submit(object, event.opcode, event.args.len(), event.files.len()) {
+					Ok(message) => break message,
+					Err(_) => read_some(&peer, 4096, &mut bytes, &mut inodes),
+				}
+			};
+			message.write_all(&event.args);
+			for &i in &event.files {
+				message.write_fd(&files[i]);
+			}
+			message.finish();
+			// or not, letting the socket fill up
+			match reads.next().unwrap() {
+				0 => (),
+				&len => read_some(&peer, len, &mut bytes, &mut inodes),
+			}
+		}
+		while bytes.len() < stream.len() {
+			let _ = send.poll_flush();
+			read_some(&peer, 4096, &mut bytes, &mut inodes);
+		}
+		prop_assert!(bytes == stream, "sent {} bytes, received {} different ones", stream.len(), bytes.len());
+		prop_assert_eq!(inodes, expected_inodes);
+	}
+}
@@ -0,0 +1,44 @@
+//! Replaying frame times from a script.
+
+mod common;
+
+use myway::clock::{self, Script};
+use std::io::ErrorKind;
+
+fn frames(count: usize) -> Vec<u32> {
+	(0..count).map(|_| clock::begin_frame().millis()).collect()
+}
+
+#[test]
+fn absolute_and_relative_times() {
+	common::isolated(|| {
+		clock::replay(Script::parse("# a dropped frame\n1000\n+16\n\n+33\n1066\n").unwrap());
+		assert_eq!(frames(4), [1000, 1016, 1049, 1066]);
+		assert_eq!(clock::frame_time().millis(), 1066);
+	});
+}
+
+#[test]
+fn last_interval_repeats() {
+	common::isolated(|| {
+		clock::replay(Script::parse("500\n+10\n").unwrap());
+		assert_eq!(frames(5), [500, 510, 520, 530, 540]);
+	});
+}
+
+#[test]
+fn single_time_stands_still() {
+	common::isolated(|| {
+		clock::replay(Script::parse("+7").unwrap());
+		assert_eq!(frames(3), [7, 7, 7]);
+	});
+}
+
+#[test]
+fn rejects_bad_scripts() {
+	let error = Script::parse("100\n50\n").unwrap_err();
+	assert_eq!(error.kind(), ErrorKind::InvalidData);
+	assert!(error.to_string().starts_with("line 2:"), "{error}");
+	assert_eq!(Script::parse("+-5").unwrap_err().kind(), ErrorKind::InvalidData);
+	assert_eq!(Script::parse("soon").unwrap_err().kind(), ErrorKind::InvalidData);
+}
@@ -0,0 +1,38 @@
+//! Helpers shared by the tests. Not every test uses every helper.
+#![allow(dead_code)]
+
+use myway::client::{
+	testing::{request, Harness},
+	Client,
+};
+use std::{os::unix::net::UnixStream, panic, thread};
+
+/// A client connected to a socket the test holds the other end of. Both ends are nonblocking.
+///
+/// This is for the tests of the transport itself, which work the socket directly. Everything else drives the client
+/// through a [`Harness`].
+pub fn connect() -> (Client, UnixStream) {
+	let (server, peer) = UnixStream::pair().unwrap();
+	server.set_nonblocking(true).unwrap();
+	peer.set_nonblocking(true).unwrap();
+	(Client::new(server, 0), peer)
+}
+
+/// A harness whose client created the registry as object 2, for binding globals from.
+pub fn registry() -> Harness {
+	let mut harness = Harness::new();
+	harness.feed(&request(1, 1, &[2]), &[]).unwrap();
+	harness
+}
+
+/// Run `test` on a thread of its own, passing on its panic if it fails.
+///
+/// The compositor serves every client from one thread, so what clients share lives in thread locals: the window stack,
+/// the frame clock, activation tokens, globals added at runtime, and whether `wl_shell` or kiosk mode is enabled. A
+/// fresh thread starts with none of it, so a test that changes any of these doesn't leak them into the next, even
+/// when libtest runs tests one after another on the same thread.
+pub fn isolated(test: impl FnOnce() + Send + 'static) {
+	if let Err(payload) = thread::spawn(test).join() {
+		panic::resume_unwind(payload);
+	}
+}
@@ -0,0 +1,132 @@
+//! The sequence an `xdg_surface` goes through before it may have a buffer: an initial commit without one, answered
+//! with a configure, which the client acks.
+
+mod common;
+
+use myway::{
+	client::testing::{request, Harness},
+	error::ServerError,
+};
+
+/// Bind `wl_shm` as object 3, `wl_compositor` as 4 and `xdg_wm_base` as 5. Then create a pool 6 with a 16x16 buffer 7,
+/// and a surface 8 with an `xdg_surface` 9.
+fn setup() -> Harness {
+	let mut harness = common::registry();
+	harness.bind("wl_shm", 1, 3);
+	harness.bind("wl_compositor", 6, 4);
+	harness.bind("xdg_wm_base", 5, 5);
+	harness.pool(3, 6, 1024);
+	harness.feed(&request(6, 0, &[7, 0, 16, 16, 64, 0]), &[]).unwrap();
+	harness.feed(&request(4, 0, &[8]), &[]).unwrap();
+	harness.feed(&request(5, 2, &[9, 8]), &[]).unwrap();
+	harness.events();
+	harness
+}
+
+/// Attach `buffer` (0 for none) to surface 8 and commit it.
+fn commit(harness: &mut Harness, buffer: u32) -> Result<(), ServerError> {
+	harness.feed(&request(8, 1, &[buffer, 0, 0]), &[])?;
+	harness.feed(&request(8, 6, &[]), &[])
+}
+
+/// Code of the `xdg_surface` error in `result`.
+fn xdg_surface_error(result: Result<(), ServerError>) -> u32 {
+	match result {
+		Err(ServerError::Protocol(err)) => {
+			assert_eq!(err.object.map(u32::from), Some(9), "{}", err.message);
+			err.code
+		},
+		other => panic!("expected a protocol error, got {other:?}"),
+	}
+}
+
+#[test]
+fn initial_commit_is_configured() {
+	let mut harness = setup();
+	harness.feed(&request(9, 1, &[10]), &[]).unwrap();
+	commit(&mut harness, 0).unwrap();
+	let events = harness.events();
+	// xdg_toplevel.configure with no size and no states, then xdg_surface.configure
+	let configure: Vec<_> = events.iter().filter(|&&(object, _, _)| object == 9 || object == 10).collect();
+	assert_eq!(configure.len(), 3, "{configure:?}");
+	assert_eq!(configure[0], &(10, 3, vec![0])); // empty wm_capabilities
+	assert_eq!(configure[1], &(10, 0, vec![0, 0, 0]));
+	assert_eq!(configure[2].0, 9);
+	let serial = configure[2].2[0];
+
+	harness.feed(&request(9, 4, &[serial]), &[]).unwrap();
+	commit(&mut harness, 7).unwrap();
+	harness.events();
+	// later commits aren't configured again
+	commit(&mut harness, 0).unwrap();
+	let events = harness.events();
+	assert!(events.iter().all(|&(object, _, _)| object != 9 && object != 10), "{events:?}");
+}
+
+#[test]
+fn buffer_before_initial_commit() {
+	let mut harness = setup();
+	harness.feed(&request(9, 1, &[10]), &[]).unwrap();
+	assert_eq!(xdg_surface_error(commit(&mut harness, 7)), 3, "expected unconfigured_buffer");
+}
+
+#[test]
+fn buffer_before_ack() {
+	let mut harness = setup();
+	harness.feed(&request(9, 1, &[10]), &[]).unwrap();
+	commit(&mut harness, 0).unwrap();
+	assert_eq!(xdg_surface_error(commit(&mut harness, 7)), 3, "expected unconfigured_buffer");
+}
+
+#[test]
+fn ack_of_unsent_serial() {
+	let mut harness = setup();
+	harness.feed(&request(9, 1, &[10]), &[]).unwrap();
+	commit(&mut harness, 0).unwrap();
+	let events = harness.events();
+	let serial = events.iter().find(|&&(object, opcode, _)| (object, opcode) == (9, 0)).unwrap().2[0];
+	let result = harness.feed(&request(9, 4, &[serial.wrapping_add(1)]), &[]);
+	assert_eq!(xdg_surface_error(result), 4, "expected invalid_serial");
+}
+
+#[test]
+fn commit_without_role() {
+	let mut harness = setup();
+	assert_eq!(xdg_surface_error(commit(&mut harness, 0)), 1, "expected not_constructed");
+}
+
+#[test]
+fn unsupported_requests_are_ignored() {
+	let mut harness = setup();
+	harness.feed(&request(9, 1, &[10]), &[]).unwrap();
+	commit(&mut harness, 0).unwrap();
+	// xdg_surface.set_window_geometry, then a positioner 11 is set up and destroyed
+	harness.feed(&request(9, 3, &[0, 0, 16, 16]), &[]).unwrap();
+	harness.feed(&request(5, 1, &[11]), &[]).unwrap();
+	harness.feed(&request(11, 1, &[8, 8]), &[]).unwrap();
+	harness.feed(&request(11, 2, &[0, 0, 16, 16]), &[]).unwrap();
+	harness.feed(&request(11, 0, &[]), &[]).unwrap();
+	// xdg_toplevel.set_maximized, unset_maximized, set_fullscreen, unset_fullscreen and set_minimized
+	for (opcode, args) in [(9, &[][..]), (10, &[]), (11, &[0]), (12, &[]), (13, &[])] {
+		harness.feed(&request(10, opcode, args), &[]).unwrap();
+	}
+	// xdg_wm_base.destroy
+	harness.feed(&request(5, 0, &[]), &[]).unwrap();
+}
+
+#[test]
+fn destroyed_xdg_surface_is_forgotten() {
+	let mut harness = setup();
+	harness.feed(&request(9, 0, &[]), &[]).unwrap();
+	// nothing left to configure, so the commit neither fails nor gets a configure
+	commit(&mut harness, 0).unwrap();
+	let events = harness.events();
+	assert!(events.iter().all(|&(object, _, _)| object != 9), "{events:?}");
+
+	// the surface can get a new xdg_surface 10, whose toplevel 11 is configured as usual
+	harness.feed(&request(5, 2, &[10, 8]), &[]).unwrap();
+	harness.feed(&request(10, 1, &[11]), &[]).unwrap();
+	commit(&mut harness, 0).unwrap();
+	let events = harness.events();
+	assert!(events.iter().any(|&(object, opcode, _)| (object, opcode) == (10, 0)), "{events:?}");
+}
@@ -0,0 +1,57 @@
+//! Inspecting a client's state through `myway_debug_v1`, dispatched the way the main loop does.
+
+mod common;
+
+use myway::client::testing::{parse_string, request, Harness};
+
+/// Bind `wl_compositor` as object 3 and `myway_debug_v1` as object 4.
+fn setup() -> Harness {
+	let mut harness = common::registry();
+	harness.bind("wl_compositor", 6, 3);
+	harness.bind("myway_debug_v1", 1, 4);
+	harness.events();
+	harness
+}
+
+#[test]
+fn lists_objects_in_order() {
+	let mut harness = setup();
+	harness.feed(&request(3, 0, &[5]), &[]).unwrap();
+	harness.feed(&request(4, 1, &[6]), &[]).unwrap();
+
+	let events = harness.events();
+	let listed: Vec<_> = events
+		.iter()
+		.filter(|&&(object, opcode, _)| (object, opcode) == (6, 0))
+		.map(|(_, _, args)| (args[0], parse_string(&args[1..])))
+		.collect();
+	let expected = [
+		(1, "wl_display"),
+		(2, "wl_registry"),
+		(3, "wl_compositor"),
+		(4, "myway_debug_v1"),
+		(5, "wl_surface"),
+		(6, "myway_debug_object_list_v1"),
+	];
+	assert_eq!(listed, expected.map(|(id, interface)| (id, interface.to_owned())));
+	// done, followed by wl_display.delete_id freeing the list's ID
+	let tail: Vec<_> =
+		events[events.len() - 2..].iter().map(|(object, opcode, args)| (*object, *opcode, &args[..])).collect();
+	assert_eq!(tail, [(6, 1, &[][..]), (1, 1, &[6][..])]);
+	let objects = harness.client().objects();
+	assert!(objects.iter().all(|(id, _)| u32::from(id) != 6), "list outlived its done event");
+}
+
+#[test]
+fn counts_surface_commits() {
+	let mut harness = setup();
+	harness.feed(&request(3, 0, &[5]), &[]).unwrap();
+	for _ in 0..3 {
+		harness.feed(&request(5, 6, &[]), &[]).unwrap();
+	}
+	harness.feed(&request(4, 2, &[6, 5]), &[]).unwrap();
+
+	let events = harness.events();
+	let stats = events.iter().find(|&&(object, opcode, _)| (object, opcode) == (6, 0)).unwrap();
+	assert_eq!(stats.2[..2], [0, 3]);
+}
@@ -0,0 +1,120 @@
+//! Clients that go away, cleanly or in the middle of a message.
+
+mod common;
+
+use common::connect;
+use myway::{
+	client::Client,
+	epoll::{Epoll, Event, EPOLLHUP, EPOLLIN, EPOLLRDHUP},
+	protocol::Word,
+};
+use std::{
+	io::{ErrorKind, Write},
+	net::Shutdown,
+	os::unix::net::UnixStream,
+	task::Poll,
+	time::Duration,
+};
+
+/// Header of a message to `wl_display@1` with opcode 1 (`get_registry`), `len` bytes long.
+fn header(len: u16) -> [u8; 8] {
+	let words: [Word; 2] = [1, u32::from(len) << 16 | 1];
+	let mut bytes = [0; 8];
+	bytes[..4].copy_from_slice(&words[0].to_ne_bytes());
+	bytes[4..].copy_from_slice(&words[1].to_ne_bytes());
+	bytes
+}
+
+/// Poll for the next message, returning its object and opcode.
+fn recv(client: &mut Client) -> Poll<std::io::Result<(u32, u16)>> {
+	let (_, mut recv, _) = client.split_mut();
+	recv.poll_recv().map_ok(|msg| (msg.object_id().into(), msg.opcode()))
+}
+
+fn has_partial_message(client: &mut Client) -> bool {
+	let (_, recv, _) = client.split_mut();
+	recv.has_partial_message()
+}
+
+#[test]
+fn closed_between_messages() {
+	let (mut client, mut peer) = connect();
+	peer.write_all(&header(12)).unwrap();
+	peer.write_all(&2u32.to_ne_bytes()).unwrap();
+	drop(peer);
+
+	assert!(matches!(recv(&mut client), Poll::Ready(Ok((1, 1)))));
+	match recv(&mut client) {
+		Poll::Ready(Err(err)) => assert_eq!(err.kind(), ErrorKind::UnexpectedEof),
+		other => panic!("expected EOF, got {other:?}"),
+	}
+	assert!(!has_partial_message(&mut client));
+}
+
+#[test]
+fn closed_mid_header() {
+	let (mut client, mut peer) = connect();
+	peer.write_all(&header(12)[..6]).unwrap();
+	drop(peer);
+
+	match recv(&mut client) {
+		Poll::Ready(Err(err)) => assert_eq!(err.kind(), ErrorKind::UnexpectedEof),
+		other => panic!("expected EOF, got {other:?}"),
+	}
+	assert!(has_partial_message(&mut client));
+}
+
+#[test]
+fn closed_mid_body() {
+	let (mut client, mut peer) = connect();
+	peer.write_all(&header(16)).unwrap();
+	peer.write_all(&2u32.to_ne_bytes()).unwrap();
+	drop(peer);
+
+	match recv(&mut client) {
+		Poll::Ready(Err(err)) => assert_eq!(err.kind(), ErrorKind::UnexpectedEof),
+		other => panic!("expected EOF, got {other:?}"),
+	}
+	assert!(has_partial_message(&mut client));
+}
+
+#[test]
+fn incomplete_message_is_pending_while_connected() {
+	let (mut client, mut peer) = connect();
+	peer.write_all(&header(16)).unwrap();
+
+	assert!(recv(&mut client).is_pending());
+	assert!(has_partial_message(&mut client));
+}
+
+#[test]
+fn half_closed_still_delivers_requests() {
+	let (mut client, mut peer) = connect();
+	peer.write_all(&header(12)).unwrap();
+	peer.write_all(&2u32.to_ne_bytes()).unwrap();
+	peer.shutdown(Shutdown::Write).unwrap();
+
+	assert!(matches!(recv(&mut client), Poll::Ready(Ok((1, 1)))));
+	assert!(matches!(recv(&mut client), Poll::Ready(Err(err)) if err.kind() == ErrorKind::UnexpectedEof));
+	assert!(!has_partial_message(&mut client));
+}
+
+#[test]
+fn epoll_reports_hangup() {
+	let epoll = Epoll::new().unwrap();
+	let (server, peer) = UnixStream::pair().unwrap();
+	epoll.register(&server, EPOLLIN | EPOLLRDHUP, 7).unwrap();
+
+	peer.shutdown(Shutdown::Write).unwrap();
+	let mut events = [Event::empty(); 4];
+	let ready = epoll.wait_for_activity(&mut events, Some(Duration::from_secs(1))).unwrap();
+	assert_eq!(ready.len(), 1);
+	assert_eq!(ready[0].data(), 7);
+	assert!(ready[0].events().contains(EPOLLRDHUP));
+	assert!(!ready[0].events().contains(EPOLLHUP));
+
+	drop(peer);
+	let ready = epoll.wait_for_activity(&mut events, Some(Duration::from_secs(1))).unwrap();
+	assert_eq!(ready.len(), 1);
+	assert!(ready[0].events().contains(EPOLLHUP));
+}
@@ -0,0 +1,128 @@
+//! Objects implemented with `ObjectDyn` instead of the generated bindings, offered through a global registered at
+//! runtime, and implementations of the generated interface traits used as trait objects.
+
+mod common;
+
+use myway::{
+	client::{
+		testing::{request, Harness, Loopback},
+		RecvMessage, SendHalf,
+	},
+	error::{InvalidRequest, ProtocolError, Result, ServerError},
+	object_impls,
+	object_map::{ObjectDyn, Objects},
+	protocol::{wl_region::WlRegion, AnyObject, Id},
+};
+
+/// Counts `bump` requests (opcode 0). `fail` (1) raises a protocol error, and `destroy` (2) destroys it.
+#[derive(Debug, Default)]
+struct Counter {
+	bumps: u32,
+}
+
+impl ObjectDyn for Counter {
+	fn interface(&self) -> &'static str {
+		"test_counter"
+	}
+
+	fn handle_request(
+		&mut self,
+		self_id: Id<AnyObject>,
+		_objects: &mut Objects,
+		_client: &mut SendHalf<'_>,
+		message: RecvMessage<'_>,
+	) -> Result<bool> {
+		match message.opcode() {
+			0 => {
+				message.finish()?;
+				self.bumps += 1;
+				Ok(true)
+			},
+			1 => Err(ProtocolError::new(7u32, "asked to fail").on(self_id).into()),
+			2 => {
+				message.finish()?;
+				Ok(false)
+			},
+			opcode => Err(InvalidRequest::opcode(self.interface(), self_id, opcode)),
+		}
+	}
+}
+
+/// Register the counter global, and bind it as object 3.
+fn setup() -> Harness {
+	object_impls::register_global("test_counter", 1, |_| Box::new(Counter::default()));
+	let mut harness = common::registry();
+	assert!(harness.advertised("test_counter"));
+	harness.bind("test_counter", 1, 3);
+	harness
+}
+
+/// The counter bound as object 3, as its debug representation, or `None` if it's gone.
+fn counter(harness: &mut Harness) -> Option<String> {
+	let mut objects = harness.client().objects().iter();
+	let (_, object) = objects.find(|&(id, _)| u32::from(id) == 3)?;
+	assert_eq!(object.interface(), "test_counter");
+	Some(format!("{object:?}"))
+}
+
+#[test]
+fn bound_global_handles_requests() {
+	common::isolated(|| {
+		let mut harness = setup();
+		harness.feed(&request(3, 0, &[]), &[]).unwrap();
+		harness.feed(&request(3, 0, &[]), &[]).unwrap();
+		assert!(counter(&mut harness).unwrap().contains("bumps: 2"));
+
+		harness.feed(&request(3, 2, &[]), &[]).unwrap();
+		assert_eq!(counter(&mut harness), None, "destroyed object is still there");
+	});
+}
+
+#[test]
+fn object_is_kept_when_its_handler_fails() {
+	common::isolated(|| {
+		let mut harness = setup();
+		harness.feed(&request(3, 0, &[]), &[]).unwrap();
+		match harness.feed(&request(3, 1, &[]), &[]) {
+			Err(ServerError::Protocol(err)) => assert_eq!((err.code, err.message.as_str()), (7, "asked to fail")),
+			other => panic!("expected a protocol error, got {other:?}"),
+		}
+		assert!(counter(&mut harness).expect("object lost to the error").contains("bumps: 1"));
+	});
+}
+
+/// Sums the area added to it, and takes none away.
+#[derive(Debug, Default)]
+struct Area(i32);
+
+impl WlRegion for Area {
+	fn handle_destroy(self, _client: &mut SendHalf<'_>) -> Result<()> {
+		Ok(())
+	}
+
+	fn handle_add(&mut self, _client: &mut SendHalf<'_>, _x: i32, _y: i32, width: i32, height: i32) -> Result<()> {
+		self.0 += width * height;
+		Ok(())
+	}
+
+	fn handle_subtract(
+		&mut self,
+		_client: &mut SendHalf<'_>,
+		_x: i32,
+		_y: i32,
+		_width: i32,
+		_height: i32,
+	) -> Result<()> {
+		Ok(())
+	}
+}
+
+#[test]
+fn generated_traits_are_object_safe() {
+	let mut area = Area::default();
+	let mut loopback = Loopback::new();
+	let region: &mut dyn WlRegion = &mut area;
+	region.handle_add(&mut loopback.send_half(), 0, 0, 2, 3).unwrap();
+	region.handle_subtract(&mut loopback.send_half(), 0, 0, 1, 1).unwrap();
+	assert_eq!(area.0, 6);
+}
@@ -0,0 +1,118 @@
+//! Parsing EDIDs built byte by byte, with the fields a real monitor would fill in.
+
+use myway::edid::{Edid, Mode};
+use std::io::ErrorKind;
+
+/// An EDID for a 4K monitor named `DELL U2720Q`, with a serial number descriptor.
+fn monitor() -> Vec<u8> {
+	let mut block = vec![0; 128];
+	block[..8].copy_from_slice(&[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]);
+	// "DEL": D=4, E=5, L=12 in five bits each
+	block[8..10].copy_from_slice(&(4u16 << 10 | 5 << 5 | 12).to_be_bytes());
+	block[10..12].copy_from_slice(&0xa0f3u16.to_le_bytes());
+	block[12..16].copy_from_slice(&12345u32.to_le_bytes());
+	block[18] = 1;
+	block[19] = 4;
+	// 60 by 34 centimeters
+	block[21] = 60;
+	block[22] = 34;
+	// standard timings: 1920x1080@60, the rest unused
+	block[38..54].fill(0x01);
+	block[38..40].copy_from_slice(&[(1920 / 8 - 31) as u8, 0b11 << 6]);
+	// 3840x2160, 533.25 MHz pixel clock, 160 pixels horizontal and 62 lines vertical blanking
+	block[54..72].copy_from_slice(&detailed_timing(53325, 3840, 160, 2160, 62));
+	block[72..90].copy_from_slice(&text_descriptor(0xfc, "DELL U2720Q"));
+	block[90..108].copy_from_slice(&text_descriptor(0xff, "ABC1234"));
+	// dummy descriptor
+	block[108..126].copy_from_slice(&[0, 0, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+	fix_checksum(&mut block);
+	block
+}
+
+fn detailed_timing(clock_10khz: u16, width: u16, h_blank: u16, height: u16, v_blank: u16) -> [u8; 18] {
+	let mut timing = [0; 18];
+	timing[..2].copy_from_slice(&clock_10khz.to_le_bytes());
+	timing[2] = width as u8;
+	timing[3] = h_blank as u8;
+	timing[4] = ((width >> 8) << 4 | h_blank >> 8) as u8;
+	timing[5] = height as u8;
+	timing[6] = v_blank as u8;
+	timing[7] = ((height >> 8) << 4 | v_blank >> 8) as u8;
+	timing
+}
+
+fn text_descriptor(tag: u8, text: &str) -> [u8; 18] {
+	let mut descriptor = [0x20; 18];
+	descriptor[..5].copy_from_slice(&[0, 0, 0, tag, 0]);
+	descriptor[5..5 + text.len()].copy_from_slice(text.as_bytes());
+	descriptor[5 + text.len()] = b'\n';
+	descriptor
+}
+
+fn fix_checksum(block: &mut [u8]) {
+	block[127] = 0;
+	let sum = block.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+	block[127] = sum.wrapping_neg();
+}
+
+#[test]
+fn identification() {
+	let edid = Edid::parse(&monitor()).unwrap();
+	assert_eq!(edid.manufacturer, "DEL");
+	assert_eq!(edid.product_code, 0xa0f3);
+	assert_eq!(edid.name.as_deref(), Some("DELL U2720Q"));
+	assert_eq!(edid.serial.as_deref(), Some("ABC1234"));
+	assert_eq!(edid.size_mm, Some([600, 340]));
+	assert_eq!(edid.model(), "DELL U2720Q");
+	assert_eq!(edid.identity().to_string(), "DEL-a0f3-ABC1234");
+}
+
+#[test]
+fn modes() {
+	let edid = Edid::parse(&monitor()).unwrap();
+	let native = Mode { width: 3840, height: 2160, refresh: 59997, preferred: true };
+	let standard = Mode { width: 1920, height: 1080, refresh: 60000, preferred: false };
+	assert_eq!(edid.modes, [native, standard]);
+}
+
+#[test]
+fn numeric_serial_without_descriptors() {
+	let mut block = monitor();
+	block[72..108].fill(0);
+	block[75] = 0x10;
+	block[93] = 0x10;
+	fix_checksum(&mut block);
+	let edid = Edid::parse(&block).unwrap();
+	assert_eq!(edid.name, None);
+	assert_eq!(edid.serial.as_deref(), Some("12345"));
+	assert_eq!(edid.model(), "DEL 0xa0f3");
+}
+
+#[test]
+fn projector_has_no_size() {
+	let mut block = monitor();
+	block[22] = 0;
+	fix_checksum(&mut block);
+	assert_eq!(Edid::parse(&block).unwrap().size_mm, None);
+}
+
+#[test]
+fn extension_blocks_are_ignored() {
+	let mut edid = monitor();
+	edid[126] = 1;
+	fix_checksum(&mut edid);
+	edid.extend([0x02; 128]);
+	assert_eq!(Edid::parse(&edid).unwrap().name.as_deref(), Some("DELL U2720Q"));
+}
+
+#[test]
+fn rejects_corrupt() {
+	let mut block = monitor();
+	block[100] ^= 1;
+	assert_eq!(Edid::parse(&block).unwrap_err().kind(), ErrorKind::InvalidData);
+	assert_eq!(Edid::parse(&monitor()[..64]).unwrap_err().kind(), ErrorKind::InvalidData);
+	let mut block = monitor();
+	block[0] = 1;
+	fix_checksum(&mut block);
+	assert_eq!(Edid::parse(&block).unwrap_err().kind(), ErrorKind::InvalidData);
+}
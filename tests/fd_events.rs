@@ -0,0 +1,102 @@
+//! Events carrying file descriptors, like `wl_keyboard.keymap`: each descriptor stays with the event it was written
+//! into, however many events came before it.
+
+use myway::{
+	client::{testing::Loopback, Client, SendHalf},
+	protocol::{EncodeArg, Id},
+};
+use nix::{
+	cmsg_space,
+	sys::socket::{recvmsg, ControlMessageOwned, MsgFlags},
+};
+use std::{
+	fs::File,
+	io::IoSliceMut,
+	os::unix::{
+		fs::MetadataExt,
+		io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+		net::UnixStream,
+	},
+	task::Poll,
+};
+
+/// A distinct file per `n`, told apart by its inode.
+fn file(n: u32) -> File {
+	let path = std::env::temp_dir().join(format!("myway-fd-events-{}-{n}", std::process::id()));
+	let file = File::create(&path).unwrap();
+	std::fs::remove_file(path).unwrap();
+	file
+}
+
+fn inode(fd: &impl AsRawFd) -> u64 {
+	// borrow the descriptor as a file without closing it
+	let file = std::mem::ManuallyDrop::new(unsafe { File::from_raw_fd(fd.as_raw_fd()) });
+	file.metadata().unwrap().ino()
+}
+
+/// Write an event to object 3 carrying `n` in its body and `files` as file descriptors.
+fn submit(send: &mut SendHalf<'_>, n: u32, files: &[&File]) {
+	let mut message = send.submit(Id::new(3).unwrap(), 0, n.encoded_len().into(), files.len()).unwrap();
+	n.encode(&mut message);
+	for file in files {
+		message.write_fd(*file);
+	}
+	message.finish();
+}
+
+#[test]
+fn fds_stay_with_their_events() {
+	let files: Vec<File> = (0..4).map(file).collect();
+	let mut loopback = Loopback::new();
+	// more events than fit in the buffer at once, so it has to be compacted along the way
+	for round in 0..4 {
+		submit(&mut loopback.send_half(), round, &[&files[0]]);
+		submit(&mut loopback.send_half(), round, &[&files[1], &files[2]]);
+		submit(&mut loopback.send_half(), round, &[]);
+		submit(&mut loopback.send_half(), round, &[&files[3]]);
+
+		for expected in [&[0][..], &[1, 2], &[], &[3]] {
+			let mut message = loopback.recv();
+			assert_eq!(message.take().unwrap(), round);
+			for &i in expected {
+				let fd: OwnedFd = message.take_fd().unwrap();
+				assert_eq!(inode(&fd), inode(&files[i]), "round {round}: wrong file descriptor for file {i}");
+				// the loopback hands out the sender's own descriptors, which mustn't be closed twice
+				std::mem::forget(fd);
+			}
+		}
+	}
+}
+
+#[test]
+fn fds_are_sent_with_their_events() {
+	let (server, peer) = UnixStream::pair().unwrap();
+	server.set_nonblocking(true).unwrap();
+	let mut client = Client::new(server, 0);
+	let files: Vec<File> = (0..3).map(file).collect();
+	let (mut send, ..) = client.split_mut();
+	submit(&mut send, 0, &[&files[0]]);
+	submit(&mut send, 1, &[&files[1], &files[2]]);
+	assert!(matches!(send.poll_flush(), Poll::Ready(Ok(()))));
+
+	let mut bytes = [0; 64];
+	let mut cmsg = cmsg_space!([RawFd; 8]);
+	let msg = recvmsg::<()>(
+		peer.as_raw_fd(),
+		&mut [IoSliceMut::new(&mut bytes)],
+		Some(&mut cmsg),
+		MsgFlags::MSG_CMSG_CLOEXEC,
+	)
+	.unwrap();
+	assert_eq!(msg.bytes, 24, "two events of one argument each");
+	let received: Vec<OwnedFd> = msg
+		.cmsgs()
+		.flat_map(|cmsg| match cmsg {
+			ControlMessageOwned::ScmRights(fds) => fds,
+			_ => Vec::new(),
+		})
+		.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+		.collect();
+	let inodes: Vec<u64> = received.iter().map(inode).collect();
+	assert_eq!(inodes, files.iter().map(inode).collect::<Vec<_>>());
+}
@@ -0,0 +1,96 @@
+//! Parents of toplevels, set with `xdg_toplevel.set_parent` or through `xdg-foreign`: a toplevel can be neither its own
+//! parent nor the parent of one of its ancestors.
+
+mod common;
+
+use myway::{
+	client::testing::{request, string, Harness},
+	error::ServerError,
+};
+
+/// Bind `wl_compositor` as object 3, `xdg_wm_base` as 4, `zxdg_exporter_v2` as 5 and `zxdg_importer_v2` as 6. Then
+/// make surfaces 7 and 8 toplevels 11 and 12, through `xdg_surface`s 9 and 10.
+fn setup() -> Harness {
+	let mut harness = common::registry();
+	harness.bind("wl_compositor", 6, 3);
+	harness.bind("xdg_wm_base", 5, 4);
+	harness.bind("zxdg_exporter_v2", 1, 5);
+	harness.bind("zxdg_importer_v2", 1, 6);
+	harness.feed(&request(3, 0, &[7]), &[]).unwrap();
+	harness.feed(&request(3, 0, &[8]), &[]).unwrap();
+	for (surface, xdg_surface) in [(7, 9), (8, 10)] {
+		harness.feed(&request(4, 2, &[xdg_surface, surface]), &[]).unwrap();
+	}
+	for (xdg_surface, toplevel) in [(9, 11), (10, 12)] {
+		harness.feed(&request(xdg_surface, 1, &[toplevel]), &[]).unwrap();
+	}
+	harness
+}
+
+/// Export `surface` as object 13 and import it again as 14.
+fn export_and_import(harness: &mut Harness, surface: u32) {
+	let before = harness.words().len();
+	harness.feed(&request(5, 1, &[13, surface]), &[]).unwrap();
+	// zxdg_exported_v2.handle(handle)
+	let handle = match harness.words()[before..] {
+		[13, header, len, ref handle @ ..] if header & 0xffff == 0 => {
+			let bytes: Vec<u8> = handle.iter().flat_map(|word| word.to_ne_bytes()).collect();
+			String::from_utf8(bytes[..len as usize - 1].to_vec()).unwrap()
+		},
+		ref other => panic!("expected zxdg_exported_v2.handle, got {other:?}"),
+	};
+	let mut args = vec![14];
+	args.extend(string(&handle));
+	harness.feed(&request(6, 1, &args), &[]).unwrap();
+}
+
+/// Code of the protocol error `result` failed with, checking it was posted on `object`.
+fn error_code(result: Result<(), ServerError>, object: u32) -> u32 {
+	match result {
+		Err(ServerError::Protocol(err)) => {
+			assert_eq!(err.object.map(u32::from), Some(object), "{}", err.message);
+			err.code
+		},
+		other => panic!("expected a protocol error, got {other:?}"),
+	}
+}
+
+#[test]
+fn toplevel_parents() {
+	let mut harness = setup();
+	harness.feed(&request(11, 1, &[12]), &[]).unwrap();
+	// replacing and unsetting the parent is fine
+	harness.feed(&request(11, 1, &[12]), &[]).unwrap();
+	harness.feed(&request(11, 1, &[0]), &[]).unwrap();
+	harness.feed(&request(12, 1, &[11]), &[]).unwrap();
+}
+
+#[test]
+fn toplevel_is_not_its_own_parent() {
+	let mut harness = setup();
+	// refused before it gets to the handler, as the toplevel can't be borrowed as its own argument
+	assert!(matches!(harness.feed(&request(11, 1, &[11]), &[]), Err(ServerError::Protocol(_))));
+}
+
+#[test]
+fn toplevel_is_not_the_parent_of_its_ancestors() {
+	let mut harness = setup();
+	harness.feed(&request(11, 1, &[12]), &[]).unwrap();
+	assert_eq!(error_code(harness.feed(&request(12, 1, &[11]), &[]), 12), 1, "expected invalid_parent");
+}
+
+#[test]
+fn imported_toplevel_is_not_its_own_parent() {
+	let mut harness = setup();
+	export_and_import(&mut harness, 7);
+	assert_eq!(error_code(harness.feed(&request(14, 1, &[7]), &[]), 14), 0, "expected invalid_surface");
+}
+
+#[test]
+fn imported_toplevel_is_not_the_parent_of_its_ancestors() {
+	let mut harness = setup();
+	export_and_import(&mut harness, 7);
+	// surface 8 becomes the parent of 7, so 7 can't become the parent of 8
+	harness.feed(&request(11, 1, &[12]), &[]).unwrap();
+	assert_eq!(error_code(harness.feed(&request(14, 1, &[8]), &[]), 14), 0, "expected invalid_surface");
+}
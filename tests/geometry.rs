@@ -0,0 +1,263 @@
+//! Points, sizes, rectangles and regions: hit testing at fractional positions, conversions to and from the protocol's
+//! fixed-point numbers and between buffer and surface coordinates, and region algebra checked against a bitmap of the
+//! pixels it should cover.
+
+use myway::{
+	geometry::{Point, Rect, Region, Size},
+	protocol::{wl_output::Transform, Fixed},
+};
+use proptest::{collection::vec, prelude::*};
+
+const TRANSFORMS: [Transform; 8] = [
+	Transform::Normal,
+	Transform::_90,
+	Transform::_180,
+	Transform::_270,
+	Transform::Flipped,
+	Transform::Flipped90,
+	Transform::Flipped180,
+	Transform::Flipped270,
+];
+
+fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+	Rect::new(Point::new(x, y), Size::new(width, height))
+}
+
+#[test]
+fn hit_testing_between_pixels() {
+	let window = rect(10, 20, 100, 50);
+	assert!(window.contains(Point::new(10.0, 20.0)), "top left corner is inside");
+	assert!(window.contains(Point::new(109.99, 69.99)));
+	assert!(!window.contains(Point::new(110.0, 30.0)), "right edge belongs to the neighbour");
+	assert!(!window.contains(Point::new(50.0, 70.0)), "bottom edge belongs to the neighbour");
+	assert!(!window.contains(Point::new(9.99, 30.0)));
+	assert!(!rect(0, 0, 0, 10).contains(Point::new(0.0, 5.0)), "empty rectangle");
+}
+
+#[test]
+fn hit_testing_at_the_bounds_of_i32() {
+	let window = rect(i32::MAX - 10, 0, 20, 20);
+	assert!(window.contains(Point::new(f64::from(i32::MAX) + 5.0, 1.0)), "overflowed instead of widening");
+}
+
+#[test]
+fn points_round_trip_through_fixed() {
+	let point = Point::new(12.5, -3.25);
+	assert_eq!(Point::<f64>::from(Point::<Fixed>::from(point)), point);
+	// the nearest 1/256th
+	assert_eq!(Point::<f64>::from(Point::<Fixed>::from(Point::new(0.001, 0.003))), Point::new(0.0, 1.0 / 256.0));
+}
+
+#[test]
+fn pixels_under_points() {
+	assert_eq!(Point::new(1.75, -0.25).floor(), Point::new(1, -1));
+	assert_eq!(Point::new(1e12, -1e12).floor(), Point::new(i32::MAX, i32::MIN));
+	assert_eq!(Point::new(0.5, 1.5) + Point::from(Point::new(2, 3)), Point::new(2.5, 4.5));
+}
+
+#[test]
+fn moving_saturates() {
+	let point = Point::new(i32::MAX - 1, i32::MIN + 1).saturating_add(Point::new(5, -5));
+	assert_eq!(point, Point::new(i32::MAX, i32::MIN));
+}
+
+#[test]
+fn display() {
+	assert_eq!(rect(-4, 8, 640, 480).to_string(), "640x480 at -4,8");
+	assert!(Size::new(0, 5).is_empty());
+	assert!(!Size::new(1, 1).is_empty());
+	let mut region = Region::from(rect(0, 0, 2, 2));
+	region.add(rect(4, 0, 1, 1));
+	assert_eq!(region.to_string(), "2x2 at 0,0, 1x1 at 4,0");
+	assert_eq!(Region::default().to_string(), "empty");
+}
+
+#[test]
+fn intersections() {
+	assert_eq!(rect(0, 0, 10, 10).intersection(rect(5, -5, 10, 10)), Some(rect(5, 0, 5, 5)));
+	assert_eq!(rect(0, 0, 10, 10).intersection(rect(10, 0, 5, 5)), None, "touching edges share no pixels");
+	assert_eq!(rect(0, 0, 10, 10).intersection(rect(2, 2, -1, 5)), None, "negative width");
+	let far = rect(i32::MAX - 5, i32::MAX - 5, i32::MAX, i32::MAX);
+	assert_eq!(far.end(), Point::new(i32::MAX, i32::MAX), "overflowed instead of stopping at the bounds");
+	assert_eq!(far.intersection(rect(i32::MAX - 2, 0, 1, i32::MAX)), Some(rect(i32::MAX - 2, i32::MAX - 5, 1, 5)));
+}
+
+#[test]
+fn subtracting_leaves_a_frame() {
+	let pieces: Vec<_> = rect(0, 0, 10, 10).subtract(rect(3, 4, 2, 2)).collect();
+	assert_eq!(pieces, [rect(0, 0, 10, 4), rect(0, 6, 10, 4), rect(0, 4, 3, 2), rect(5, 4, 5, 2)]);
+	assert_eq!(rect(0, 0, 10, 10).subtract(rect(20, 20, 1, 1)).collect::<Vec<_>>(), [rect(0, 0, 10, 10)]);
+	assert_eq!(rect(0, 0, 10, 10).subtract(rect(-1, -1, 20, 20)).count(), 0);
+	assert_eq!(rect(0, 0, 0, 10).subtract(rect(20, 20, 1, 1)).count(), 0, "empty rectangle");
+}
+
+#[test]
+fn regions_compare_by_pixels() {
+	let mut across = Region::from(rect(0, 0, 4, 2));
+	across.add(rect(0, 2, 4, 2));
+	let mut down = Region::from(rect(0, 0, 2, 4));
+	down.add(rect(2, 0, 2, 4));
+	assert_ne!(across.rects(), down.rects());
+	assert_eq!(across, down);
+	assert_eq!(across, Region::from(rect(0, 0, 4, 4)));
+	down.subtract(rect(3, 3, 1, 1));
+	assert_ne!(across, down);
+	assert_eq!(Region::from(rect(0, 0, 0, 4)), Region::default());
+	assert_eq!(across.extents(), rect(0, 0, 4, 4));
+	assert_eq!(Region::default().extents(), Rect::default());
+}
+
+/// Side of the bitmap regions are checked against, with rectangles reaching a little past it on each side.
+const GRID: i32 = 12;
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+	Add(Rect),
+	Subtract(Rect),
+	Intersect(Rect),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+	let rect = (-2..GRID, -2..GRID, -2..GRID, -2..GRID).prop_map(|(x, y, width, height)| rect(x, y, width, height));
+	prop_oneof![rect.clone().prop_map(Op::Add), rect.clone().prop_map(Op::Subtract), rect.prop_map(Op::Intersect),]
+}
+
+/// Whether `rect` covers the pixel at `x`, `y`.
+fn covers(rect: Rect, x: i32, y: i32) -> bool {
+	let end = rect.end();
+	(rect.origin.x..end.x).contains(&x) && (rect.origin.y..end.y).contains(&y)
+}
+
+proptest! {
+	#[test]
+	fn region_algebra_matches_a_bitmap(ops in vec(op(), 0..24)) {
+		let mut region = Region::default();
+		// pixels from -2 to twice the grid, which rectangles can reach
+		let span = -2..2 * GRID;
+		let mut bitmap = vec![false; span.len() * span.len()];
+		let pixels = || span.clone().flat_map(|y| span.clone().map(move |x| (x, y))).enumerate();
+		for op in ops {
+			match op {
+				Op::Add(rect) => region.add(rect),
+				Op::Subtract(rect) => region.subtract(rect),
+				Op::Intersect(rect) => region.intersect(rect),
+			}
+			for (index, (x, y)) in pixels() {
+				bitmap[index] = match op {
+					Op::Add(rect) => bitmap[index] || covers(rect, x, y),
+					Op::Subtract(rect) => bitmap[index] && !covers(rect, x, y),
+					Op::Intersect(rect) => bitmap[index] && covers(rect, x, y),
+				};
+			}
+
+			for (index, (x, y)) in pixels() {
+				let center = Point::new(f64::from(x) + 0.5, f64::from(y) + 0.5);
+				prop_assert_eq!(region.contains(center), bitmap[index], "pixel {},{} after {:?}", x, y, op);
+			}
+			prop_assert_eq!(region.area(), bitmap.iter().filter(|&&set| set).count() as u64);
+			let rects = region.rects();
+			for (index, &a) in rects.iter().enumerate() {
+				prop_assert!(!a.is_empty());
+				for &b in &rects[index + 1..] {
+					prop_assert_eq!(a.intersection(b), None, "{} and {} overlap", a, b);
+				}
+			}
+			for (index, (x, y)) in pixels() {
+				prop_assert!(!bitmap[index] || covers(region.extents(), x, y), "{},{} outside extents", x, y);
+			}
+		}
+	}
+
+	#[test]
+	fn union_of_regions(a in vec(op(), 0..8), b in vec(op(), 0..8)) {
+		let build = |ops: &[Op]| {
+			let mut region = Region::default();
+			for &op in ops {
+				match op {
+					Op::Add(rect) => region.add(rect),
+					Op::Subtract(rect) => region.subtract(rect),
+					Op::Intersect(rect) => region.intersect(rect),
+				}
+			}
+			region
+		};
+		let (a, b) = (build(&a), build(&b));
+		let mut union = a.clone();
+		union.union(&b);
+		let mut other_way = b.clone();
+		other_way.union(&a);
+		prop_assert_eq!(&union, &other_way);
+		let mut overlap = a.clone();
+		for &rect in b.rects() {
+			overlap.subtract(rect);
+		}
+		prop_assert_eq!(union.area(), overlap.area() + b.area());
+	}
+}
+
+#[test]
+fn quarter_turns_swap_sizes() {
+	for transform in TRANSFORMS {
+		let turned = Size::new(30, 20).transformed(transform);
+		let expected = if (transform as u32) % 2 == 1 { Size::new(20, 30) } else { Size::new(30, 20) };
+		assert_eq!(turned, expected, "{transform:?}");
+	}
+	assert!(Size::new(64, 48).is_multiple_of(16));
+	assert!(!Size::new(64, 49).is_multiple_of(2));
+	assert_eq!(Size::new(65, 48).scaled_down(2), Size::new(32, 24));
+}
+
+#[test]
+fn buffer_and_surface_pixels_correspond() {
+	let surface = Size::new(5, 3);
+	for transform in TRANSFORMS {
+		for scale in 1..=3 {
+			let buffer = surface.transformed(transform);
+			let whole = rect(0, 0, buffer.width * scale, buffer.height * scale);
+			assert_eq!(whole.buffer_to_surface(transform, scale, surface), rect(0, 0, 5, 3), "{transform:?}");
+			let mut covered = Region::default();
+			for y in 0..surface.height {
+				for x in 0..surface.width {
+					let pixel = rect(x, y, 1, 1);
+					let in_buffer = pixel.surface_to_buffer(transform, scale, surface);
+					assert_eq!(in_buffer.size, Size::new(scale, scale), "{transform:?} at scale {scale}");
+					assert_eq!(in_buffer.buffer_to_surface(transform, scale, surface), pixel, "{transform:?}");
+					covered.add(in_buffer);
+				}
+			}
+			assert_eq!(covered, Region::from(whole), "{transform:?} at scale {scale} missed buffer pixels");
+		}
+	}
+}
+
+#[test]
+fn transforms_turn_the_right_way() {
+	// the top left surface pixel, on a buffer turned a quarter counterclockwise, is at the bottom left of the buffer
+	let surface = Size::new(100, 50);
+	let corner = rect(0, 0, 1, 1);
+	let expected = [
+		(Transform::Normal, rect(0, 0, 1, 1)),
+		(Transform::_90, rect(0, 99, 1, 1)),
+		(Transform::_180, rect(99, 49, 1, 1)),
+		(Transform::_270, rect(49, 0, 1, 1)),
+		(Transform::Flipped, rect(99, 0, 1, 1)),
+		(Transform::Flipped90, rect(0, 0, 1, 1)),
+		(Transform::Flipped180, rect(0, 49, 1, 1)),
+		(Transform::Flipped270, rect(49, 99, 1, 1)),
+	];
+	for (transform, in_buffer) in expected {
+		assert_eq!(corner.surface_to_buffer(transform, 1, surface), in_buffer, "{transform:?}");
+	}
+}
+
+#[test]
+fn buffer_damage_rounds_outwards_and_is_cut_off() {
+	let surface = Size::new(10, 10);
+	assert_eq!(rect(3, 3, 2, 2).buffer_to_surface(Transform::Normal, 2, surface), rect(1, 1, 2, 2));
+	assert_eq!(rect(-5, 5, 10, 100).buffer_to_surface(Transform::Normal, 1, surface), rect(0, 5, 5, 5));
+	assert!(rect(-5, -5, 5, 5).buffer_to_surface(Transform::_90, 1, surface).is_empty());
+	let huge = rect(i32::MIN, i32::MIN, i32::MAX, i32::MAX);
+	assert!(huge.buffer_to_surface(Transform::_270, 3, surface).is_empty());
+	let everything = rect(i32::MIN / 2, i32::MIN / 2, i32::MAX, i32::MAX);
+	assert_eq!(everything.buffer_to_surface(Transform::Flipped90, 3, surface), rect(0, 0, 10, 10));
+}
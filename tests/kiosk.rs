@@ -0,0 +1,118 @@
+//! Kiosk mode: toplevels are fullscreen, and `zwp_fullscreen_shell_v1` presents one surface at a time. Whether a
+//! surface is mapped is seen through an idle inhibitor on it.
+
+mod common;
+
+use myway::{
+	client::testing::{request, Harness},
+	error::ServerError,
+	idle, windows,
+};
+
+/// Enable kiosk mode and bind `wl_shm` as object 3, `wl_compositor` as 4, the fullscreen shell as 5 and the idle
+/// inhibit manager as 6. Then create a pool 7 with a 16x16 buffer 8, and surfaces 9 and 10 with idle inhibitors 11 and
+/// 12.
+fn setup() -> Harness {
+	windows::enable_kiosk();
+	let mut harness = common::registry();
+	harness.bind("wl_shm", 1, 3);
+	harness.bind("wl_compositor", 6, 4);
+	harness.bind("zwp_fullscreen_shell_v1", 1, 5);
+	harness.bind("zwp_idle_inhibit_manager_v1", 1, 6);
+	harness.pool(3, 7, 1024);
+	harness.feed(&request(7, 0, &[8, 0, 16, 16, 64, 0]), &[]).unwrap();
+	harness.feed(&request(4, 0, &[9]), &[]).unwrap();
+	harness.feed(&request(4, 0, &[10]), &[]).unwrap();
+	harness.feed(&request(6, 1, &[11, 9]), &[]).unwrap();
+	harness.feed(&request(6, 1, &[12, 10]), &[]).unwrap();
+	harness
+}
+
+/// Attach `buffer` (0 for none) to `surface` and commit it.
+fn commit(harness: &mut Harness, surface: u32, buffer: u32) {
+	harness.feed(&request(surface, 1, &[buffer, 0, 0]), &[]).unwrap();
+	harness.feed(&request(surface, 6, &[]), &[]).unwrap();
+}
+
+/// `zwp_fullscreen_shell_v1.present_surface` with the default method, on no output in particular.
+fn present(harness: &mut Harness, surface: u32) -> Result<(), ServerError> {
+	harness.feed(&request(5, 1, &[surface, 0, 0]), &[])
+}
+
+#[test]
+fn only_advertised_in_kiosk_mode() {
+	common::isolated(|| {
+		let harness = common::registry();
+		assert!(!harness.advertised("zwp_fullscreen_shell_v1"));
+
+		windows::enable_kiosk();
+		let harness = common::registry();
+		assert!(harness.advertised("zwp_fullscreen_shell_v1"));
+	});
+}
+
+#[test]
+fn presenting_replaces_the_previous_surface() {
+	common::isolated(|| {
+		let mut harness = setup();
+		present(&mut harness, 9).unwrap();
+		commit(&mut harness, 9, 8);
+		assert!(idle::is_inhibited());
+
+		present(&mut harness, 10).unwrap();
+		assert!(!idle::is_inhibited(), "replaced surface still mapped");
+		commit(&mut harness, 10, 8);
+		assert!(idle::is_inhibited());
+
+		// presenting the first surface again brings it back, once it commits
+		present(&mut harness, 9).unwrap();
+		commit(&mut harness, 10, 8);
+		assert!(!idle::is_inhibited(), "surface that is no longer presented mapped");
+		commit(&mut harness, 9, 8);
+		assert!(idle::is_inhibited());
+
+		present(&mut harness, 0).unwrap();
+		assert!(!idle::is_inhibited(), "presenting no surface left one mapped");
+	});
+}
+
+#[test]
+fn presenting_is_a_role() {
+	common::isolated(|| {
+		let mut harness = setup();
+		harness.bind("xdg_wm_base", 1, 13);
+		harness.feed(&request(13, 2, &[14, 9]), &[]).unwrap();
+		match present(&mut harness, 9) {
+			Err(ServerError::Protocol(err)) => {
+				assert_eq!(err.code, 1, "not zwp_fullscreen_shell_v1.error.role: {err:?}")
+			},
+			other => panic!("expected a protocol error, got {other:?}"),
+		}
+	});
+}
+
+#[test]
+fn mode_switches_fail() {
+	common::isolated(|| {
+		let mut harness = setup();
+		let before = harness.words().len();
+		// present_surface_for_mode on surface 9, with any object standing in for the output as there are none
+		harness.feed(&request(5, 2, &[9, 4, 60_000, 13]), &[]).unwrap();
+		// zwp_fullscreen_shell_mode_feedback_v1.mode_failed, then wl_display.delete_id
+		assert_eq!(harness.words()[before..], [13, 8 << 16 | 1, 1, 12 << 16 | 1, 13]);
+	});
+}
+
+#[test]
+fn toplevels_are_fullscreen() {
+	common::isolated(|| {
+		let mut harness = setup();
+		harness.bind("xdg_wm_base", 1, 13);
+		harness.feed(&request(13, 2, &[14, 9]), &[]).unwrap();
+		harness.feed(&request(14, 1, &[15]), &[]).unwrap();
+		let before = harness.words().len();
+		harness.feed(&request(9, 6, &[]), &[]).unwrap();
+		// xdg_toplevel.configure(0, 0, [fullscreen])
+		assert_eq!(harness.words()[before..before + 6], [15, 24 << 16, 0, 0, 4, 2]);
+	});
+}
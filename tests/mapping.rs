@@ -0,0 +1,104 @@
+//! Mapping and unmapping surfaces. Whether a surface is mapped is seen through an idle inhibitor on it, which only
+//! keeps the session awake while the surface is mapped.
+
+mod common;
+
+use myway::{
+	client::testing::{request, Harness},
+	idle,
+	windows::{self, Mapping},
+};
+
+/// Bind `wl_shm` as object 3, `wl_compositor` as 4, `xdg_wm_base` as 5 and the idle inhibit manager as 6. Then create
+/// a pool 7 with a 16x16 buffer 8, and a surface 9 with an idle inhibitor 10 and an `xdg_surface` 11.
+fn setup() -> Harness {
+	let mut harness = common::registry();
+	harness.bind("wl_shm", 1, 3);
+	harness.bind("wl_compositor", 6, 4);
+	harness.bind("xdg_wm_base", 5, 5);
+	harness.bind("zwp_idle_inhibit_manager_v1", 1, 6);
+	harness.pool(3, 7, 1024);
+	harness.feed(&request(7, 0, &[8, 0, 16, 16, 64, 0]), &[]).unwrap();
+	harness.feed(&request(4, 0, &[9]), &[]).unwrap();
+	harness.feed(&request(6, 1, &[10, 9]), &[]).unwrap();
+	harness.feed(&request(5, 2, &[11, 9]), &[]).unwrap();
+	harness.events();
+	harness
+}
+
+/// Attach `buffer` (0 for none) to surface 9 and commit it.
+fn commit(harness: &mut Harness, buffer: u32) {
+	harness.feed(&request(9, 1, &[buffer, 0, 0]), &[]).unwrap();
+	harness.feed(&request(9, 6, &[]), &[]).unwrap();
+}
+
+/// Make surface 9 a toplevel 12, and go through the initial commit and configure that come before attaching a buffer.
+fn make_toplevel(harness: &mut Harness) {
+	harness.feed(&request(11, 1, &[12]), &[]).unwrap();
+	commit(harness, 0);
+	let events = harness.events();
+	let configure = events.iter().find(|&&(object, opcode, _)| (object, opcode) == (11, 0));
+	harness.feed(&request(11, 4, &[configure.expect("no xdg_surface.configure").2[0]]), &[]).unwrap();
+}
+
+#[test]
+fn buffers_map_and_unmap() {
+	common::isolated(|| {
+		let mut harness = setup();
+		make_toplevel(&mut harness);
+		assert!(!idle::is_inhibited(), "mapped without a buffer");
+
+		commit(&mut harness, 8);
+		assert!(idle::is_inhibited());
+
+		commit(&mut harness, 0);
+		assert!(!idle::is_inhibited());
+	});
+}
+
+#[test]
+fn destroying_the_role_unmaps() {
+	common::isolated(|| {
+		let mut harness = setup();
+		make_toplevel(&mut harness);
+		commit(&mut harness, 8);
+		assert!(idle::is_inhibited());
+
+		harness.feed(&request(12, 0, &[]), &[]).unwrap();
+		assert!(!idle::is_inhibited(), "surface still mapped after destroying its toplevel");
+	});
+}
+
+#[test]
+fn focus_goes_to_the_window_below() {
+	common::isolated(|| {
+		let (first, second, third) = (Mapping::default(), Mapping::default(), Mapping::default());
+		first.map();
+		second.map();
+		assert!(second.has_focus());
+		assert!(!second.map(), "mapped twice");
+		third.map();
+		assert!(third.has_focus());
+
+		second.unmap();
+		assert!(third.has_focus());
+		third.unmap();
+		assert!(first.has_focus());
+		assert!(!third.unmap(), "unmapped twice");
+		assert!(!third.has_focus());
+	});
+}
+
+#[test]
+fn dropped_windows_are_forgotten() {
+	common::isolated(|| {
+		let (first, second) = (Mapping::default(), Mapping::default());
+		first.map();
+		second.map();
+		// as if a panic had kept the surface from unmapping before it was dropped
+		drop(second);
+		assert!(!first.has_focus());
+		windows::forget_unmapped();
+		assert!(first.has_focus());
+	});
+}
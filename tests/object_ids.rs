@@ -0,0 +1,96 @@
+//! The lifecycle of object IDs: destroyed objects are announced with `wl_display.delete_id`, and their IDs can't be
+//! reused before that.
+
+mod common;
+
+use myway::{
+	client::testing::{request, Event, Harness},
+	error::{ProtocolError, ServerError},
+};
+
+/// IDs freed by the `wl_display.delete_id` events among `events`.
+fn deleted(events: &[Event]) -> Vec<u32> {
+	events.iter().filter(|&&(object, opcode, _)| (object, opcode) == (1, 1)).map(|(_, _, args)| args[0]).collect()
+}
+
+/// Bind `wl_compositor` as object 3.
+fn setup() -> Harness {
+	let mut harness = common::registry();
+	harness.bind("wl_compositor", 6, 3);
+	harness.events();
+	harness
+}
+
+fn protocol_error(result: Result<(), ServerError>) -> ProtocolError {
+	match result {
+		Err(ServerError::Protocol(err)) => err,
+		other => panic!("expected a protocol error, got {other:?}"),
+	}
+}
+
+#[test]
+fn destroyed_ids_are_released() {
+	let mut harness = setup();
+	harness.feed(&request(3, 0, &[4]), &[]).unwrap();
+	harness.feed(&request(4, 0, &[]), &[]).unwrap();
+	assert_eq!(deleted(&harness.events()), [4]);
+
+	// once the client has been told, the ID is free again
+	harness.feed(&request(3, 0, &[4]), &[]).unwrap();
+	assert_eq!(deleted(&harness.events()), []);
+	let objects = harness.client().objects();
+	assert!(objects.iter().any(|(id, object)| u32::from(id) == 4 && object.interface() == "wl_surface"));
+}
+
+#[test]
+fn destructor_events_release_ids() {
+	let mut harness = setup();
+	// wl_display.sync, whose callback is destroyed by its done event
+	harness.feed(&request(1, 0, &[4]), &[]).unwrap();
+	let events = harness.events();
+	let ids: Vec<_> = events.iter().map(|&(object, opcode, _)| (object, opcode)).collect();
+	assert_eq!(ids, [(4, 0), (1, 1)]);
+	assert_eq!(deleted(&events), [4]);
+}
+
+#[test]
+fn reuse_before_delete_id_is_rejected() {
+	let mut harness = setup();
+	// both in one go, so the delete_id isn't sent in between
+	let mut bytes = request(3, 0, &[4]);
+	bytes.extend(request(4, 0, &[]));
+	bytes.extend(request(3, 0, &[4]));
+	let err = protocol_error(harness.feed(&bytes, &[]));
+	assert_eq!(err.code, 0, "expected invalid_object");
+	assert!(err.message.contains("before wl_display.delete_id"), "{}", err.message);
+}
+
+#[test]
+fn live_id_reuse_is_rejected() {
+	let mut harness = setup();
+	harness.feed(&request(3, 0, &[4]), &[]).unwrap();
+	let err = protocol_error(harness.feed(&request(3, 0, &[4]), &[]));
+	assert_eq!(err.code, 0, "expected invalid_object");
+	assert_eq!(err.message, "id 4 is already in use by wl_surface");
+}
+
+#[test]
+fn compositor_destroys_frame_callbacks() {
+	let mut harness = setup();
+	harness.feed(&request(3, 0, &[4]), &[]).unwrap();
+	harness.feed(&request(4, 3, &[5]), &[]).unwrap();
+	harness.feed(&request(4, 6, &[]), &[]).unwrap();
+	let events = harness.events();
+	// after the surface's preferred scale and transform
+	let ids: Vec<_> =
+		events.iter().map(|&(object, opcode, _)| (object, opcode)).filter(|&(object, _)| object != 4).collect();
+	assert_eq!(ids, [(5, 0), (1, 1)]);
+	assert_eq!(deleted(&events), [5]);
+	let objects = harness.client().objects();
+	assert!(objects.iter().all(|(id, _)| u32::from(id) != 5), "callback outlived its done event");
+
+	// the callback's ID is free for the next frame
+	harness.feed(&request(4, 3, &[5]), &[]).unwrap();
+	harness.feed(&request(4, 6, &[]), &[]).unwrap();
+	assert_eq!(deleted(&harness.events()), [5]);
+}
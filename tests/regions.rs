@@ -0,0 +1,130 @@
+//! Surface regions and damage, as clients set them through `wl_region` and `wl_surface` requests, checked on the
+//! surface object itself.
+
+mod common;
+
+use myway::{
+	client::testing::{request, Harness},
+	geometry::{Point, Rect, Region, Size},
+	object_impls::window::Surface,
+	object_map::Object,
+};
+
+/// Send a request whose arguments are a rectangle, such as `wl_region.add` or `wl_surface.damage`.
+fn rect_request(harness: &mut Harness, object: u32, opcode: u16, [x, y, width, height]: [i32; 4]) {
+	harness.feed(&request(object, opcode, &[x as u32, y as u32, width as u32, height as u32]), &[]).unwrap();
+}
+
+fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+	Rect::new(Point::new(x, y), Size::new(width, height))
+}
+
+/// Bind `wl_shm` as object 3 and `wl_compositor` as 4, create a pool 5 with an 8x4 buffer 6, and a surface 7.
+fn setup() -> Harness {
+	let mut harness = common::registry();
+	harness.bind("wl_shm", 1, 3);
+	harness.bind("wl_compositor", 6, 4);
+	harness.pool(3, 5, 128);
+	harness.feed(&request(5, 0, &[6, 0, 8, 4, 32, 0]), &[]).unwrap();
+	harness.feed(&request(4, 0, &[7]), &[]).unwrap();
+	harness
+}
+
+/// Attach buffer 6 to surface 7 and commit it.
+fn commit(harness: &mut Harness) {
+	harness.feed(&request(7, 1, &[6, 0, 0]), &[]).unwrap();
+	harness.feed(&request(7, 6, &[]), &[]).unwrap();
+}
+
+/// Surface 7, to look at the state the requests left it in.
+fn find_surface(harness: &mut Harness) -> &Surface {
+	let (_, object) = harness.client().objects().iter().find(|&(id, _)| u32::from(id) == 7).unwrap();
+	Surface::downcast_ref(object).unwrap()
+}
+
+#[test]
+fn input_region() {
+	let mut harness = setup();
+	harness.feed(&request(4, 1, &[8]), &[]).unwrap();
+	rect_request(&mut harness, 8, 1, [0, 0, 4, 4]);
+	rect_request(&mut harness, 8, 2, [1, 1, 1, 1]);
+	harness.feed(&request(7, 5, &[8]), &[]).unwrap();
+	// the surface took a copy of the region
+	rect_request(&mut harness, 8, 1, [0, 0, 8, 4]);
+	commit(&mut harness);
+	let surface = find_surface(&mut harness);
+	assert!(surface.accepts_input(Point::new(0.5, 0.5)));
+	assert!(!surface.accepts_input(Point::new(1.5, 1.5)), "input in a hole of the region");
+	assert!(!surface.accepts_input(Point::new(5.0, 1.0)), "input outside the region");
+
+	// the region stays until it's set again
+	commit(&mut harness);
+	assert!(!find_surface(&mut harness).accepts_input(Point::new(5.0, 1.0)), "region was reset");
+	harness.feed(&request(7, 5, &[0]), &[]).unwrap();
+	commit(&mut harness);
+	let surface = find_surface(&mut harness);
+	assert!(surface.accepts_input(Point::new(5.0, 1.0)), "unset region doesn't cover everything");
+	assert!(!surface.accepts_input(Point::new(8.5, 1.0)), "input outside the content");
+}
+
+#[test]
+fn damage_in_surface_and_buffer_coordinates() {
+	let mut harness = setup();
+	// at scale 2 and turned a quarter, the 8x4 buffer makes a 2x4 surface
+	harness.feed(&request(7, 8, &[2]), &[]).unwrap();
+	harness.feed(&request(7, 7, &[1]), &[]).unwrap();
+	// the top left buffer pixel is at the top right of the surface
+	rect_request(&mut harness, 7, 9, [0, 0, 1, 1]);
+	rect_request(&mut harness, 7, 2, [0, 3, 1, 1]);
+	rect_request(&mut harness, 7, 2, [100, 100, 5, 5]);
+	commit(&mut harness);
+	let mut expected = Region::from(rect(1, 0, 1, 1));
+	expected.add(rect(0, 3, 1, 1));
+	assert_eq!(find_surface(&mut harness).damage(), &expected);
+
+	// damage is for one commit only
+	harness.feed(&request(7, 8, &[2]), &[]).unwrap();
+	harness.feed(&request(7, 7, &[1]), &[]).unwrap();
+	commit(&mut harness);
+	assert!(find_surface(&mut harness).damage().is_empty());
+}
+
+#[test]
+fn scattered_damage_is_merged() {
+	let mut harness = setup();
+	// a checkerboard twice the surface's size, cut down to the surface once merged
+	for y in 0..8 {
+		for x in (0..16).step_by(2) {
+			rect_request(&mut harness, 7, 2, [x + y % 2, y, 1, 1]);
+		}
+	}
+	commit(&mut harness);
+	let damage = find_surface(&mut harness).damage();
+	assert!(damage.rects().len() <= 32, "damage kept in {} pieces", damage.rects().len());
+	assert_eq!(damage, &Region::from(rect(0, 0, 8, 4)));
+}
+
+#[test]
+fn opaque_region_can_be_set_and_unset() {
+	let mut harness = setup();
+	harness.feed(&request(4, 1, &[8]), &[]).unwrap();
+	rect_request(&mut harness, 8, 1, [0, 0, 8, 4]);
+	rect_request(&mut harness, 8, 2, [2, 1, 2, 2]);
+	harness.feed(&request(7, 4, &[8]), &[]).unwrap();
+	// the surface took a copy of the region, which only applies once committed
+	rect_request(&mut harness, 8, 1, [2, 1, 2, 2]);
+	assert!(find_surface(&mut harness).opaque_region().is_empty(), "applied before the commit");
+	commit(&mut harness);
+	let mut expected = Region::from(rect(0, 0, 8, 4));
+	expected.subtract(rect(2, 1, 2, 2));
+	assert_eq!(find_surface(&mut harness).opaque_region(), &expected);
+
+	// the region stays until it's set again, even once destroyed
+	harness.feed(&request(8, 0, &[]), &[]).unwrap();
+	commit(&mut harness);
+	assert_eq!(find_surface(&mut harness).opaque_region(), &expected, "region was reset");
+	harness.feed(&request(7, 4, &[0]), &[]).unwrap();
+	assert_eq!(find_surface(&mut harness).opaque_region(), &expected, "unset before the commit");
+	commit(&mut harness);
+	assert!(find_surface(&mut harness).opaque_region().is_empty(), "still opaque after unsetting");
+}
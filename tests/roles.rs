@@ -0,0 +1,55 @@
+//! Surface roles: a surface keeps the first role it's given, even after the role object is destroyed.
+
+mod common;
+
+use myway::{
+	client::testing::{request, Harness},
+	error::ServerError,
+	protocol::Id,
+	windows::{RoleKind, SurfaceRole},
+};
+
+/// Bind `wl_compositor` as object 3 and `xdg_wm_base` as 4, and create a positioner 5, a surface 6 and its
+/// `xdg_surface` 7.
+fn setup() -> Harness {
+	let mut harness = common::registry();
+	harness.bind("wl_compositor", 6, 3);
+	harness.bind("xdg_wm_base", 5, 4);
+	harness.feed(&request(4, 1, &[5]), &[]).unwrap();
+	harness.feed(&request(3, 0, &[6]), &[]).unwrap();
+	harness.feed(&request(4, 2, &[7, 6]), &[]).unwrap();
+	harness
+}
+
+#[test]
+fn role_is_kept_after_its_object_is_destroyed() {
+	let mut harness = setup();
+	// a popup, destroyed again
+	harness.feed(&request(7, 2, &[8, 0, 5]), &[]).unwrap();
+	harness.feed(&request(8, 0, &[]), &[]).unwrap();
+	// the surface can be a popup again
+	harness.feed(&request(7, 2, &[9, 0, 5]), &[]).unwrap();
+	harness.feed(&request(9, 0, &[]), &[]).unwrap();
+
+	// but never a toplevel
+	match harness.feed(&request(7, 1, &[10]), &[]) {
+		Err(ServerError::Protocol(err)) => {
+			assert_eq!(err.object.map(u32::from), Some(4), "role errors are posted on xdg_wm_base");
+			assert_eq!(err.code, 0, "expected xdg_wm_base.error.role");
+			assert_eq!(err.message, "surface already has the xdg_popup role, not xdg_toplevel");
+		},
+		other => panic!("expected a protocol error, got {other:?}"),
+	}
+}
+
+#[test]
+fn same_role_can_be_assigned_again() {
+	let role = SurfaceRole::default();
+	let object = Id::<()>::new(3).unwrap();
+	assert_eq!(role.get(), None);
+	role.assign(RoleKind::XdgToplevel, object, 1u32).unwrap();
+	role.assign(RoleKind::XdgToplevel, object, 1u32).unwrap();
+	let err = role.assign(RoleKind::XdgPopup, object, 1u32).unwrap_err();
+	assert_eq!((err.object.map(u32::from), err.code), (Some(3), 1));
+	assert_eq!(role.get(), Some(RoleKind::XdgToplevel));
+}
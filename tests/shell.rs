@@ -0,0 +1,109 @@
+//! The deprecated `wl_shell`: only advertised once enabled, and its shell surfaces map like toplevels. Whether a
+//! surface is mapped is seen through an idle inhibitor on it.
+
+mod common;
+
+use myway::{
+	client::testing::{request, string, Harness},
+	error::ServerError,
+	idle,
+	object_impls::shell,
+};
+
+/// Enable `wl_shell` and bind `wl_shm` as object 3, `wl_compositor` as 4, `wl_shell` as 5 and the idle inhibit manager
+/// as 6. Then create a pool 7 with a 16x16 buffer 8, and a surface 9 with an idle inhibitor 10.
+fn setup() -> Harness {
+	shell::enable();
+	let mut harness = common::registry();
+	harness.bind("wl_shm", 1, 3);
+	harness.bind("wl_compositor", 6, 4);
+	harness.bind("wl_shell", 1, 5);
+	harness.bind("zwp_idle_inhibit_manager_v1", 1, 6);
+	harness.pool(3, 7, 1024);
+	harness.feed(&request(7, 0, &[8, 0, 16, 16, 64, 0]), &[]).unwrap();
+	harness.feed(&request(4, 0, &[9]), &[]).unwrap();
+	harness.feed(&request(6, 1, &[10, 9]), &[]).unwrap();
+	harness
+}
+
+/// Attach `buffer` (0 for none) to surface 9 and commit it.
+fn commit(harness: &mut Harness, buffer: u32) {
+	harness.feed(&request(9, 1, &[buffer, 0, 0]), &[]).unwrap();
+	harness.feed(&request(9, 6, &[]), &[]).unwrap();
+}
+
+#[test]
+fn not_advertised_by_default() {
+	common::isolated(|| {
+		let harness = common::registry();
+		assert!(!harness.advertised("wl_shell"), "wl_shell advertised");
+
+		// nor can it be bound by guessing its name
+		for name in 0..32 {
+			let mut harness = common::registry();
+			let mut args = vec![name];
+			args.extend(string("wl_shell"));
+			args.extend([1, 3]);
+			assert!(harness.feed(&request(2, 0, &args), &[]).is_err(), "wl_shell bound as global #{name}");
+		}
+	});
+}
+
+#[test]
+fn shell_surfaces_map_like_toplevels() {
+	common::isolated(|| {
+		let mut harness = setup();
+		harness.feed(&request(5, 0, &[11, 9]), &[]).unwrap();
+		commit(&mut harness, 8);
+		assert!(!idle::is_inhibited(), "mapped without a role");
+
+		// wl_shell_surface.set_toplevel
+		harness.feed(&request(11, 3, &[]), &[]).unwrap();
+		commit(&mut harness, 8);
+		assert!(idle::is_inhibited());
+
+		commit(&mut harness, 0);
+		assert!(!idle::is_inhibited());
+	});
+}
+
+#[test]
+fn shell_surfaces_are_a_role() {
+	common::isolated(|| {
+		let mut harness = setup();
+		harness.bind("xdg_wm_base", 1, 11);
+		harness.feed(&request(5, 0, &[12, 9]), &[]).unwrap();
+		match harness.feed(&request(11, 2, &[13, 9]), &[]) {
+			Err(ServerError::Protocol(err)) => assert_eq!(err.code, 0, "not xdg_wm_base.error.role: {err:?}"),
+			other => panic!("expected a protocol error, got {other:?}"),
+		}
+
+		let mut harness = setup();
+		harness.feed(&request(5, 0, &[11, 9]), &[]).unwrap();
+		match harness.feed(&request(5, 0, &[12, 9]), &[]) {
+			Err(ServerError::Protocol(err)) => assert_eq!(err.code, 0, "not wl_shell.error.role: {err:?}"),
+			other => panic!("expected a protocol error, got {other:?}"),
+		}
+	});
+}
+
+#[test]
+fn transient_parents_do_not_cycle() {
+	common::isolated(|| {
+		let mut harness = setup();
+		// shell surface 11 for surface 9, and 13 for a second surface 12
+		harness.feed(&request(5, 0, &[11, 9]), &[]).unwrap();
+		harness.feed(&request(4, 0, &[12]), &[]).unwrap();
+		harness.feed(&request(5, 0, &[13, 12]), &[]).unwrap();
+		// wl_shell_surface.set_transient with the inactive flag, making 12 transient for 9
+		harness.feed(&request(13, 4, &[9, 0, 0, 1]), &[]).unwrap();
+		match harness.feed(&request(11, 4, &[12, 0, 0, 1]), &[]) {
+			Err(ServerError::Protocol(err)) => assert_eq!(err.code, 1, "not wl_display.error.invalid_method: {err:?}"),
+			other => panic!("expected a protocol error, got {other:?}"),
+		}
+
+		let mut harness = setup();
+		harness.feed(&request(5, 0, &[11, 9]), &[]).unwrap();
+		assert!(harness.feed(&request(11, 4, &[9, 0, 0, 1]), &[]).is_err(), "surface made transient for itself");
+	});
+}
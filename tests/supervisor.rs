@@ -0,0 +1,63 @@
+//! Supervised clients: restarted with growing backoff when they crash, and ending the session when the primary one
+//! exits cleanly. The clients are shell commands that exit right away, and time is passed in, so the backoff is exact.
+
+use myway::supervisor::{Reaped, Supervisor};
+use std::time::{Duration, Instant};
+
+/// Reap until `done` holds or the primary client exited cleanly, returning whether it did.
+fn reap_until(supervisor: &mut Supervisor, now: Instant, done: impl Fn(&Supervisor) -> bool) -> Reaped {
+	let deadline = Instant::now() + Duration::from_secs(10);
+	loop {
+		let reaped = supervisor.reap(now);
+		if reaped != Reaped::Continue || done(supervisor) {
+			return reaped;
+		}
+		assert!(Instant::now() < deadline, "clients didn't exit");
+		std::thread::sleep(Duration::from_millis(5));
+	}
+}
+
+#[test]
+fn primary_exiting_ends_the_session() {
+	let now = Instant::now();
+	let mut supervisor = Supervisor::new(["true".to_owned()], now);
+	supervisor.restart_due(now);
+	assert_eq!(reap_until(&mut supervisor, now, |_| false), Reaped::PrimaryExited);
+	assert_eq!(supervisor.next_restart(now), None, "restarting a client that exited cleanly");
+}
+
+#[test]
+fn crashes_back_off() {
+	let mut now = Instant::now();
+	let mut supervisor = Supervisor::new(["exit 3".to_owned()], now);
+	for backoff in [1, 2, 4, 8, 16, 32, 60, 60] {
+		supervisor.restart_due(now);
+		assert_eq!(supervisor.next_restart(now), None, "restart still scheduled after restarting");
+		let reaped = reap_until(&mut supervisor, now, |supervisor| supervisor.next_restart(now).is_some());
+		assert_eq!(reaped, Reaped::Continue);
+		assert_eq!(supervisor.next_restart(now), Some(Duration::from_secs(backoff)));
+		// not due a moment early
+		supervisor.restart_due(now + Duration::from_secs(backoff) - Duration::from_millis(1));
+		assert!(supervisor.next_restart(now).is_some());
+		now += Duration::from_secs(backoff);
+	}
+
+	// running for a while before crashing starts the backoff over
+	supervisor.restart_due(now);
+	let later = now + Duration::from_secs(120);
+	reap_until(&mut supervisor, later, |supervisor| supervisor.next_restart(later).is_some());
+	assert_eq!(supervisor.next_restart(later), Some(Duration::from_secs(1)));
+}
+
+#[test]
+fn only_the_primary_ends_the_session() {
+	let now = Instant::now();
+	let mut supervisor = Supervisor::new(["exit 1".to_owned(), "true".to_owned()], now);
+	supervisor.restart_due(now);
+	// once the primary client crashed, and the other exited cleanly without ending the session
+	let reaped = reap_until(&mut supervisor, now, |supervisor| supervisor.next_restart(now).is_some());
+	assert_eq!(reaped, Reaped::Continue);
+	std::thread::sleep(Duration::from_millis(100));
+	assert_eq!(supervisor.reap(now), Reaped::Continue);
+	assert_eq!(supervisor.next_restart(now), Some(Duration::from_secs(1)));
+}
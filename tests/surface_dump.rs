@@ -0,0 +1,160 @@
+//! The hand-rolled PNG encoder behind surface dumps: its checksums against published check values, and its output
+//! taken apart chunk by chunk, as a decoder would.
+
+use myway::{
+	protocol::wl_shm::Format,
+	surface_dump::{adler32, crc32, encode_png, Frame},
+};
+
+/// A chunk of a PNG file: its type and data.
+type Chunk = (String, Vec<u8>);
+
+fn crc(data: &[u8]) -> u32 {
+	!crc32(!0, data)
+}
+
+/// Adler-32 the slow way, reducing after every byte.
+fn adler32_reference(data: &[u8]) -> u32 {
+	let (mut a, mut b) = (1u32, 0u32);
+	for &byte in data {
+		a = (a + byte as u32) % 65521;
+		b = (b + a) % 65521;
+	}
+	(b << 16) | a
+}
+
+/// Split a PNG file into its chunks, checking the signature and every length field and CRC on the way.
+fn chunks(png: &[u8]) -> Vec<Chunk> {
+	assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+	let mut rest = &png[8..];
+	let mut chunks = Vec::new();
+	while !rest.is_empty() {
+		assert!(rest.len() >= 12, "truncated chunk header: {rest:?}");
+		let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+		assert!(rest.len() >= 12 + len, "chunk length {len} runs past the end of the file");
+		let (ty, data) = (&rest[4..8], &rest[8..8 + len]);
+		let stored = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+		assert_eq!(stored, crc(&rest[4..8 + len]), "bad CRC on {ty:?} chunk");
+		chunks.push((String::from_utf8(ty.to_vec()).unwrap(), data.to_vec()));
+		rest = &rest[12 + len..];
+	}
+	chunks
+}
+
+/// Undo a zlib stream made of stored deflate blocks, checking the block headers and the trailing Adler-32.
+fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+	assert_eq!(&zlib[..2], [0x78, 0x01]);
+	assert_eq!(u16::from_be_bytes([zlib[0], zlib[1]]) % 31, 0, "bad zlib header check bits");
+	let mut rest = &zlib[2..];
+	let mut data = Vec::new();
+	loop {
+		assert_eq!(rest[0] & !1, 0, "not a stored block: {:#x}", rest[0]);
+		let last = rest[0] & 1 != 0;
+		let len = u16::from_le_bytes([rest[1], rest[2]]);
+		let nlen = u16::from_le_bytes([rest[3], rest[4]]);
+		assert_eq!(nlen, !len);
+		data.extend_from_slice(&rest[5..5 + len as usize]);
+		rest = &rest[5 + len as usize..];
+		if last {
+			break;
+		}
+	}
+	assert_eq!(rest, adler32(&data).to_be_bytes());
+	data
+}
+
+fn encode(frame: Frame<'_>) -> Vec<Chunk> {
+	let mut png = Vec::new();
+	encode_png(&mut png, 7, 3, frame).unwrap();
+	chunks(&png)
+}
+
+#[test]
+fn crc32_check_values() {
+	assert_eq!(crc(b""), 0);
+	assert_eq!(crc(b"123456789"), 0xcbf43926);
+	assert_eq!(crc(b"The quick brown fox jumps over the lazy dog"), 0x414fa339);
+	// every PNG ends with this
+	assert_eq!(crc(b"IEND"), 0xae426082);
+	// continuing over the rest gives the same as going over it all at once
+	assert_eq!(!crc32(crc32(!0, b"1234"), b"56789"), 0xcbf43926);
+}
+
+#[test]
+fn adler32_check_values() {
+	assert_eq!(adler32(b""), 1);
+	assert_eq!(adler32(b"a"), 0x00620062);
+	assert_eq!(adler32(b"abc"), 0x024d0127);
+	assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+	// long runs of 0xff are the worst case for the deferred reduction
+	for len in [5551, 5552, 5553, 100_000] {
+		let data = vec![0xff; len];
+		assert_eq!(adler32(&data), adler32_reference(&data), "{len} bytes");
+	}
+}
+
+#[test]
+fn argb_becomes_rgba() {
+	// 2x2 pixels with 4 bytes of padding on each row, stored as B, G, R, A
+	#[rustfmt::skip]
+	let pixels = [
+		1, 2, 3, 4, 5, 6, 7, 8, 0xee, 0xee, 0xee, 0xee,
+		9, 10, 11, 12, 13, 14, 15, 16, 0xee, 0xee, 0xee, 0xee,
+	];
+	let frame = Frame { pixels: &pixels, width: 2, height: 2, stride: 12, format: Format::Argb8888 };
+	let chunks = encode(frame);
+	let types: Vec<_> = chunks.iter().map(|(ty, _)| ty.as_str()).collect();
+	assert_eq!(types, ["IHDR", "tEXt", "tEXt", "tEXt", "tEXt", "IDAT", "IEND"]);
+
+	let ihdr = &chunks[0].1;
+	assert_eq!(ihdr[..8], [0, 0, 0, 2, 0, 0, 0, 2]);
+	// 8 bits per sample, RGBA, deflate, adaptive filtering, no interlacing
+	assert_eq!(ihdr[8..], [8, 6, 0, 0, 0]);
+
+	let text: Vec<_> = chunks[1..5].iter().map(|(_, data)| String::from_utf8(data.clone()).unwrap()).collect();
+	assert_eq!(text, ["myway:surface\x007", "myway:size\x002x2", "myway:format\x00Argb8888", "myway:commit\x003"]);
+
+	// each row starts with filter type 0, and has the padding dropped
+	#[rustfmt::skip]
+	let expected = [
+		0, 3, 2, 1, 4, 7, 6, 5, 8,
+		0, 11, 10, 9, 12, 15, 14, 13, 16,
+	];
+	assert_eq!(inflate_stored(&chunks[5].1), expected);
+	assert!(chunks[6].1.is_empty());
+}
+
+#[test]
+fn xrgb_becomes_rgb() {
+	let pixels = [1, 2, 3, 4];
+	let frame = Frame { pixels: &pixels, width: 1, height: 1, stride: 4, format: Format::Xrgb8888 };
+	let chunks = encode(frame);
+	assert_eq!(chunks[0].1[8..], [8, 2, 0, 0, 0]);
+	assert_eq!(inflate_stored(&chunks[5].1), [0, 3, 2, 1]);
+}
+
+#[test]
+fn large_images_span_several_blocks() {
+	// 1 + 3 * 200 bytes per row, over 65535 (the most a stored block holds) in total
+	let (width, height) = (200, 120);
+	let pixels: Vec<u8> = (0..width * height * 4).map(|n| n as u8).collect();
+	let frame = Frame { pixels: &pixels, width, height, stride: width * 4, format: Format::Xrgb8888 };
+	let chunks = encode(frame);
+	let raw = inflate_stored(&chunks[5].1);
+	assert_eq!(raw.len(), (1 + 3 * width as usize) * height as usize);
+	for (row, scanline) in raw.chunks(1 + 3 * width as usize).enumerate() {
+		assert_eq!(scanline[0], 0);
+		let start = row * width as usize * 4;
+		let px = &pixels[start..start + 4];
+		assert_eq!(scanline[1..4], [px[2], px[1], px[0]], "row {row}");
+	}
+}
+
+#[test]
+fn undersized_buffers_are_refused() {
+	let pixels = [0; 15];
+	let frame = Frame { pixels: &pixels, width: 2, height: 2, stride: 8, format: Format::Argb8888 };
+	assert!(encode_png(&mut Vec::new(), 0, 0, frame).is_err());
+	let frame = Frame { pixels: &pixels, width: 2, height: 1, stride: 4, format: Format::Argb8888 };
+	assert!(encode_png(&mut Vec::new(), 0, 0, frame).is_err());
+}
@@ -0,0 +1,198 @@
+//! Reading requests off the socket: long sessions, file descriptors, and messages no client should send. However
+//! hostile the client, the compositor fails with an error rather than panicking.
+
+use myway::{
+	client::testing::{pool_file, request, string, Harness},
+	error::ServerError,
+	protocol::INTERFACES,
+};
+use std::{
+	io::ErrorKind,
+	os::unix::io::{AsRawFd, RawFd},
+};
+
+/// Create the registry as object 2 and bind every global into the objects from 3 up, at version 1.
+fn bind_all(harness: &mut Harness) {
+	harness.feed(&request(1, 1, &[2]), &[]).unwrap();
+	let received = harness.words();
+	let mut rest = &received[..];
+	let mut id = 3;
+	// wl_registry.global(name, interface, version)
+	while let [object, header, ref args @ ..] = *rest {
+		let len = (header >> 16) as usize / 4;
+		if (object, header as u16) == (2, 0) {
+			let mut bind = args[..len - 3].to_vec();
+			bind.extend([1, id]);
+			harness.feed(&request(2, 0, &bind), &[]).unwrap();
+			id += 1;
+		}
+		rest = &rest[len..];
+	}
+}
+
+/// Bind `wl_shm` as object 3.
+fn bind_shm(harness: &mut Harness) {
+	harness.feed(&request(1, 1, &[2]), &[]).unwrap();
+	harness.bind("wl_shm", 1, 3);
+}
+
+fn io_error(result: Result<(), ServerError>) -> std::io::Error {
+	match result {
+		Err(ServerError::Io(err)) => err,
+		other => panic!("expected an I/O error, got {other:?}"),
+	}
+}
+
+#[test]
+fn long_sessions_stay_connected() {
+	let mut harness = Harness::new();
+	// several times the size of the receive buffer, one wl_display.sync at a time
+	for _ in 0..2000 {
+		harness.feed(&request(1, 0, &[2]), &[]).unwrap();
+	}
+	// and in one go, with messages straddling the end of the buffer
+	let syncs: Vec<u8> = (0..2000).flat_map(|i| request(1, 0, &[3 + i])).collect();
+	harness.feed(&syncs, &[]).unwrap();
+}
+
+#[test]
+fn oversized_message() {
+	let mut harness = Harness::new();
+	let header = [1, 0x8000 << 16].into_iter().flat_map(u32::to_ne_bytes).collect::<Vec<u8>>();
+	assert_eq!(io_error(harness.feed(&header, &[])).kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn file_descriptors_across_requests() {
+	let mut harness = Harness::new();
+	bind_shm(&mut harness);
+	let file = pool_file(64);
+	// more pools than file descriptors fit in the buffer at once
+	for id in 4..24 {
+		harness.feed(&request(3, 0, &[id, 64]), &[file.as_raw_fd()]).unwrap();
+	}
+	let pools = harness.client().objects().iter().filter(|(_, object)| object.interface() == "wl_shm_pool").count();
+	assert_eq!(pools, 20);
+}
+
+#[test]
+fn missing_file_descriptor() {
+	let mut harness = Harness::new();
+	bind_shm(&mut harness);
+	match harness.feed(&request(3, 0, &[4, 64]), &[]) {
+		Err(ServerError::InvalidRequest(err)) => {
+			assert_eq!((err.request, err.arg), (Some("create_pool"), Some("fd")));
+			assert_eq!(err.reason, "too few file descriptors");
+		},
+		other => panic!("expected an invalid request, got {other:?}"),
+	}
+}
+
+#[test]
+fn too_many_file_descriptors() {
+	let mut harness = Harness::new();
+	let file = pool_file(64);
+	let fds: Vec<RawFd> = vec![file.as_raw_fd(); 32];
+	assert_eq!(io_error(harness.feed(&request(1, 0, &[2]), &fds)).kind(), ErrorKind::InvalidInput);
+}
+
+/// xorshift64, to generate the same requests on every run.
+struct Rng(u64);
+
+impl Rng {
+	fn next(&mut self) -> u32 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		(self.0 >> 32) as u32
+	}
+
+	fn below(&mut self, n: u32) -> u32 {
+		self.next() % n
+	}
+}
+
+/// A request to one of the client's objects, well-typed according to the protocol most of the time.
+fn random_request(rng: &mut Rng, harness: &mut Harness, fd: RawFd) -> (Vec<u8>, Vec<RawFd>) {
+	let objects: Vec<(u32, &str)> =
+		harness.client().objects().iter().map(|(id, object)| (u32::from(id), object.interface())).collect();
+	let next_id = objects.last().map_or(2, |&(id, _)| id + 1);
+	// the globals are already bound, so binding more mostly fails before anything interesting happens
+	let (object, interface) = loop {
+		let (object, interface) = objects[rng.below(objects.len() as u32) as usize];
+		if object > 2 || rng.below(10) == 0 {
+			break (object, interface);
+		}
+	};
+	let desc = INTERFACES.iter().find(|desc| desc.name == interface).unwrap();
+	// objects with no requests, like wl_callback, only get unknown opcodes
+	let requests = desc.requests.len() as u32;
+	let opcode = match rng.below(50) {
+		0 => requests,
+		_ => rng.below(requests.max(1)),
+	};
+	let mut args = Vec::new();
+	let mut fds = Vec::new();
+	for arg in desc.requests.get(opcode as usize).map_or(&[][..], |request| request.args) {
+		match arg.ty {
+			"new_id" => args.push(next_id),
+			"object" => {
+				let candidates: Vec<u32> = objects
+					.iter()
+					.filter(|&&(_, interface)| arg.interface.map_or(true, |wanted| interface == wanted))
+					.map(|&(id, _)| id)
+					.collect();
+				args.push(match rng.below(16) {
+					0 => 0,
+					1 => rng.below(next_id + 2),
+					_ if candidates.is_empty() => 0,
+					_ => candidates[rng.below(candidates.len() as u32) as usize],
+				});
+			},
+			"string" => {
+				let interface = INTERFACES[rng.below(INTERFACES.len() as u32) as usize].name;
+				args.extend(string([interface, "", "\u{e9}t\u{e9}"][rng.below(3) as usize]));
+			},
+			"array" => {
+				let len = rng.below(4);
+				args.push(len * 4);
+				args.extend((0..len).map(|_| rng.below(16)));
+			},
+			"fd" => fds.push(fd),
+			_ => args.push(match rng.below(4) {
+				0 => rng.next(),
+				1 => rng.below(4096),
+				_ => rng.below(8),
+			}),
+		}
+	}
+	match rng.below(100) {
+		0 => drop(args.pop()),
+		1 => args.push(rng.next()),
+		2 => drop(fds.pop()),
+		3 => fds.push(fd),
+		_ => (),
+	}
+	let mut bytes = request(object, opcode as u16, &args);
+	if rng.below(100) == 0 {
+		let header = rng.next().to_ne_bytes();
+		bytes[4..8].copy_from_slice(&header);
+	}
+	(bytes, fds)
+}
+
+#[test]
+fn random_requests_never_panic() {
+	let file = pool_file(4096);
+	for seed in 1..=1000u64 {
+		let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+		let mut harness = Harness::new();
+		bind_all(&mut harness);
+		for _ in 0..200 {
+			let (bytes, fds) = random_request(&mut rng, &mut harness, file.as_raw_fd());
+			if harness.feed(&bytes, &fds).is_err() {
+				break;
+			}
+		}
+	}
+}
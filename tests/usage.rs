@@ -0,0 +1,53 @@
+//! Accounting for the memory a client holds, and checking none of it outlives the client.
+
+mod common;
+
+use myway::client::{
+	testing::{request, Harness},
+	Usage,
+};
+
+/// Bind `wl_shm` as object 3, `wl_compositor` as 4 and `xdg_wm_base` as 5.
+fn setup() -> Harness {
+	let mut harness = common::registry();
+	harness.bind("wl_shm", 1, 3);
+	harness.bind("wl_compositor", 4, 4);
+	harness.bind("xdg_wm_base", 5, 5);
+	harness
+}
+
+#[test]
+fn pools_are_counted_once() {
+	let mut harness = setup();
+	harness.pool(3, 6, 8192);
+	// two 16x16 ARGB buffers
+	harness.feed(&request(6, 0, &[7, 0, 16, 16, 64, 0]), &[]).unwrap();
+	harness.feed(&request(6, 0, &[8, 1024, 16, 16, 64, 0]), &[]).unwrap();
+	let usage = harness.client().usage();
+	assert_eq!(usage, Usage { objects: 8, shm_bytes: 8192, unsent_bytes: 0, queued_events: 0 });
+
+	// the buffers keep the pool mapped
+	harness.feed(&request(6, 1, &[]), &[]).unwrap();
+	assert_eq!(harness.client().usage().shm_bytes, 8192);
+	harness.feed(&request(7, 0, &[]), &[]).unwrap();
+	harness.feed(&request(8, 0, &[]), &[]).unwrap();
+	assert_eq!(harness.client().usage().shm_bytes, 0);
+}
+
+#[test]
+fn closing_leaves_nothing_behind() {
+	let mut harness = setup();
+	harness.pool(3, 6, 1024);
+	harness.feed(&request(6, 0, &[7, 0, 16, 16, 64, 0]), &[]).unwrap();
+	harness.feed(&request(4, 0, &[8]), &[]).unwrap();
+	harness.feed(&request(5, 2, &[9, 8]), &[]).unwrap();
+	harness.feed(&request(9, 1, &[10]), &[]).unwrap();
+	// the initial commit, and acking the configure it gets before attaching the buffer
+	harness.feed(&request(8, 6, &[]), &[]).unwrap();
+	let events = harness.events();
+	let configure = events.iter().find(|&&(object, opcode, _)| (object, opcode) == (9, 0)).unwrap();
+	harness.feed(&request(9, 4, &[configure.2[0]]), &[]).unwrap();
+	harness.feed(&request(8, 1, &[7, 0, 0]), &[]).unwrap();
+	harness.feed(&request(8, 6, &[]), &[]).unwrap();
+	assert_eq!(harness.into_client().close(), []);
+}
@@ -0,0 +1,221 @@
+//! The server as seen by the `wayland-client` crate, to catch anything a real client library would trip over.
+//!
+//! Each test starts the `myway` binary on its own socket. Built only with the `client-tests` feature, which needs
+//! a newer toolchain than the rest of the crate.
+
+use std::{
+	fs::{DirBuilder, File},
+	io::{BufRead, BufReader, Write},
+	os::unix::{fs::DirBuilderExt, io::AsFd, net::UnixStream},
+	path::PathBuf,
+	process::{Child, Command, Stdio},
+};
+use wayland_client::{
+	delegate_noop,
+	globals::{registry_queue_init, GlobalList, GlobalListContents},
+	protocol::{
+		wl_buffer::WlBuffer,
+		wl_callback::{self, WlCallback},
+		wl_compositor::WlCompositor,
+		wl_registry::WlRegistry,
+		wl_shm::{self, WlShm},
+		wl_shm_pool::WlShmPool,
+		wl_surface::WlSurface,
+	},
+	Connection, Dispatch, EventQueue, QueueHandle, WEnum,
+};
+use wayland_protocols::xdg::shell::client::{
+	xdg_surface::XdgSurface,
+	xdg_toplevel::{self, XdgToplevel},
+	xdg_wm_base::XdgWmBase,
+};
+
+/// A running server, killed when dropped.
+struct Server {
+	child: Child,
+	/// Directory only the user can access, as the server requires, holding the socket.
+	dir: PathBuf,
+	socket: PathBuf,
+}
+
+impl Server {
+	/// Start the server on a socket named after the test, returning once it listens.
+	fn start(name: &str) -> Self {
+		let dir = std::env::temp_dir().join(format!("myway-{}-{name}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		DirBuilder::new().mode(0o700).create(&dir).unwrap();
+		let socket = dir.join("wayland");
+		let mut child = Command::new(env!("CARGO_BIN_EXE_myway"))
+			.arg("--socket-path")
+			.arg(&socket)
+			.stdout(Stdio::piped())
+			.spawn()
+			.unwrap();
+		// the display is announced once the socket is listening
+		let mut line = String::new();
+		BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+		assert!(line.starts_with("WAYLAND_DISPLAY="), "server didn't start: {line:?}");
+		Self { child, dir, socket }
+	}
+
+	fn connect(&self) -> Connection {
+		Connection::from_socket(UnixStream::connect(&self.socket).unwrap()).unwrap()
+	}
+}
+
+impl Drop for Server {
+	fn drop(&mut self) {
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+		let _ = std::fs::remove_dir_all(&self.dir);
+	}
+}
+
+/// Events the tests look at.
+#[derive(Debug, Default)]
+struct State {
+	shm_formats: Vec<wl_shm::Format>,
+	wm_capabilities: Option<Vec<u8>>,
+	frames_done: u32,
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+	fn event(
+		_: &mut Self,
+		_: &WlRegistry,
+		_: <WlRegistry as wayland_client::Proxy>::Event,
+		_: &GlobalListContents,
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+	}
+}
+
+impl Dispatch<WlShm, ()> for State {
+	fn event(state: &mut Self, _: &WlShm, event: wl_shm::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+		if let wl_shm::Event::Format { format: WEnum::Value(format) } = event {
+			state.shm_formats.push(format);
+		}
+	}
+}
+
+impl Dispatch<WlCallback, ()> for State {
+	fn event(
+		state: &mut Self,
+		_: &WlCallback,
+		event: wl_callback::Event,
+		_: &(),
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		if let wl_callback::Event::Done { .. } = event {
+			state.frames_done += 1;
+		}
+	}
+}
+
+impl Dispatch<XdgToplevel, ()> for State {
+	fn event(
+		state: &mut Self,
+		_: &XdgToplevel,
+		event: xdg_toplevel::Event,
+		_: &(),
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		if let xdg_toplevel::Event::WmCapabilities { capabilities } = event {
+			state.wm_capabilities = Some(capabilities);
+		}
+	}
+}
+
+delegate_noop!(State: ignore WlCompositor);
+delegate_noop!(State: ignore WlSurface);
+delegate_noop!(State: ignore WlShmPool);
+delegate_noop!(State: ignore WlBuffer);
+delegate_noop!(State: ignore XdgWmBase);
+delegate_noop!(State: ignore XdgSurface);
+
+fn init(conn: &Connection) -> (GlobalList, EventQueue<State>, State) {
+	let (globals, mut queue) = registry_queue_init::<State>(conn).unwrap();
+	let mut state = State::default();
+	queue.roundtrip(&mut state).unwrap();
+	(globals, queue, state)
+}
+
+#[test]
+fn enumerates_globals() {
+	let server = Server::start("globals");
+	let (globals, _queue, _state) = init(&server.connect());
+	let interfaces: Vec<_> = globals.contents().with_list(|list| list.iter().map(|g| g.interface.clone()).collect());
+	for interface in ["wl_compositor", "wl_shm", "xdg_wm_base"] {
+		assert!(interfaces.iter().any(|i| i == interface), "{interface} missing from {interfaces:?}");
+	}
+}
+
+#[test]
+fn shm_buffer_frame_callback() {
+	let server = Server::start("shm");
+	let (globals, mut queue, mut state) = init(&server.connect());
+	let qh = queue.handle();
+	let compositor: WlCompositor = globals.bind(&qh, 1..=6, ()).unwrap();
+	let shm: WlShm = globals.bind(&qh, 1..=1, ()).unwrap();
+	queue.roundtrip(&mut state).unwrap();
+	assert!(state.shm_formats.contains(&wl_shm::Format::Argb8888));
+	assert!(state.shm_formats.contains(&wl_shm::Format::Xrgb8888));
+
+	let (width, height) = (16, 8);
+	let mut file = tempfile(&format!("myway-{}-shm-pool", std::process::id()));
+	file.write_all(&vec![0x80; width * height * 4]).unwrap();
+	let pool = shm.create_pool(file.as_fd(), (width * height * 4) as i32, &qh, ());
+	let buffer =
+		pool.create_buffer(0, width as i32, height as i32, width as i32 * 4, wl_shm::Format::Argb8888, &qh, ());
+	let surface = compositor.create_surface(&qh, ());
+	surface.attach(Some(&buffer), 0, 0);
+	surface.frame(&qh, ());
+	surface.commit();
+	queue.roundtrip(&mut state).unwrap();
+	assert_eq!(state.frames_done, 1);
+}
+
+#[test]
+fn toplevel_gets_wm_capabilities() {
+	let server = Server::start("toplevel");
+	let (globals, mut queue, mut state) = init(&server.connect());
+	let qh = queue.handle();
+	let compositor: WlCompositor = globals.bind(&qh, 1..=6, ()).unwrap();
+	let wm_base: XdgWmBase = globals.bind(&qh, 5..=5, ()).unwrap();
+	let surface = compositor.create_surface(&qh, ());
+	let xdg_surface = wm_base.get_xdg_surface(&surface, &qh, ());
+	let toplevel = xdg_surface.get_toplevel(&qh, ());
+	toplevel.set_title("conformance".into());
+	toplevel.set_app_id("myway.test".into());
+	toplevel.set_min_size(100, 100);
+	toplevel.set_max_size(200, 200);
+	surface.commit();
+	queue.roundtrip(&mut state).unwrap();
+	assert_eq!(state.wm_capabilities, Some(Vec::new()));
+}
+
+#[test]
+fn protocol_error_reaches_client() {
+	let server = Server::start("error");
+	let conn = server.connect();
+	let (globals, mut queue, mut state) = init(&conn);
+	let qh = queue.handle();
+	let compositor: WlCompositor = globals.bind(&qh, 6..=6, ()).unwrap();
+	let surface = compositor.create_surface(&qh, ());
+	surface.set_buffer_scale(0);
+	assert!(queue.roundtrip(&mut state).is_err());
+	let error = conn.protocol_error().expect("connection died without a protocol error");
+	assert_eq!(error.object_interface, "wl_surface");
+	assert_eq!(error.code, 0, "expected invalid_scale");
+}
+
+/// An unlinked file of the given name to back a pool.
+fn tempfile(name: &str) -> File {
+	let path = std::env::temp_dir().join(name);
+	let file = File::options().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+	std::fs::remove_file(path).unwrap();
+	file
+}